@@ -0,0 +1,122 @@
+use chrono::prelude::*;
+use sha2::{Digest, Sha256};
+
+use crate::util::{ID, generate_id};
+use crate::parse::Content;
+use crate::unknown::Unknown;
+
+// a file attached to a task (an <attachment>). the file itself isn't in the
+// archive, it's stored in data/{data identifier}.zip next to the archives
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Attachment {
+    // metadata
+    pub id: ID,
+    pub task: Option<ID>,
+    pub added: DateTime<Utc>,
+    pub modified: Option<DateTime<Utc>>,
+    // attributes
+    pub name: String,
+    pub preview_image: Option<Vec<u8>>, // a small PNG thumbnail
+    pub data_identifier: Option<String>, // ex: "data-{sha256 of the file}"
+    // everything else in the record, written back out as it was read
+    pub unknown: Unknown,
+}
+
+// self explanatory
+impl Default for Attachment {
+    fn default() -> Self {
+        Attachment {
+            id: generate_id(),
+            task: None,
+            added: Utc::now(),
+            modified: Some(Utc::now()),
+            name: "".into(),
+            preview_image: None,
+            data_identifier: None,
+            unknown: Unknown::default(),
+        }
+    }
+}
+
+// enumeration of the fields of an Attachment as they appear in an
+// <attachment>. see TaskField for why this exists
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum AttachmentField {
+    Task,
+    Added,
+    Modified,
+    Name,
+    PreviewImage,
+    DataIdentifier,
+}
+
+impl Attachment {
+    // copies only the given fields from other onto self
+    pub fn merge(&mut self, other: &Attachment, fields: &[AttachmentField]) {
+        for field in fields {
+            match field {
+                AttachmentField::Task => self.task = other.task.clone(),
+                AttachmentField::Added => self.added = other.added,
+                AttachmentField::Modified => self.modified = other.modified,
+                AttachmentField::Name => self.name = other.name.clone(),
+                AttachmentField::PreviewImage => {
+                    self.preview_image = other.preview_image.clone()
+                }
+                AttachmentField::DataIdentifier => {
+                    self.data_identifier = other.data_identifier.clone()
+                }
+            }
+        }
+    }
+}
+
+// the identifier a file is stored under. files are named after a hash of their
+// contents so the same file attached twice is only stored once
+pub fn data_identifier(data: &[u8]) -> String {
+    let hash = Sha256::digest(data);
+    let hex: String = hash.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("data-{}", hex)
+}
+
+// queries over attachments
+impl Content {
+    // finds an attachment by id
+    pub fn attachment(&self, id: &str) -> Option<&Attachment> {
+        self.attachments.iter().find(|a| a.id == id)
+    }
+
+    // the attachments of a task in the order they were added
+    pub fn attachments_for(&self, task: &str) -> Vec<&Attachment> {
+        let mut attachments: Vec<&Attachment> = self.attachments.iter()
+            .filter(|a| a.task.as_deref() == Some(task))
+            .collect();
+        attachments.sort_by_key(|a| a.added);
+        attachments
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_attachments_for_task() {
+        let first = Attachment {
+            task: Some("task".into()),
+            added: "2018-09-10T20:04:24.956Z".parse().unwrap(),
+            ..Attachment::default()
+        };
+        let second = Attachment {
+            task: Some("task".into()),
+            ..Attachment::default()
+        };
+        let content = Content {
+            attachments: vec![second.clone(), first.clone(), Attachment::default()],
+            ..Content::default()
+        };
+
+        assert!(content.attachments_for("task") == vec![&first, &second]);
+        assert!(data_identifier(b"")
+            == "data-e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+    }
+}
@@ -1,33 +1,284 @@
 use std::env;
 #[macro_use] extern crate openfocus;
-use chrono::Utc;
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use colored::*;
 use openfocus::error::*;
 use openfocus::task::Task;
-use openfocus::db::{Database, Content};
+use openfocus::tag::{Tag, TagStatus};
+use openfocus::project::ProjectStatus;
+use openfocus::folder::SidebarItem;
+use openfocus::note::Note;
+use openfocus::db::{At, Database, Content};
+use openfocus::doctor::Problem;
 use openfocus::filter::Filter;
+use openfocus::perspective::{Perspective, ViewMode};
+use openfocus::rule;
 
-type MainResult = Result<(), Box<std::error::Error>>;
+type MainResult = Result<(), Error>;
 
 // converts the name of a filter to a builtin one
-fn perspective_name_to_filter(name: &str) -> Filter {
+fn perspective_name_to_filter(name: &str) -> Option<Filter> {
     match name {
-        "inbox" => Filter::new_inbox(),
-        "flagged" => Filter::new_flagged(),
-        "forecast" => Filter::new_forecast(),
-        "projects" => Filter::new_projects(),
-        "completed" => Filter::new_complete(),
-        _ => panic!("unknown filter")
+        "inbox" => Some(Filter::new_inbox()),
+        "flagged" => Some(Filter::new_flagged()),
+        "forecast" => Some(Filter::new_forecast()),
+        "completed" => Some(Filter::new_complete()),
+        _ => None,
     }
 }
 
+// converts the value of --at to a point in the database's history. it's either
+// a date, in local time unless it says otherwise, or the id of an archive. a
+// day on its own means the end of it
+fn parse_at(value: &str) -> Result<At, Error> {
+    if let Ok(date) = DateTime::parse_from_rfc3339(value) {
+        return Ok(At::Date(date.with_timezone(&Utc)));
+    }
+
+    let naive = ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M"].iter()
+        .find_map(|f| NaiveDateTime::parse_from_str(value, f).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()
+                .and_then(|d| d.and_hms_opt(23, 59, 59))
+        });
+    if let Some(naive) = naive {
+        return match Local.from_local_datetime(&naive).earliest() {
+            Some(date) => Ok(At::Date(date.with_timezone(&Utc))),
+            None => Err(err!(InvalidArgument)),
+        };
+    }
+
+    // archive ids are 11 characters, ex: oWItkEsdnz0
+    if value.len() == 11 && value.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        return Ok(At::Archive(value.into()));
+    }
+    Err(err!(InvalidArgument))
+}
+
+// the argument at index i. if it's missing, how the mode is used is printed
+// and it's an InvalidArgument error
+fn arg<'a>(args: &'a [String], i: usize, usage: &str) -> Result<&'a str, Error> {
+    match args.get(i) {
+        Some(arg) => Ok(arg),
+        None => {
+            eprintln!("usage: {} {} {} {}", args[0], args[1], args[2], usage);
+            Err(err!(InvalidArgument))
+        }
+    }
+}
+
+// print a task along with the names of its tags
+fn print_task(content: &Content, t: &Task) {
+    let tags: String = content.tags_for(t).iter()
+        .filter_map(|tag| content.tag_path(&tag.id))
+        .map(|path| format!(" @{}", path))
+        .collect();
+    println!("({})\t{}{}", t.id, t, tags);
+}
+
 // the main for filter/output mode
 fn filter_main(args: Vec<String>, db: Database) -> MainResult {
+    let content = db.content();
+
     // filter the relevant tasks
-    let filter = perspective_name_to_filter(&args[2]);
+    if let Some(filter) = perspective_name_to_filter(&args[2]) {
+        for t in filter.into_iter(content.tasks.iter()) {
+            print_task(content, t);
+        }
+        return Ok(());
+    }
+
+    // anything that isn't a builtin is the name of a custom perspective,
+    // which is shown with its grouping and sort order
+    let perspective = match content.perspective(&args[2]) {
+        Some(p) => p,
+        None => return Err(err!(NotFound)),
+    };
+    for group in perspective.apply(content) {
+        if !group.title.is_empty() {
+            println!("{}", group.title.bold());
+        }
+        for t in group.tasks {
+            print_task(content, t);
+        }
+    }
+
+    Ok(())
+}
+
+// the main for listing the custom perspectives
+fn perspectives_main(db: Database) -> MainResult {
+    for p in db.content().perspectives_by_name() {
+        println!("({})\t{}", p.id, p.name.as_deref().unwrap_or("Untitled"));
+    }
+    Ok(())
+}
+
+// the main for creating, editing and deleting custom perspectives
+fn perspective_main(args: Vec<String>, mut db: Database) -> MainResult {
+    let (action, name) = match (args.get(3), args.get(4)) {
+        (Some(action), Some(name)) => (action.as_str(), name),
+        _ => return Err(err!(InvalidArgument)),
+    };
+
+    let mut perspective = match action {
+        "create" => Perspective::new(name),
+        "edit" | "delete" => match db.content().perspective(name) {
+            Some(p) => p.clone(),
+            None => return Err(err!(NotFound)),
+        },
+        _ => return Err(err!(InvalidArgument)),
+    };
+
+    if action == "delete" {
+        return db.delete(&perspective.id);
+    }
+
+    // update attributes
+    let mut iter = args.iter().skip(5);
+    while let Some(arg) = iter.next() {
+        if !arg.starts_with('-') {
+            return Err(err!(InvalidArgument));
+        }
+
+        do_perspective_update(&mut perspective, &arg[1..], &mut iter)?;
+    }
+
+    // update the modified date attribute
+    perspective.modified = Some(Utc::now());
+
+    println!("({})\t{}", perspective.id, perspective.name.as_deref().unwrap_or("Untitled"));
+    db.write(Content::new_perspective(perspective))
+}
+
+// apply a single command line flag to a perspective
+fn do_perspective_update<'a>(
+    perspective: &mut Perspective,
+    arg: &str,
+    iter: &mut impl Iterator<Item = &'a String>
+) -> MainResult {
+    let value = match iter.next() {
+        Some(v) => v.as_str(),
+        None => return Err(err!(InvalidArgument))
+    };
+    let view_state = &mut perspective.view_state;
+
+    match arg {
+        "name" => perspective.name = Some(value.into()),
+        "icon" => perspective.icon = Some(value.into()),
+        "rules" | "r" => perspective.rules = rule::parse_rules(value)?,
+        "aggregate" | "a" => perspective.aggregate = value.parse()?,
+        "mode" => {
+            view_state.view_mode = match value.into() {
+                ViewMode::Other(_) => return Err(err!(InvalidArgument)),
+                mode => mode,
+            }
+        },
+        "group" | "g" => view_state.collation = value.into(),
+        "sort" | "s" => view_state.sort = value.into(),
+        "completion" => view_state.completion_filter = Some(value.into()),
+        "flagged" => view_state.flagged_filter = Some(value.into()),
+        "duration" => view_state.duration_filter = Some(value.into()),
+        _ => return Err(err!(InvalidArgument))
+    }
+
+    Ok(())
+}
+
+// the main for listing projects grouped by folder, like the sidebar
+fn projects_main(db: Database) -> MainResult {
+    // print the folders and projects in a folder, recursing into subfolders
+    fn print_level(content: &Content, folder: Option<&str>, depth: usize) {
+        let indent = "  ".repeat(depth);
+        for item in content.sidebar(folder) {
+            match item {
+                SidebarItem::Folder(f) if !f.dropped() => {
+                    println!("({})\t{}{}/", f.id, indent, f.name);
+                    print_level(content, Some(&f.id), depth + 1);
+                }
+                SidebarItem::Project(p) => {
+                    let status = match p.status() {
+                        ProjectStatus::Active => "",
+                        ProjectStatus::OnHold => " (on hold)",
+                        // finished projects are hidden like in the sidebar
+                        ProjectStatus::Done | ProjectStatus::Dropped => continue,
+                    };
+                    println!("({})\t{}{}{}", p.task.id, indent, p.task, status);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    print_level(db.content(), None, 0);
+    Ok(())
+}
+
+// the main for listing tags as a tree
+fn tags_main(db: Database) -> MainResult {
+    // print a tag followed by all of its children, indented by depth
+    fn print_tag(content: &Content, tag: &Tag, depth: usize) {
+        let status = match tag.status() {
+            TagStatus::Active => "",
+            TagStatus::OnHold => " (on hold)",
+            TagStatus::Dropped => " (dropped)",
+        };
+        println!("({})\t{}{}{}", tag.id, "  ".repeat(depth), tag.name, status);
+        for child in content.tag_children(&tag.id) {
+            print_tag(content, child, depth + 1);
+        }
+    }
 
-    // print the tasks
-    for t in filter.into_iter(db.content().tasks.iter()) {
-        println!("({})\t{}", t.id, t);
+    let content = db.content();
+    for tag in content.root_tags() {
+        print_tag(content, tag, 0);
+    }
+
+    Ok(())
+}
+
+// the main for showing a single task in full
+fn show_main(args: Vec<String>, db: Database) -> MainResult {
+    let id = arg(&args, 3, "<task id>")?;
+    let content = db.content();
+    let task = match content.tasks.iter().find(|t| t.id == id) {
+        Some(t) => t,
+        None => return Err(err!(NotFound)),
+    };
+
+    println!("({})\t{}", task.id, task);
+
+    // print the project the task belongs to
+    if let Some(project) = task.parent.as_ref().and_then(|id| content.project(id)) {
+        println!("project:\t{}", project.task.title);
+    }
+
+    // print the task's tags
+    let tags: Vec<String> = content.tags_for(task).iter()
+        .filter_map(|tag| content.tag_path(&tag.id))
+        .collect();
+    if !tags.is_empty() {
+        println!("tags:\t\t{}", tags.join(", "));
+    }
+
+    // print the names of the attached files
+    let attachments: Vec<&str> = content.attachments_for(&task.id).iter()
+        .map(|a| a.name.as_str())
+        .collect();
+    if !attachments.is_empty() {
+        println!("attachments:\t{}", attachments.join(", "));
+    }
+
+    // print how the task repeats
+    if let Some(rule) = task.repetition_rule.as_ref() {
+        let method = task.repetition_method.map(|m| m.as_str()).unwrap_or("fixed");
+        println!("repeats:\t{} ({})", rule, method);
+    }
+
+    // print the note
+    if let Some(note) = task.note.as_ref() {
+        println!();
+        println!("{}", note.to_ansi());
     }
 
     Ok(())
@@ -35,15 +286,16 @@ fn filter_main(args: Vec<String>, db: Database) -> MainResult {
 
 // the main for create mode
 fn update_main(args: Vec<String>, mut db: Database) -> MainResult {
-    let id = &args[3];
+    let id = arg(&args, 3, "<task id> [-<attribute> <value>]...")?;
 
     // find the task
-    let task: &Task = match db.content().tasks.iter().find(|t| &t.id == id) {
+    let task: &Task = match db.content().tasks.iter().find(|t| t.id == id) {
         Some(t) => t,
         None => return Err(err!(NotFound))
     };
 
     // clone the task
+    let original = task.clone();
     let mut task = task.clone();
 
     // update attributes
@@ -62,8 +314,17 @@ fn update_main(args: Vec<String>, mut db: Database) -> MainResult {
     // print updated task
     println!("{}", &task);
 
+    // completing a repeating task moves it on to its next occurrence and
+    // clones what was completed. only the fields that changed are written
+    let delta = match task.completed {
+        Some(completed) if original.completed.is_none() => {
+            db.content().complete_task(&task, completed)?
+        }
+        _ => Content::edit_task(&original, task),
+    };
+
     // submit changes
-    db.write(Content::new_task(task))?;
+    db.write(delta)?;
     Ok(())
 }
 
@@ -88,7 +349,7 @@ fn do_update<'a>(
         },
         "note" | "n" => {
             task.note = Some(match iter.next() {
-                Some(n) => n.clone(),
+                Some(n) => Note::from_plain(n),
                 None => return Err(err!(InvalidArgument))
             })
         },
@@ -108,7 +369,7 @@ fn do_update<'a>(
             })
         },
         "defer" => {
-            task.due = Some(match iter.next() {
+            task.start = Some(match iter.next() {
                 Some(d) => d.parse()?,
                 None => return Err(err!(InvalidArgument))
             })
@@ -128,32 +389,176 @@ fn do_update<'a>(
 // the main for creation mode
 fn create_main(args: Vec<String>, mut db: Database) -> MainResult {
     // create the task
-    let mut task = Task::default();
-    task.title = args[3].clone();
-    task.inbox = true;
+    let task = Task {
+        title: arg(&args, 3, "<title>")?.into(),
+        inbox: true,
+        ..Task::default()
+    };
     // write it to the database
     let delta = Content::new_task(task);
     db.write(delta)
 }
 
+// the main for deletion mode
+fn delete_main(args: Vec<String>, mut db: Database) -> MainResult {
+    db.delete(arg(&args, 3, "<id>")?)
+}
+
+// the main for folding all of the archives into a new root
+fn compact_main(mut db: Database) -> MainResult {
+    db.compact()
+}
+
+// the main for creating a new, empty database
+fn init_main(args: Vec<String>) -> MainResult {
+    Database::create((&args[2]).into())?;
+    println!("created {}", args[2]);
+    Ok(())
+}
+
+// the main for listing the other devices syncing the database
+fn clients_main(db: Database) -> MainResult {
+    for client in db.clients()? {
+        let last_sync = client.last_sync
+            .map(|d| d.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_else(|| "never".into());
+        println!(
+            "({})\t{}\t{}\tlast synced {}",
+            client.id,
+            client.name.as_deref().unwrap_or("Unknown"),
+            client.model.as_deref().unwrap_or("Unknown"),
+            last_sync,
+        );
+    }
+    Ok(())
+}
+
+// the main for checking a database for problems and, with --fix, writing a
+// delta to fix the ones that can be
+fn doctor_main(args: Vec<String>, mut db: Database) -> MainResult {
+    let fix = match args.get(3).map(|a| a.as_str()) {
+        Some("--fix") => true,
+        Some(_) => return Err(err!(InvalidArgument)),
+        None => false,
+    };
+
+    // badly named archives are in both
+    let problems = db.check()?;
+    let unreadable = db.skipped().iter().filter(|e| match e.path() {
+        Some(path) => !problems.contains(&Problem::BadArchiveName(path.to_path_buf())),
+        None => true,
+    });
+    for e in unreadable {
+        println!("{}\t{}", "skipped".red(), e);
+    }
+    for problem in problems.iter() {
+        let label = if problem.fixable() { "fixable".yellow() } else { "problem".red() };
+        println!("{}\t{}", label, problem);
+    }
+    if problems.is_empty() && db.skipped().is_empty() {
+        println!("no problems found");
+    }
+
+    // the skipped archives could hold what looks to be missing
+    if fix && !db.skipped().is_empty() {
+        println!("not fixing anything until the skipped archives can be read");
+    } else if fix && db.repair(&problems)? {
+        println!("wrote a delta fixing {} problems", problems.iter().filter(|p| p.fixable()).count());
+    }
+    Ok(())
+}
+
+// the main for listing the files attached to a task
+fn attachments_main(args: Vec<String>, db: Database) -> MainResult {
+    let id = arg(&args, 3, "<task id>")?;
+    for attachment in db.content().attachments_for(id) {
+        println!("({})\t{}", attachment.id, attachment.name);
+    }
+    Ok(())
+}
+
+// the main for attaching a file to a task
+fn attach_main(args: Vec<String>, mut db: Database) -> MainResult {
+    let id = arg(&args, 3, "<task id> <file>")?;
+    let path = std::path::Path::new(arg(&args, 4, "<task id> <file>")?);
+    let name = match path.file_name().and_then(|n| n.to_str()) {
+        Some(n) => n,
+        None => return Err(err!(InvalidArgument)),
+    };
+    let data = std::fs::read(path)?;
+    let id = db.add_attachment(id, name, &data)?;
+    println!("({})\t{}", id, name);
+    Ok(())
+}
+
+// the main for saving an attached file. defaults to the attachment's name in
+// the current directory
+fn extract_main(args: Vec<String>, db: Database) -> MainResult {
+    let id = arg(&args, 3, "<attachment id> [destination]")?;
+    let attachment = match db.content().attachment(id) {
+        Some(a) => a,
+        None => return Err(err!(NotFound)),
+    };
+    let dest = args.get(4).unwrap_or(&attachment.name);
+    std::fs::write(dest, db.read_attachment(&attachment.id)?)?;
+    Ok(())
+}
+
 // the actual main that chooses between modes
 fn main() -> MainResult {
-    let args: Vec<String> = env::args().collect();
+    let mut args: Vec<String> = env::args().collect();
+
+    // --at can go anywhere and loads the database as it was at that point
+    let at = match args.iter().position(|a| a == "--at") {
+        Some(i) if i + 1 < args.len() => {
+            let value = args.remove(i + 1);
+            args.remove(i);
+            Some(parse_at(&value)?)
+        }
+        Some(_) => return Err(err!(InvalidArgument)),
+        None => None,
+    };
 
     // print usage if too few arguments are passed
     if args.len() < 3 {
-        println!("usage: {} [filename] [perspective / new / update]", &args[0]);
+        println!(
+            "usage: {} [--at <date / archive id>] [filename] [perspective / new / show / update / delete]",
+            &args[0],
+        );
         std::process::exit(1);
     }
 
-    // open the database
+    // creating a database is the one mode that doesn't open one first
+    if args[1] == "init" {
+        return init_main(args);
+    }
+
+    // open the database. the doctor reads as much of a broken one as it can
     let path = (&args[1]).into();
-    let db = Database::new(path)?;
+    let db = if let Some(at) = at {
+        Database::open_at(path, at)?
+    } else if args[2] == "doctor" {
+        Database::recover(path)?
+    } else {
+        Database::new(path)?
+    };
 
     // mode switch
     match args[2].as_ref() {
         "new" => create_main(args, db),
         "update" => update_main(args, db),
+        "delete" => delete_main(args, db),
+        "tags" => tags_main(db),
+        "show" => show_main(args, db),
+        "projects" => projects_main(db),
+        "perspectives" => perspectives_main(db),
+        "perspective" => perspective_main(args, db),
+        "attachments" => attachments_main(args, db),
+        "attach" => attach_main(args, db),
+        "extract" => extract_main(args, db),
+        "compact" => compact_main(db),
+        "clients" => clients_main(db),
+        "doctor" => doctor_main(args, db),
         _ => filter_main(args, db),
     }
 }
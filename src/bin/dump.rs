@@ -2,7 +2,7 @@ use std::env;
 use std::fs::File;
 use openfocus::parse::parse;
 
-fn main() -> Result<(), Box<std::error::Error>> {
+fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args: Vec<String> = env::args().collect();
 
     if args.len() < 2 {
@@ -0,0 +1,268 @@
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::fs::{read_dir, File};
+use std::path::{Path, PathBuf};
+use chrono::prelude::*;
+
+use crate::error::*;
+use crate::util::generate_id;
+use crate::plist::{PlistItem, read_plist, write_plist_file};
+
+// every device syncing a database registers itself with a
+// {date}={client id}.client plist next to the archives. the date is when the
+// file was last written and "tailIdentifiers" are the archives the device has
+// synced up to, which is how OmniFocus knows what's safe to compact away
+
+// the features of the format openfocus understands, advertised in its own
+// client file. these match the .capability files of a database
+pub const CAPABILITIES: &[&str] = &[
+    "active_object_hidden_dates",
+    "delta_transactions",
+    "external_attachments",
+    "stable_repeats",
+    "unknown_element_import",
+    "versioned_perspectives",
+];
+
+// a device that syncs with the database
+#[derive(Debug, PartialEq, Clone)]
+pub struct Client {
+    pub id: String,
+    pub name: Option<String>,                // ex: "Will's MacBook Pro"
+    pub model: Option<String>,               // ex: "MacBookPro15,1"
+    pub registered: Option<DateTime<Utc>>,
+    pub last_sync: Option<DateTime<Utc>>,
+    pub tails: Vec<String>,                  // the archives it has synced up to
+    pub file_path: PathBuf,
+    // the whole file, so keys openfocus doesn't use are written back out
+    pub plist: BTreeMap<String, PlistItem>,
+}
+
+impl Client {
+    // reads a {date}={client id}.client file
+    pub fn read(path: PathBuf) -> Result<Client, Error> {
+        let path_parser = Regex::new(r"(\d{14})=([^/\\]+)\.client$").unwrap();
+        let id = match path_parser.captures(path.to_str().unwrap_or("")) {
+            Some(caps) => caps.get(2).unwrap().as_str().to_string(),
+            None => return Err(crate::err!(Parse).in_file(&path)),
+        };
+
+        let plist = File::open(&path)
+            .map_err(Error::from)
+            .and_then(read_plist)
+            .map_err(|e| e.in_file(&path))?;
+        let plist = match plist {
+            PlistItem::Dict(d) => d,
+            _ => return Err(crate::err!(Parse).in_file(&path)),
+        };
+        let string = |key: &str| plist.get(key).and_then(|v| v.as_str()).map(|s| s.to_string());
+        let date = |key: &str| plist.get(key).and_then(|v| v.as_date());
+
+        Ok(Client {
+            id: string("clientIdentifier").unwrap_or(id),
+            name: string("name").or_else(|| string("HostName")),
+            model: string("HardwareModel"),
+            registered: date("registrationDate"),
+            last_sync: date("lastSyncDate"),
+            tails: plist.get("tailIdentifiers")
+                .and_then(|t| t.as_array())
+                .unwrap_or(&[])
+                .iter()
+                .filter_map(|t| t.as_str().map(|s| s.to_string()))
+                .collect(),
+            file_path: path,
+            plist,
+        })
+    }
+
+    // the plist written out for this client. anything set on the struct
+    // replaces what was read
+    fn to_plist(&self) -> PlistItem {
+        let mut plist = self.plist.clone();
+        let mut set = |key: &str, value: Option<PlistItem>| {
+            if let Some(value) = value {
+                plist.insert(key.into(), value);
+            }
+        };
+        set("clientIdentifier", Some(PlistItem::String(self.id.clone())));
+        set("name", self.name.clone().map(PlistItem::String));
+        set("HardwareModel", self.model.clone().map(PlistItem::String));
+        set("registrationDate", self.registered.map(PlistItem::Date));
+        set("lastSyncDate", self.last_sync.map(PlistItem::Date));
+        set("tailIdentifiers", Some(PlistItem::Array(
+            self.tails.iter().cloned().map(PlistItem::String).collect()
+        )));
+        PlistItem::Dict(plist)
+    }
+}
+
+// all of the client files in a database. files that can't be read, ex: from
+// a newer version of OmniFocus, are left out so one bad file doesn't stop
+// every write
+pub fn read_clients(db_path: &Path) -> Result<Vec<Client>, Error> {
+    let mut clients = Vec::new();
+    for entry in read_dir(db_path)? {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) == Some("client") {
+            if let Ok(client) = Client::read(path) {
+                clients.push(client);
+            }
+        }
+    }
+    Ok(clients)
+}
+
+// records that this device has synced up to tail by writing a new
+// {now}={client id}.client file and removing the old one
+pub fn update_client(db_path: &Path, client_id: &str, tail: &str) -> Result<Client, Error> {
+    let existing: Vec<Client> = read_clients(db_path)?
+        .into_iter()
+        .filter(|c| c.id == client_id)
+        .collect();
+
+    // plist dates only go down to the second
+    let now = Utc::now().with_nanosecond(0).unwrap();
+    let mut client = match existing.first() {
+        Some(c) => c.clone(),
+        None => {
+            let mut plist = BTreeMap::new();
+            plist.insert("bundleIdentifier".to_string(), PlistItem::String("openfocus".into()));
+            Client {
+                id: client_id.into(),
+                name: Some(host_name()),
+                model: Some(std::env::consts::OS.into()),
+                registered: Some(now),
+                last_sync: None,
+                tails: vec![],
+                file_path: PathBuf::new(),
+                plist,
+            }
+        }
+    };
+    client.last_sync = Some(now);
+    client.tails = vec![tail.into()];
+    client.plist.insert("bundleVersion".into(), PlistItem::String(env!("CARGO_PKG_VERSION").into()));
+    client.plist.insert("capabilities".into(), PlistItem::Array(
+        CAPABILITIES.iter().map(|c| PlistItem::String(c.to_string())).collect()
+    ));
+
+    let file_name = format!("{}={}.client", now.format("%Y%m%d%H%M%S"), client_id);
+    client.file_path = db_path.join(file_name);
+    // written beside the final name and renamed so it's never seen half done
+    let plist = client.to_plist();
+    let tmp_path = client.file_path.with_extension("client.tmp");
+    write_plist_file(File::create(&tmp_path)?, &plist)?;
+    std::fs::rename(&tmp_path, &client.file_path)?;
+    if let PlistItem::Dict(plist) = plist {
+        client.plist = plist;
+    }
+
+    // the new file is in place so the old ones can go
+    for old in existing.iter().filter(|c| c.file_path != client.file_path) {
+        std::fs::remove_file(&old.file_path)?;
+    }
+    Ok(client)
+}
+
+// the name of this machine, for other devices to show
+fn host_name() -> String {
+    std::env::var("HOSTNAME").ok()
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .map(|h| h.trim().to_string())
+        .filter(|h| !h.is_empty())
+        .unwrap_or_else(|| "openfocus".into())
+}
+
+// where openfocus keeps its settings, ex: ~/.config/openfocus. setting
+// $OPENFOCUS_CONFIG_DIR puts them somewhere else, ex: to keep tests away from
+// the real config
+pub fn config_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os("OPENFOCUS_CONFIG_DIR") {
+        return PathBuf::from(dir);
+    }
+    std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))
+        .unwrap_or_else(std::env::temp_dir)
+        .join("openfocus")
+}
+
+// the id this device uses in client files. it's generated the first time and
+// kept in dir, see config_dir(), so every run of openfocus is the same client
+pub fn local_client_id(dir: &Path) -> Result<String, Error> {
+    if let Some(id) = saved_client_id(dir) {
+        return Ok(id);
+    }
+    let path = dir.join("client-id");
+
+    // the id is written out in full under a temporary name first and linked
+    // into place, so two processes starting at once agree on the same id
+    std::fs::create_dir_all(dir)?;
+    let id = generate_id();
+    let tmp_path = dir.join(format!("client-id.{}.tmp", id));
+    std::fs::write(&tmp_path, &id)?;
+    let linked = std::fs::hard_link(&tmp_path, &path);
+    std::fs::remove_file(&tmp_path)?;
+    match linked {
+        Ok(()) => Ok(id),
+        Err(_) => Ok(std::fs::read_to_string(&path)?.trim().to_string()),
+    }
+}
+
+// the id local_client_id() kept in dir, if it's made one yet
+pub fn saved_client_id(dir: &Path) -> Option<String> {
+    std::fs::read_to_string(dir.join("client-id")).ok()
+        .map(|id| id.trim().to_string())
+        .filter(|id| !id.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::TestDir;
+
+    #[test]
+    fn test_client_round_trip() {
+        let tmp = TestDir::default();
+        let dir = tmp.path().to_path_buf();
+        std::fs::write(dir.join("20191010204900=cm3GScgD_ET.client"), r#"<?xml version="1.0" encoding="UTF-8"?>
+            <plist version="1.0"><dict>
+            <key>HardwareModel</key><string>MacBookPro15,1</string>
+            <key>OSVersion</key><string>10.15</string>
+            <key>lastSyncDate</key><date>2019-11-24T03:57:38Z</date>
+            <key>name</key><string>Laptop</string>
+            <key>tailIdentifiers</key><array><string>oWItkEsdnz0</string></array>
+            </dict></plist>"#).unwrap();
+
+        // a client file that can't be read is left out
+        std::fs::write(dir.join("20191010204900=broken.client"), "not a plist").unwrap();
+
+        let first = update_client(&dir, "openfocusabc", "oWItkEsdnz0").unwrap();
+        let second = update_client(&dir, "openfocusabc", "newheadxxxx").unwrap();
+        let clients = read_clients(&dir).unwrap();
+        assert!(clients.len() == 2);
+
+        let mac = clients.iter().find(|c| c.id == "cm3GScgD_ET").unwrap();
+        assert!(mac.name == Some("Laptop".into()));
+        assert!(mac.model == Some("MacBookPro15,1".into()));
+        assert!(mac.last_sync == Some(Utc.with_ymd_and_hms(2019, 11, 24, 3, 57, 38).unwrap()));
+        assert!(mac.tails == vec!["oWItkEsdnz0".to_string()]);
+
+        // updating keeps the registration and replaces the tail
+        let ours = clients.iter().find(|c| c.id == "openfocusabc").unwrap();
+        assert!(ours == &second);
+        assert!(ours.registered == first.registered);
+        assert!(ours.tails == vec!["newheadxxxx".to_string()]);
+        assert!(ours.plist.contains_key("capabilities"));
+    }
+
+    #[test]
+    fn test_local_client_id() {
+        let tmp = TestDir::default();
+        let dir = tmp.path().join("openfocus");
+        assert!(saved_client_id(&dir).is_none());
+        let id = local_client_id(&dir).unwrap();
+        assert!(dir.join("client-id").exists());
+        assert!(local_client_id(&dir).unwrap() == id);
+    }
+}
@@ -1,14 +1,64 @@
 use regex::Regex;
+use std::collections::BTreeMap;
 use std::fs::{read_dir, File};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::io::prelude::*;
-use chrono::Utc;
-use crate::parse::parse;
+use chrono::prelude::*;
+use crate::parse::{parse, parse_lenient};
 use crate::error::*;
-use crate::util::generate_id;
+use crate::util::{ID, generate_id};
 use xml::writer::{EventWriter, XmlEvent};
 use zip::write::ZipWriter;
-pub use crate::parse::Content;
+use crate::task::{Task, TaskField};
+use crate::tag::Tag;
+use crate::transaction::Transaction;
+use crate::doctor::{Problem, check_archive, check_content, repair};
+use crate::note::write_note;
+use crate::unknown::{Unknown, write_element};
+use crate::plist::{PlistItem, write_plist, write_plist_file};
+use crate::client::{Client, CAPABILITIES, read_clients, update_client, local_client_id, saved_client_id, config_dir};
+use crate::attachment::{Attachment, data_identifier};
+pub use crate::parse::{Content, Entity, Tombstone, TaskUpdate};
+
+// the date in the filename of the root archive
+const ROOT_DATE: &str = "00000000000000";
+
+// finds all of the files in the database ending in .zip and creates a
+// corresponding Archive struct. if skipped is given files whose names aren't
+// an archive's are left out and the errors for them added to it
+fn scan_archives(path: &Path, mut skipped: Option<&mut Vec<Error>>) -> Result<Vec<Archive>, Error> {
+    let mut archives = Vec::new();
+    for entry in read_dir(path)? {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) == Some("zip") {
+            match (Archive::new(path), skipped.as_mut()) {
+                (Ok(archive), _) => archives.push(archive),
+                (Err(e), Some(skipped)) => skipped.push(e),
+                (Err(e), None) => return Err(e),
+            }
+        }
+    }
+    Ok(archives)
+}
+
+// two or more archives written on top of the same parent, usually by two
+// devices syncing at about the same time. every branch is applied, interleaved
+// by timestamp, so when more than one branch changed the same entity the
+// change in the newest archive wins
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Conflict {
+    pub parent: String,        // the ID of the archive the branches split from
+    pub branches: Vec<String>, // the ID of the first archive of each branch
+    pub ids: Vec<ID>,          // entities changed in more than one branch
+}
+
+// a point in the history of a database to load it as of. see
+// Database::open_at
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum At {
+    Archive(String),     // up to and including the archive with this ID
+    Date(DateTime<Utc>), // every archive written at or before this time
+}
 
 // represents the whole of a `.ofocus` file (actually a directory)
 pub struct Database {
@@ -16,73 +66,627 @@ pub struct Database {
     head_id: Option<String>, // the ID of the most recent archive file
     archives: Vec<Archive>,  // all of the archive files in the database
     content: Content,        // all of the loaded data
+    conflicts: Vec<Conflict>, // the forks found while loading
+    changed: Vec<(Archive, Vec<ID>)>, // the entities each loaded archive changed
+    client_id: Option<String>, // the id of this device's .client file, once it's needed
+    lenient: bool,           // whether unreadable archives and records are skipped
+    skipped: Vec<Error>,     // what was skipped, if lenient
+    at: Option<At>,          // where loading stops, if not the head
 }
 
 impl Database {
     // creates a database struct and gets a list of all archive files inside
     pub fn new(path: PathBuf) -> Result<Database, Error> {
-        // find all files in the dir ending in .zip and create a corresponding
-        // Archive struct
-        let archives = read_dir(&path)?
-            .filter(|p| {
-                p.is_ok() &&
-                p.as_ref().unwrap().path().to_str().unwrap().ends_with(".zip")
-            })
-            .map(|p| Archive::new(p.unwrap().path()))
-            .collect::<Result<Vec<Archive>, Error>>()?;
+        Database::open(path, false, None)
+    }
+
+    // opens a database that may be damaged, ex: by a sync that stopped part
+    // way through writing an archive. archives that can't be read at all are
+    // treated as empty and records that can't be read are left out, and the
+    // rest is loaded as usual. the errors for everything left out are kept in
+    // skipped()
+    pub fn recover(path: PathBuf) -> Result<Database, Error> {
+        Database::open(path, true, None)
+    }
+
+    // loads the database the way it was at some point in the past by only
+    // applying the archives up to there. only what hasn't been compacted away
+    // can be gone back to, anything earlier is the root. a database opened
+    // at a point in the past can't be written to
+    pub fn open_at(path: PathBuf, at: At) -> Result<Database, Error> {
+        Database::open(path, false, Some(at))
+    }
+
+    fn open(path: PathBuf, lenient: bool, at: Option<At>) -> Result<Database, Error> {
+        let mut skipped = Vec::new();
+        let archives = scan_archives(&path, if lenient { Some(&mut skipped) } else { None })?;
 
         // create a database
         let mut db = Database {
             file_path: path,
             head_id: None,
             archives,
-            content: Content { tasks: Vec::new(), perspectives: Vec::new() }
+            content: Content::default(),
+            conflicts: Vec::new(),
+            changed: Vec::new(),
+            client_id: None,
+            lenient,
+            skipped,
+            at,
         };
 
         // load all the archives
+        if db.root().is_none() {
+            return Err(crate::err!(NotFound));
+        }
         db.load_all()?;
 
         // return the new database
         Ok(db)
     }
 
-    // load all the archives in the database in order. last part of an archive
-    // filename is a pointer to the next filename
+    // creates a new, empty database at path with the root archive and
+    // .capability files OmniFocus expects
+    pub fn create(path: PathBuf) -> Result<Database, Error> {
+        std::fs::create_dir_all(&path)?;
+        if !scan_archives(&path, None)?.is_empty() {
+            return Err(crate::err!(InvalidArgument));
+        }
+        let client_id = local_client_id(&config_dir())?;
+
+        // each capability is a plist saying who turned it on and when
+        let now = Utc::now().with_nanosecond(0).unwrap();
+        for name in CAPABILITIES {
+            let mut plist = BTreeMap::new();
+            plist.insert("dateEnabled".to_string(), PlistItem::Date(now));
+            plist.insert("enablingClientIdentifier".to_string(), PlistItem::String(client_id.clone()));
+            plist.insert("name".to_string(), PlistItem::String(name.to_string()));
+            plist.insert("origin".to_string(), PlistItem::String("creation".into()));
+            let file = File::create(path.join(format!("{}.capability", name)))?;
+            write_plist_file(file, &PlistItem::Dict(plist))?;
+        }
+
+        // the root of a new database has no records in it, the same as one
+        // OmniFocus creates
+        let archive = Archive::create(ROOT_DATE, &generate_id(), &generate_id(), &path, Content::default())?;
+        update_client(&path, &client_id, &archive.id)?;
+        Database::new(path)
+    }
+
+    // load all the archives in the database in order. the middle part of an
+    // archive's filename points at the archive it was written on top of
     fn load_all(&mut self) -> Result<(), Error> {
-        // get the root archive which has a timestamp of "00000000000000"
-        let mut curr: Option<&Archive> = Some(self.archives.iter()
-                        .filter(|a| a.date == "00000000000000")
-                        .next()
-                        .expect("database has no root!"));
-
-        // while there is another archive to process
-        while let Some(archive) = curr {
-            self.head_id = Some(archive.id.clone());
+        self.content = Content::default();
+        self.changed = Vec::new();
+        let chain = self.loaded_chain()?;
+        self.apply(chain)
+    }
+
+    // the part of the chain that's loaded, which is all of it unless the
+    // database was opened at a point in the past
+    fn loaded_chain(&self) -> Result<Vec<Archive>, Error> {
+        let chain = self.chain().into_iter().cloned();
+        match self.at.as_ref() {
+            None => Ok(chain.collect()),
+            // archive dates are in UTC, the same as the dates they're named
+            // with
+            Some(At::Date(date)) => {
+                let date = date.format("%Y%m%d%H%M%S").to_string();
+                Ok(chain.take_while(|a| a.date <= date).collect())
+            }
+            Some(At::Archive(id)) => {
+                let mut chain: Vec<Archive> = chain.collect();
+                match chain.iter().position(|a| &a.id == id) {
+                    Some(i) => chain.truncate(i + 1),
+                    None => return Err(crate::err!(NotFound)),
+                }
+                Ok(chain)
+            }
+        }
+    }
 
-            // read the archive data into the database
-            self.content.update(archive.read()?);
+    // reads archives into the database in the order given
+    fn apply(&mut self, archives: Vec<Archive>) -> Result<(), Error> {
+        for archive in archives {
+            self.head_id = Some(archive.id.clone());
 
-            // find the next archive to read
-            curr = self.archives.iter()
-                    .filter(|a| a.parent_id == archive.id)
-                    .next();
+            // read the archive data into the database. when lenient an
+            // archive that can't be read is still part of the chain, it just
+            // doesn't change anything
+            let delta = if self.lenient {
+                match archive.read_lenient() {
+                    Ok((delta, skipped)) => {
+                        self.skipped.extend(skipped);
+                        delta
+                    }
+                    Err(e) => {
+                        self.skipped.push(e);
+                        Content::default()
+                    }
+                }
+            } else {
+                archive.read()?
+            };
+            self.changed.push((archive, delta.ids()));
+            self.content.update(delta);
         }
 
+        // the ids of the entities each archive changed are kept to find
+        // conflicts as more archives show up
+        self.conflicts = find_conflicts(&self.changed);
+
+        // now that every folder is known put them in tree order
+        self.content.sort_folders();
+
         Ok(())
     }
 
-    // write a new Content struct out to the filesystem
+    // the archives to load in the order they're applied. starting from the
+    // root the next archive is always the oldest one whose parent has already
+    // been applied, so the branches of a fork are interleaved by timestamp
+    fn chain(&self) -> Vec<&Archive> {
+        let children = |id: &str| -> Vec<&Archive> {
+            self.archives.iter()
+                .filter(|a| a.date != ROOT_DATE && a.parent_id == id)
+                .collect()
+        };
+
+        let mut frontier: Vec<&Archive> = self.root().into_iter().collect();
+        let mut chain: Vec<&Archive> = Vec::new();
+        while !frontier.is_empty() {
+            let next = frontier.iter()
+                .enumerate()
+                .min_by(|(_, a), (_, b)| (&a.date, &a.id).cmp(&(&b.date, &b.id)))
+                .map(|(i, _)| i)
+                .unwrap();
+            let archive = frontier.remove(next);
+            for child in children(&archive.id) {
+                if !chain.iter().any(|a| a.id == child.id) {
+                    frontier.push(child);
+                }
+            }
+            chain.push(archive);
+        }
+        chain
+    }
+
+    // the archive everything else is applied on top of, which has a timestamp
+    // of "00000000000000". there can be more than one if a compaction was
+    // interrupted or two devices compacted at once. there's none if the
+    // directory isn't a database
+    fn root(&self) -> Option<&Archive> {
+        let roots: Vec<&Archive> = self.archives.iter()
+            .filter(|a| a.date == ROOT_DATE)
+            .collect();
+
+        // a compacted root has the same id and parent as the delta it was
+        // compacted up to so it replaces that part of any older chain. the
+        // parent is still around if the compaction stopped after removing
+        // the delta
+        let compacted = roots.iter().find(|r| {
+            self.archives.iter().any(|a| {
+                (a.date != ROOT_DATE && a.id == r.id)
+                    || (a.file_path != r.file_path && a.id == r.parent_id)
+            })
+        });
+
+        // otherwise the root whose chain was written to most recently wins
+        let newest = |root: &Archive| -> String {
+            let mut ids = vec![root.id.clone()];
+            let mut newest = root.date.clone();
+            let mut i = 0;
+            while i < ids.len() {
+                let children: Vec<&Archive> = self.archives.iter()
+                    .filter(|a| a.date != ROOT_DATE && a.parent_id == ids[i])
+                    .collect();
+                for a in children {
+                    if !ids.contains(&a.id) {
+                        ids.push(a.id.clone());
+                        newest = newest.max(a.date.clone());
+                    }
+                }
+                i += 1;
+            }
+            newest
+        };
+
+        compacted.or_else(|| roots.iter().max_by_key(|r| newest(r))).copied()
+    }
+
+    // takes an advisory lock on the database directory which is held until the
+    // returned file is dropped. it only keeps other openfocus processes out,
+    // OmniFocus and sync tools don't know about it
+    fn lock(&self) -> Result<File, Error> {
+        let dir = File::open(&self.file_path)?;
+        dir.lock()?;
+        Ok(dir)
+    }
+
+    // picks up archives written by another process since the database was
+    // loaded. only the new archives are read and they're applied on top of
+    // what's already loaded. if archives were removed or a new root showed up
+    // another client has compacted the database, and if a new archive comes
+    // before one that's loaded the order has changed, and everything is read
+    // again. returns whether anything changed
+    pub fn reload(&mut self) -> Result<bool, Error> {
+        let mut bad_names = Vec::new();
+        let archives = scan_archives(
+            &self.file_path,
+            if self.lenient { Some(&mut bad_names) } else { None },
+        )?;
+        let known = |a: &Archive, list: &[Archive]| list.iter().any(|b| b.file_path == a.file_path);
+
+        let removed = self.archives.iter().any(|a| !known(a, &archives));
+        let new: Vec<Archive> = archives.iter()
+            .filter(|a| !known(a, &self.archives))
+            .cloned()
+            .collect();
+        let compacted = removed || new.iter().any(|a| a.date == ROOT_DATE);
+
+        // badly named files are looked at again each time. anything skipped in
+        // the archives that stay loaded is still skipped
+        if compacted {
+            self.skipped = bad_names;
+        } else {
+            let loaded = &self.archives;
+            self.skipped.retain(|e| loaded.iter().any(|a| e.path() == Some(&a.file_path)));
+            self.skipped.extend(bad_names);
+        }
+
+        if !removed && new.is_empty() {
+            return Ok(false);
+        }
+
+        if compacted {
+            self.archives = archives;
+            self.load_all()?;
+            return Ok(true);
+        }
+
+        self.archives.extend(new.iter().cloned());
+        let chain = self.loaded_chain()?;
+
+        // a new archive that comes before one already applied, ex: the older
+        // branch of a fork that synced late, changes what everything after it
+        // was applied on top of so it all has to be read again
+        let first_new = chain.iter().position(|a| known(a, &new));
+        let last_old = chain.iter().rposition(|a| !known(a, &new));
+        if matches!((first_new, last_old), (Some(n), Some(o)) if n < o) {
+            let archives = &self.archives;
+            self.skipped.retain(|e| !archives.iter().any(|a| e.path() == Some(&a.file_path)));
+            self.load_all()?;
+            return Ok(true);
+        }
+
+        let head = chain.last().map(|a| a.id.clone());
+        self.apply(chain.into_iter().filter(|a| known(a, &new)).collect())?;
+        self.head_id = head;
+        Ok(true)
+    }
+
+    // the forks found the last time the database was loaded
+    pub fn conflicts(&self) -> &[Conflict] {
+        &self.conflicts
+    }
+
+    // the archives and records left out of a database opened with recover(),
+    // each error saying which file it was in and where
+    pub fn skipped(&self) -> &[Error] {
+        &self.skipped
+    }
+
+    // write a new Content struct out to the filesystem. partial updates are
+    // only written for tasks, a delta with updates to anything else is an
+    // InvalidArgument error. updates to tasks another process deleted in the
+    // meantime are a NotFound error naming them, and nothing is written
     pub fn write(&mut self, delta: Content) -> Result<(), Error> {
-        // if this was a long running process Database.content would need to be
-        // updated somehow
-        let cur_head = self.head_id.as_ref()
-            .expect("attempted to write to db without loading it first");
-        let archive = Archive::save(cur_head, &self.file_path, delta)?;
-        self.head_id = Some((&archive.id).clone());
+        // writing on top of the past would fork the chain
+        if self.at.is_some() {
+            return Err(crate::err!(InvalidArgument));
+        }
+        let _lock = self.lock()?;
+
+        // if another process wrote in the meantime the delta goes on top of
+        // its head instead of forking the chain. op="update" records only
+        // carry the fields that changed so they apply cleanly to the new
+        // head, apart from updates to tasks that have since been deleted
+        if self.reload()? {
+            let tasks = &self.content.tasks;
+            let deleted: Vec<&str> = delta.task_updates.iter()
+                .map(|u| u.item.id.as_str())
+                .filter(|id| !tasks.iter().any(|t| t.id == *id))
+                .collect();
+            if !deleted.is_empty() {
+                return Err(crate::err!(NotFound).for_entity(&deleted.join(", ")));
+            }
+        }
+
+        let cur_head = match self.head_id.as_ref() {
+            Some(id) => id,
+            None => return Err(crate::err!(NotFound)),
+        };
+        let archive = Archive::save(cur_head, &self.file_path, delta.clone())?;
+        // let other devices know this one is caught up with its own write
+        let client_id = self.client_id()?.to_string();
+        update_client(&self.file_path, &client_id, &archive.id)?;
+
+        // the delta is applied the same way it would be if it was read back
+        self.head_id = Some(archive.id.clone());
+        self.changed.push((archive.clone(), delta.ids()));
         self.archives.push(archive);
+        self.content.update(delta);
+        self.content.sort_folders();
+        Ok(())
+    }
+
+    // writes a tombstone delta for the entity with the given id. deleting a
+    // task or tag also deletes all of its children so nothing is left pointing
+    // at it
+    pub fn delete(&mut self, id: &str) -> Result<(), Error> {
+        self.transaction(|tx| tx.delete(id))
+    }
+
+    // runs f and writes every edit it made to the transaction as a single
+    // archive, then applies them to the loaded content. if f returns an error
+    // nothing is written and the content is left as it was
+    pub fn transaction<T>(
+        &mut self,
+        f: impl FnOnce(&mut Transaction) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let mut tx = Transaction::new(self.content.clone());
+        let result = f(&mut tx)?;
+
+        if let Some(delta) = tx.into_delta() {
+            self.write(delta)?;
+        }
+        Ok(result)
+    }
+
+    // folds the chain of archives into a single new root the way OmniFocus
+    // does. the root takes the name of the current head,
+    // 00000000000000={head's parent}+{head}.zip, so clients that have synced up
+    // to the head still point at an archive that exists and their .client
+    // files stay valid. clients that are behind would never see the
+    // transactions being folded in so compacting waits until they catch up
+    pub fn compact(&mut self) -> Result<(), Error> {
+        if self.at.is_some() {
+            return Err(crate::err!(InvalidArgument));
+        }
+        let _lock = self.lock()?;
+
+        // another process may have written since the database was loaded
+        self.reload()?;
+
+        let head = match self.archives.iter().find(|a| Some(&a.id) == self.head_id.as_ref()) {
+            Some(a) => a,
+            None => return Err(crate::err!(NotFound)),
+        };
+        // a lone root is already compacted
+        if head.date == ROOT_DATE {
+            return Ok(());
+        }
+
+        // whatever was skipped reading the chain would be lost for good
+        if !self.skipped.is_empty() {
+            return Err(crate::err!(InvalidArgument));
+        }
+
+        // this device has everything that was just loaded
+        for client in self.clients()? {
+            if !client.tails.contains(&head.id) {
+                return Err(crate::err!(Unsynced));
+            }
+        }
+
+        // the archives that were loaded, including every branch of a fork
+        let chain: Vec<PathBuf> = self.chain().iter()
+            .map(|a| a.file_path.clone())
+            .collect();
+
+        let root = Archive::create(
+            ROOT_DATE,
+            &head.parent_id,
+            &head.id,
+            &self.file_path,
+            self.content.clone(),
+        )?;
+        let head_id = root.id.clone();
+
+        // retire the old archives, the old root first so that if this stops
+        // part way through there's only one root left to load from
+        for path in chain.iter() {
+            std::fs::remove_file(path)?;
+        }
+        self.archives.retain(|a| !chain.contains(&a.file_path));
+        self.changed = vec![(root.clone(), Vec::new())];
+        self.conflicts = Vec::new();
+        self.archives.push(root);
+        let client_id = self.client_id()?.to_string();
+        update_client(&self.file_path, &client_id, &head_id)?;
         Ok(())
     }
 
+    // the other devices syncing this database, from their .client files
+    pub fn clients(&self) -> Result<Vec<Client>, Error> {
+        // without an id this device hasn't written a client file yet
+        let client_id = self.client_id.clone().or_else(|| saved_client_id(&config_dir()));
+        Ok(read_clients(&self.file_path)?
+            .into_iter()
+            .filter(|c| Some(&c.id) != client_id.as_ref())
+            .collect())
+    }
+
+    // the id this device registers itself under. it's only looked up, and
+    // made the first time, when it's needed so reading a database never
+    // writes anything
+    pub fn client_id(&mut self) -> Result<&str, Error> {
+        if self.client_id.is_none() {
+            self.client_id = Some(local_client_id(&config_dir())?);
+        }
+        Ok(self.client_id.as_deref().unwrap_or_default())
+    }
+
+    // tags a task by writing a new task-to-tag link. a task's first tag is also
+    // its primary <context> which older clients rely on so that's kept in sync
+    pub fn add_tag(&mut self, task_id: &str, tag_id: &str) -> Result<(), Error> {
+        self.transaction(|tx| tx.add_tag(task_id, tag_id))
+    }
+
+    // untags a task by writing a tombstone for the task-to-tag link. if it was
+    // the task's primary tag the next one takes its place
+    pub fn remove_tag(&mut self, task_id: &str, tag_id: &str) -> Result<(), Error> {
+        self.transaction(|tx| tx.remove_tag(task_id, tag_id))
+    }
+
+    // marks a task as complete. a repeating task moves on to its next
+    // occurrence instead, and what was completed is cloned in the same delta
+    pub fn complete(&mut self, id: &str) -> Result<(), Error> {
+        self.transaction(|tx| tx.complete(id))
+    }
+
+    // the path of the zip file in data/ that holds an attachment's contents
+    fn data_path(&self, identifier: &str) -> PathBuf {
+        self.file_path.join("data").join(format!("{}.zip", identifier))
+    }
+
+    // reads the contents of an attached file out of its data/ zip
+    pub fn read_attachment(&self, id: &str) -> Result<Vec<u8>, Error> {
+        let attachment = match self.content.attachment(id) {
+            Some(a) => a,
+            None => return Err(crate::err!(NotFound)),
+        };
+        let identifier = match attachment.data_identifier.as_ref() {
+            Some(i) => i,
+            None => return Err(crate::err!(NotFound)),
+        };
+
+        // the zip holds a single file named after the attachment. anything
+        // else isn't what the attachment says it is
+        let mut zip = zip::ZipArchive::new(File::open(self.data_path(identifier))?)?;
+        let mut file = match zip.by_name(&attachment.name) {
+            Ok(f) => f,
+            Err(zip::result::ZipError::FileNotFound) => return Err(crate::err!(NotFound)),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+        Ok(data)
+    }
+
+    // attaches a file to a task. the contents are stored in data/ under their
+    // hash and the <attachment> pointing at them is written as a delta.
+    // returns the id of the new attachment
+    pub fn add_attachment(
+        &mut self,
+        task_id: &str,
+        name: &str,
+        data: &[u8],
+    ) -> Result<ID, Error> {
+        if !self.content.tasks.iter().any(|t| t.id == task_id) {
+            return Err(crate::err!(NotFound));
+        }
+
+        // identical files share the same data/ zip so only write it once
+        let identifier = data_identifier(data);
+        let path = self.data_path(&identifier);
+        if !path.exists() {
+            std::fs::create_dir_all(path.parent().unwrap())?;
+            write_data(&path, name, data)?;
+        }
+
+        let attachment = Attachment {
+            task: Some(task_id.into()),
+            name: name.into(),
+            data_identifier: Some(identifier),
+            ..Attachment::default()
+        };
+        let id = attachment.id.clone();
+        self.write(Content { attachments: vec![attachment], ..Content::default() })?;
+        Ok(id)
+    }
+
+    // the tags of a task, in order
+    pub fn task_tags(&self, task: &Task) -> Vec<&Tag> {
+        self.content.tags_for(task)
+    }
+
+    // looks for anything wrong with the database: its files, the archives
+    // that aren't loaded and the loaded data. what a database opened with
+    // recover() couldn't read is in skipped() instead
+    pub fn check(&self) -> Result<Vec<Problem>, Error> {
+        let mut problems = Vec::new();
+
+        // badly named archives are never loaded, whether or not they'd read
+        let mut bad_names = Vec::new();
+        scan_archives(&self.file_path, Some(&mut bad_names))?;
+        for e in bad_names {
+            if let Some(path) = e.path() {
+                problems.push(Problem::BadArchiveName(path.to_path_buf()));
+            }
+        }
+
+        // every archive is either part of the chain or one of the roots
+        let chain = self.chain();
+        for archive in self.archives.iter() {
+            if archive.date != ROOT_DATE && !chain.iter().any(|a| a.file_path == archive.file_path) {
+                problems.push(Problem::OrphanedArchive {
+                    path: archive.file_path.clone(),
+                    parent: archive.parent_id.clone(),
+                });
+            }
+        }
+
+        // ids used twice in the same archive are merged away when it's
+        // loaded, so each archive is checked by itself. archives that can't
+        // be read are in skipped()
+        for archive in chain.iter() {
+            if let Ok(content) = archive.read() {
+                problems.extend(check_archive(&content));
+            }
+        }
+        for problem in check_content(&self.content) {
+            if !problems.contains(&problem) {
+                problems.push(problem);
+            }
+        }
+
+        // files in data/ are named after the hash of what's in them
+        let data = self.file_path.join("data");
+        if data.is_dir() {
+            for entry in read_dir(data)? {
+                let path = entry?.path();
+                let name = match path.file_stem().and_then(|n| n.to_str()) {
+                    Some(name) if path.extension().and_then(|e| e.to_str()) == Some("zip") => name,
+                    _ => continue,
+                };
+                let identifier = read_data(&path).ok().map(|d| data_identifier(&d));
+                if identifier.as_deref() != Some(name) {
+                    problems.push(Problem::BadData { path: path.clone(), identifier });
+                }
+            }
+        }
+
+        Ok(problems)
+    }
+
+    // writes a delta fixing the problems that can be fixed, see
+    // doctor::repair. returns whether anything was written
+    pub fn repair(&mut self, problems: &[Problem]) -> Result<bool, Error> {
+        // what looks like a reference to nothing may point into something
+        // that was skipped, so nothing is fixed until it can all be read
+        if !self.skipped.is_empty() {
+            return Err(crate::err!(InvalidArgument));
+        }
+        match repair(&self.content, problems) {
+            Some(delta) => {
+                self.write(delta)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
     // returns a readonly ref to the content (mutations are done by creating
     // Content structs as deltas
     pub fn content(&self) -> &Content {
@@ -90,8 +694,85 @@ impl Database {
     }
 }
 
+// the contents of the first file in a data/ zip
+fn read_data(path: &Path) -> Result<Vec<u8>, Error> {
+    let mut zip = zip::ZipArchive::new(File::open(path)?)?;
+    let mut data = Vec::new();
+    zip.by_index(0)?.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+// writes a data/ zip holding a single file. like an archive it's written next
+// to where it goes and renamed into place, so a zip that's there is whole
+fn write_data(path: &Path, name: &str, data: &[u8]) -> Result<(), Error> {
+    // another process may be writing the same file at the same time
+    let tmp_path = path.with_extension(format!("zip.{}.tmp", generate_id()));
+    let written = File::create(&tmp_path).map_err(Error::from).and_then(|file| {
+        let mut zip = ZipWriter::new(file);
+        zip.start_file(name, zip::write::FileOptions::default())?;
+        zip.write_all(data)?;
+        zip.finish()?;
+        Ok(std::fs::rename(&tmp_path, path)?)
+    });
+    if written.is_err() {
+        let _ = std::fs::remove_file(&tmp_path);
+    }
+    written
+}
+
+// finds the forks in a chain of archives along with the entities changed by
+// more than one of their branches. changed holds each archive in the order
+// it was applied with the ids of the entities it changed
+fn find_conflicts(changed: &[(Archive, Vec<ID>)]) -> Vec<Conflict> {
+    // every id changed by an archive or anything applied on top of it
+    fn changed_below(id: &str, changed: &[(Archive, Vec<ID>)]) -> Vec<ID> {
+        let mut archives = vec![id.to_string()];
+        let mut ids: Vec<ID> = Vec::new();
+        let mut i = 0;
+        while i < archives.len() {
+            for (archive, changed_ids) in changed.iter() {
+                if archive.id == archives[i] {
+                    ids.extend(changed_ids.iter().cloned());
+                } else if archive.parent_id == archives[i] && !archives.contains(&archive.id) {
+                    archives.push(archive.id.clone());
+                }
+            }
+            i += 1;
+        }
+        ids.sort();
+        ids.dedup();
+        ids
+    }
+
+    let mut conflicts = Vec::new();
+    for (parent, _) in changed.iter() {
+        let branches: Vec<String> = changed.iter()
+            .filter(|(a, _)| a.date != ROOT_DATE && a.parent_id == parent.id)
+            .map(|(a, _)| a.id.clone())
+            .collect();
+        if branches.len() < 2 {
+            continue;
+        }
+
+        let per_branch: Vec<Vec<ID>> = branches.iter()
+            .map(|b| changed_below(b, changed))
+            .collect();
+        let mut ids: Vec<ID> = per_branch.iter()
+            .flatten()
+            .filter(|id| per_branch.iter().filter(|b| b.contains(id)).count() > 1)
+            .cloned()
+            .collect();
+        ids.sort();
+        ids.dedup();
+
+        conflicts.push(Conflict { parent: parent.id.clone(), branches, ids });
+    }
+    conflicts
+}
+
 // represents a single zip file in the database which is either the root or a
 // delta on the root
+#[derive(Debug, Clone)]
 struct Archive {
     file_path: PathBuf,
     date: String,
@@ -107,7 +788,7 @@ impl Archive {
             Regex::new(r"(\d{14})=([A-Za-z0-9_-]{11})\+([A-Za-z0-9_-]{11}).zip$")
             .unwrap();
 
-        let path_string: String = path.to_str().unwrap().into();
+        let path_string: String = path.to_string_lossy().into();
         let caps = path_parser.captures(&path_string);
 
         if let Some(caps) = caps {
@@ -118,30 +799,65 @@ impl Archive {
                 id:        caps.get(3).unwrap().as_str().into(),
             })
         } else {
-            Err(crate::err!(Parse))
+            Err(crate::err!(Parse).in_file(&path))
         }
     }
 
     // write out a Content struct as a delta
-    fn save(parent_id: &str, db_path: &PathBuf, delta: Content) -> Result<Archive, Error> {
+    fn save(parent_id: &str, db_path: &Path, delta: Content) -> Result<Archive, Error> {
         let id = generate_id();
-        let gmt = Utc::now().format("%Y%m%d%6f").to_string();
-        let file_name = format!("{}={}+{}.zip", gmt, parent_id, id);
-        let file_path = {
-            let mut tmp = db_path.clone();
-            tmp.push(file_name);
-            tmp
-        };
+        let gmt = Utc::now().format("%Y%m%d%H%M%S").to_string();
+        Archive::create(&gmt, parent_id, &id, db_path, delta)
+    }
 
+    // write out a Content struct as {date}={parent id}+{id}.zip
+    fn create(
+        date: &str,
+        parent_id: &str,
+        id: &str,
+        db_path: &Path,
+        delta: Content,
+    ) -> Result<Archive, Error> {
+        // only tasks can be written as op="update" records. anything else
+        // has to be written as a full record, failing here rather than
+        // leaving it out means what's on disk never differs from what's loaded
+        if !delta.tag_updates.is_empty()
+            || !delta.task_tag_updates.is_empty()
+            || !delta.folder_updates.is_empty()
+            || !delta.attachment_updates.is_empty()
+            || !delta.perspective_updates.is_empty()
+        {
+            return Err(crate::err!(InvalidArgument));
+        }
+
+        let file_name = format!("{}={}+{}.zip", date, parent_id, id);
+        let file_path = db_path.join(file_name);
 
         let archive = Archive {
-            id,
+            id: id.to_string(),
             parent_id: parent_id.to_string(),
             file_path,
-            date: gmt,
+            date: date.to_string(),
         };
 
-        let file = File::create(archive.file_path.as_path())?;
+        // the archive is written next to where it goes and renamed into place
+        // once it's complete so nobody ever reads half of one. if it can't be
+        // finished what was written is removed
+        let tmp_path = archive.file_path.with_extension("zip.tmp");
+        let written = Archive::write_contents(&tmp_path, delta)
+            .and_then(|()| Ok(std::fs::rename(&tmp_path, &archive.file_path)?));
+        if let Err(e) = written {
+            let _ = std::fs::remove_file(&tmp_path);
+            return Err(e);
+        }
+
+        // return the new archive to the database object
+        Ok(archive)
+    }
+
+    // writes a delta out as the contents.xml of a new zip at path
+    fn write_contents(path: &Path, delta: Content) -> Result<(), Error> {
+        let file = File::create(path)?;
         let mut zip = ZipWriter::new(file);
         zip.start_file("contents.xml", zip::write::FileOptions::default())?;
         let mut xml = EventWriter::new(zip);
@@ -164,7 +880,7 @@ impl Archive {
         ) -> Result<(), Error> {
             let tmp: XmlEvent = XmlEvent::start_element(name).into();
             xml.write(tmp)?;
-            let tmp: XmlEvent = XmlEvent::characters(text).into();
+            let tmp = XmlEvent::characters(text);
             xml.write(tmp)?;
             end(xml)?;
             Ok(())
@@ -180,12 +896,32 @@ impl Archive {
             for (k, v) in attrs {
                 tmp = tmp.attr(k, v);
             }
-            let tmp: XmlEvent = tmp.into();
             xml.write(tmp)?;
             Ok(())
         }
 
 
+        // the attributes of a record's root element followed by the ones
+        // that were read but aren't modeled
+        fn with_unknown<'a>(
+            attrs: Vec<(&'a str, &'a str)>,
+            unknown: &'a Unknown,
+        ) -> Vec<(&'a str, &'a str)> {
+            let extra = unknown.attributes.iter().map(|(k, v)| (k.as_str(), v.as_str()));
+            attrs.into_iter().chain(extra).collect()
+        }
+
+        // write the child elements that were read but aren't modeled
+        fn unknown_elements(
+            xml: &mut ConcreteEventWriter,
+            unknown: &Unknown,
+        ) -> Result<(), Error> {
+            for element in unknown.elements.iter() {
+                write_element(xml, element)?;
+            }
+            Ok(())
+        }
+
         // write the form <foo bar="baz" />
         fn attrs(
             xml: &mut ConcreteEventWriter,
@@ -205,7 +941,7 @@ impl Archive {
         })?;
 
         // write newline
-        xml.inner_mut().write(b"\n")?;
+        xml.inner_mut().write_all(b"\n")?;
 
         // write the opening <omnifocus ...> tag
         attrs_open(&mut xml, "omnifocus", vec![
@@ -217,128 +953,1533 @@ impl Archive {
             ("machine-model", "unknown")
         ])?;
 
-        // iterate each task
-        for task in delta.tasks {
-            // write <task id="{id}">
-            attrs_open(&mut xml, "task", vec![("id", &task.id)])?;
+        // write the element for a single field of a task. <rank> and
+        // <next-clone-identifier> can't be empty so they're left out instead
+        fn task_field(
+            xml: &mut ConcreteEventWriter,
+            task: &Task,
+            field: TaskField,
+        ) -> Result<(), Error> {
+            // write the form <foo>{date}</foo> or <foo/>
+            fn date(
+                xml: &mut ConcreteEventWriter,
+                name: &str,
+                date: Option<DateTime<Utc>>,
+            ) -> Result<(), Error> {
+                match date {
+                    Some(date) => text(xml, name, &date.to_rfc3339_opts(
+                        chrono::SecondsFormat::Millis,
+                        true
+                    )),
+                    None => attrs(xml, name, vec![]),
+                }
+            }
+
+            match field {
+                // write <project>...</project> or <project/> for regular tasks
+                TaskField::Project => {
+                    if let Some(project) = task.project.as_ref() {
+                        attrs_open(xml, "project", vec![])?;
+
+                        // write <folder idref="{folder}"/>
+                        if let Some(folder) = project.folder.as_ref() {
+                            attrs(xml, "folder", vec![("idref", folder)])?;
+                        } else {
+                            attrs(xml, "folder", vec![])?;
+                        }
+
+                        // write <singleton>{true/false}</singleton>
+                        text(xml, "singleton", &project.singleton.to_string())?;
 
-            // write <project/>
-            attrs(&mut xml, "project", vec![])?;
+                        // write <last-review> and <next-review>
+                        date(xml, "last-review", project.last_review)?;
+                        date(xml, "next-review", project.next_review)?;
 
-            // write <inbox>{true/false}</inbox>
-            text(&mut xml, "inbox", &task.inbox.to_string())?;
+                        // write <review-interval>{interval}</review-interval>
+                        if let Some(interval) = project.review_interval.as_ref() {
+                            text(xml, "review-interval", interval)?;
+                        } else {
+                            attrs(xml, "review-interval", vec![])?;
+                        }
 
-            // write <task />
-            if let Some(parent_id) = task.parent {
-                attrs(&mut xml, "task", vec![("id", &parent_id)])?;
+                        // write <status>{status}</status>
+                        text(xml, "status", project.status.as_str())?;
+                        unknown_elements(xml, &project.unknown)?;
+
+                        // </project>
+                        end(xml)?;
+                    } else {
+                        attrs(xml, "project", vec![])?;
+                    }
+                }
+
+                // write <inbox>{true/false}</inbox>
+                TaskField::Inbox => text(xml, "inbox", &task.inbox.to_string())?,
+
+                // write <task idref="{parent}"/>
+                TaskField::Parent => {
+                    if let Some(parent_id) = task.parent.as_ref() {
+                        attrs(xml, "task", vec![("idref", parent_id)])?;
+                    } else {
+                        attrs(xml, "task", vec![])?;
+                    }
+                }
+
+                // write <added>{date}</added>
+                TaskField::Added => date(xml, "added", Some(task.added))?,
+
+                // write <name>{title}</name>
+                TaskField::Title => text(xml, "name", &task.title)?,
+
+                // write <note>...</note>
+                TaskField::Note => {
+                    if let Some(note) = task.note.as_ref() {
+                        write_note(xml, note)?;
+                    } else {
+                        attrs(xml, "note", vec![])?;
+                    }
+                }
+
+                // write <rank>{rank}</rank>
+                TaskField::Rank => {
+                    if let Some(rank) = task.rank {
+                        text(xml, "rank", &rank.to_string())?;
+                    } else {
+                        attrs(xml, "rank", vec![])?;
+                    }
+                }
+
+                // write <context idref="{tag}"/>
+                TaskField::Context => {
+                    if let Some(context_id) = task.context.as_ref() {
+                        attrs(xml, "context", vec![("idref", context_id)])?;
+                    } else {
+                        attrs(xml, "context", vec![])?;
+                    }
+                }
+
+                // write the dates
+                TaskField::Start => date(xml, "start", task.start)?,
+                TaskField::Due => date(xml, "due", task.due)?,
+                TaskField::Completed => date(xml, "completed", task.completed)?,
+                TaskField::Modified => date(xml, "modified", task.modified)?,
+
+                // write <estimated-minutes>
+                TaskField::EstimatedDuration => {
+                    if let Some(est) = task.estimated_duration {
+                        text(xml, "estimated-minutes", &est.to_string())?;
+                    } else {
+                        attrs(xml, "estimated-minutes", vec![])?;
+                    }
+                }
+
+                // write <flagged>{true/false}</flagged>
+                TaskField::Flagged => text(xml, "flagged", &task.flagged.to_string())?,
+
+                // write <completed-by-children>{true/false}</completed-by-children>
+                TaskField::CompleteByChildren => text(
+                    xml,
+                    "completed-by-children",
+                    &task.complete_by_children.to_string()
+                )?,
+
+                // write <order>{parallel/sequential}</order>
+                TaskField::Order => {
+                    if let Some(order) = task.order.as_ref() {
+                        text(xml, "order", match order {
+                            crate::task::SubtaskOrder::Parallel => "parallel",
+                            crate::task::SubtaskOrder::Sequential => "sequential",
+                        })?;
+                    } else {
+                        attrs(xml, "order", vec![])?;
+                    }
+                }
+
+                // write <repetition-rule>{rule}</repetition-rule>
+                TaskField::RepetitionRule => {
+                    if let Some(rule) = task.repetition_rule.as_ref() {
+                        text(xml, "repetition-rule", &rule.to_string())?;
+                    } else {
+                        attrs(xml, "repetition-rule", vec![])?;
+                    }
+                }
+
+                // write <repetition-method>{method}</repetition-method>
+                TaskField::RepetitionMethod => {
+                    if let Some(method) = task.repetition_method {
+                        text(xml, "repetition-method", method.as_str())?;
+                    } else {
+                        attrs(xml, "repetition-method", vec![])?;
+                    }
+                }
+
+                // write <next-clone-identifier>{n}</next-clone-identifier>
+                TaskField::NextCloneIdentifier => {
+                    if let Some(n) = task.next_clone_identifier {
+                        text(xml, "next-clone-identifier", &n.to_string())?;
+                    } else {
+                        attrs(xml, "next-clone-identifier", vec![])?;
+                    }
+                }
+            }
+            Ok(())
+        }
+
+        // iterate each folder
+        for folder in delta.folders {
+            // write <folder id="{id}">
+            attrs_open(&mut xml, "folder", with_unknown(
+                vec![("id", &folder.id)],
+                &folder.unknown,
+            ))?;
+
+            // write <folder idref="{parent}"/>
+            if let Some(parent) = folder.parent.as_ref() {
+                attrs(&mut xml, "folder", vec![("idref", parent)])?;
             } else {
-                attrs(&mut xml, "task", vec![])?;
+                attrs(&mut xml, "folder", vec![])?;
             }
 
             // write <added>{date}</added>
-            text(&mut xml, "added", &task.added.to_rfc3339_opts(
+            text(&mut xml, "added", &folder.added.to_rfc3339_opts(
                 chrono::SecondsFormat::Millis,
                 true
             ))?;
 
-            // write <name>{title}</name>
-            text(&mut xml, "name", &task.title)?;
-
-            // write <rank>{rank}</rank>
-            if let Some(rank) = task.rank {
-                text(&mut xml, "rank", &rank.to_string())?;
+            // write <modified>{date}</modified>
+            if let Some(modified) = folder.modified {
+                text(&mut xml, "modified", &modified.to_rfc3339_opts(
+                    chrono::SecondsFormat::Millis,
+                    true
+                ))?;
             }
 
-            // write <context />
-            if let Some(context_id) = task.context {
-                attrs(&mut xml, "context", vec![("id", &context_id)])?;
+            // write <name>{name}</name>
+            text(&mut xml, "name", &folder.name)?;
+
+            // write <note>...</note>
+            if let Some(note) = folder.note.as_ref() {
+                write_note(&mut xml, note)?;
             } else {
-                attrs(&mut xml, "context", vec![])?;
+                attrs(&mut xml, "note", vec![])?;
             }
 
-            // write <start>{date}</start>
-            if let Some(start) = task.start {
-                text(&mut xml, "start", &start.to_rfc3339_opts(
-                    chrono::SecondsFormat::Millis,
-                    true
-                ))?;
-            } else {
-                attrs(&mut xml, "start", vec![])?;
+            // write <rank>{rank}</rank>
+            if let Some(rank) = folder.rank {
+                text(&mut xml, "rank", &rank.to_string())?;
             }
 
-            // write <due>{date}</due>
-            if let Some(due) = task.due {
-                text(&mut xml, "due", &due.to_rfc3339_opts(
+            // write <hidden>{date}</hidden>
+            if let Some(hidden) = folder.hidden {
+                text(&mut xml, "hidden", &hidden.to_rfc3339_opts(
                     chrono::SecondsFormat::Millis,
                     true
                 ))?;
             } else {
-                attrs(&mut xml, "due", vec![])?;
+                attrs(&mut xml, "hidden", vec![])?;
             }
 
-            // write <completed>{date}</completed>
-            if let Some(completed) = task.completed {
-                text(&mut xml, "completed", &completed.to_rfc3339_opts(
-                    chrono::SecondsFormat::Millis,
-                    true
-                ))?;
+            unknown_elements(&mut xml, &folder.unknown)?;
+
+            // </folder>
+            end(&mut xml)?;
+        }
+
+        // iterate each tag
+        for tag in delta.tags {
+            // write <context id="{id}">
+            attrs_open(&mut xml, "context", with_unknown(
+                vec![("id", &tag.id)],
+                &tag.unknown,
+            ))?;
+
+            // write <context idref="{parent}"/>
+            if let Some(parent) = tag.parent.as_ref() {
+                attrs(&mut xml, "context", vec![("idref", parent)])?;
             } else {
-                attrs(&mut xml, "completed", vec![])?;
+                attrs(&mut xml, "context", vec![])?;
             }
 
+            // write <added>{date}</added>
+            text(&mut xml, "added", &tag.added.to_rfc3339_opts(
+                chrono::SecondsFormat::Millis,
+                true
+            ))?;
+
             // write <modified>{date}</modified>
-            if let Some(modified) = task.modified {
+            if let Some(modified) = tag.modified {
                 text(&mut xml, "modified", &modified.to_rfc3339_opts(
                     chrono::SecondsFormat::Millis,
                     true
                 ))?;
-            } else {
-                attrs(&mut xml, "modified", vec![])?;
             }
 
-            // write <estimated-minutes>
-            if let Some(est) = task.estimated_duration {
-                text(&mut xml, "estimated-minutes", &est.to_string())?;
+            // write <name>{name}</name>
+            text(&mut xml, "name", &tag.name)?;
+
+            // write <note>...</note>
+            if let Some(note) = tag.note.as_ref() {
+                write_note(&mut xml, note)?;
             } else {
-                attrs(&mut xml, "estimated-minutes", vec![])?;
+                attrs(&mut xml, "note", vec![])?;
             }
 
-            // write <flagged>{true/false}</flagged>
-            text(&mut xml, "flagged", &task.flagged.to_string())?;
-
-            // write <complete-by-children>{true/false}</complete-by-children>
-            text(
-                &mut xml,
-                "complete-by-children",
-                &task.complete_by_children.to_string()
-            )?;
-
-            // write <order>{parallel/sequential}</order>
-            if let Some(order) = task.order {
-                text(&mut xml, "order", match order {
-                    crate::task::SubtaskOrder::Parallel => "parallel",
-                    crate::task::SubtaskOrder::Sequential => "sequential",
-                })?;
-            } else {
-                attrs(&mut xml, "order", vec![])?;
+            // write <rank>{rank}</rank>
+            if let Some(rank) = tag.rank {
+                text(&mut xml, "rank", &rank.to_string())?;
             }
 
+            // write <hidden>{date}</hidden>
+            if let Some(hidden) = tag.hidden {
+                text(&mut xml, "hidden", &hidden.to_rfc3339_opts(
+                    chrono::SecondsFormat::Millis,
+                    true
+                ))?;
+            } else {
+                attrs(&mut xml, "hidden", vec![])?;
+            }
+
+            // write <prohibits-next-action>{true/false}</prohibits-next-action>
+            text(
+                &mut xml,
+                "prohibits-next-action",
+                &tag.prohibits_next_action.to_string()
+            )?;
+
+            // write <location name="..." latitude="..." .../>
+            if let Some(location) = tag.location.as_ref() {
+                let float = |f: Option<f64>| f.map(|f| f.to_string());
+                let values = vec![
+                    ("name", location.name.clone()),
+                    ("latitude", float(location.latitude)),
+                    ("longitude", float(location.longitude)),
+                    ("radius", float(location.radius)),
+                ];
+                let values: Vec<(&str, String)> = values.into_iter()
+                    .filter_map(|(k, v)| v.map(|v| (k, v)))
+                    .collect();
+                attrs(&mut xml, "location", values.iter()
+                    .map(|(k, v)| (*k, v.as_str()))
+                    .collect())?;
+            } else {
+                attrs(&mut xml, "location", vec![])?;
+            }
+
+            // write <tasks-user-ordered>{true/false}</tasks-user-ordered>
+            text(
+                &mut xml,
+                "tasks-user-ordered",
+                &tag.tasks_user_ordered.to_string()
+            )?;
+
+            unknown_elements(&mut xml, &tag.unknown)?;
+
+            // </context>
+            end(&mut xml)?;
+        }
+
+        // iterate each new task, writing every field
+        for task in delta.tasks {
+            // write <task id="{id}">
+            attrs_open(&mut xml, "task", with_unknown(vec![("id", &task.id)], &task.unknown))?;
+            for field in TaskField::ALL.iter() {
+                task_field(&mut xml, &task, *field)?;
+            }
+            unknown_elements(&mut xml, &task.unknown)?;
             // </task>
             end(&mut xml)?;
         }
 
+        // iterate each edited task, writing only the fields that changed like
+        // OmniFocus does so concurrent edits to other fields aren't clobbered
+        for update in delta.task_updates {
+            // write <task id="{id}" op="update">
+            // the unknown attributes are written back too. they're already
+            // what the task has so it's harmless if they didn't change
+            attrs_open(&mut xml, "task", with_unknown(
+                vec![("id", &update.item.id), ("op", "update")],
+                &update.item.unknown,
+            ))?;
+            for field in TaskField::ALL.iter().filter(|f| update.fields.contains(f)) {
+                task_field(&mut xml, &update.item, *field)?;
+            }
+            // </task>
+            end(&mut xml)?;
+        }
+
+        // iterate each task-to-tag link
+        for link in delta.task_tags {
+            // write <task-to-tag id="{task}.{tag}">
+            attrs_open(&mut xml, "task-to-tag", with_unknown(
+                vec![("id", &link.id)],
+                &link.unknown,
+            ))?;
+
+            // write <added>{date}</added>
+            text(&mut xml, "added", &link.added.to_rfc3339_opts(
+                chrono::SecondsFormat::Millis,
+                true
+            ))?;
+
+            // write <modified>{date}</modified>
+            if let Some(modified) = link.modified {
+                text(&mut xml, "modified", &modified.to_rfc3339_opts(
+                    chrono::SecondsFormat::Millis,
+                    true
+                ))?;
+            }
+
+            // write <task idref="{task}"/> and <context idref="{tag}"/>
+            attrs(&mut xml, "task", vec![("idref", &link.task)])?;
+            attrs(&mut xml, "context", vec![("idref", &link.tag)])?;
+
+            // write <rank-in-task>{rank}</rank-in-task>
+            if let Some(rank) = link.rank_in_task {
+                text(&mut xml, "rank-in-task", &rank)?;
+            } else {
+                attrs(&mut xml, "rank-in-task", vec![])?;
+            }
+
+            // write <rank-in-tag>{rank}</rank-in-tag>
+            if let Some(rank) = link.rank_in_tag {
+                text(&mut xml, "rank-in-tag", &rank)?;
+            } else {
+                attrs(&mut xml, "rank-in-tag", vec![])?;
+            }
+
+            unknown_elements(&mut xml, &link.unknown)?;
+
+            // </task-to-tag>
+            end(&mut xml)?;
+        }
+
+        // iterate each attachment
+        for attachment in delta.attachments {
+            // write <attachment id="{id}">
+            attrs_open(&mut xml, "attachment", with_unknown(
+                vec![("id", &attachment.id)],
+                &attachment.unknown,
+            ))?;
+
+            // write <task idref="{task}"/>
+            if let Some(task) = attachment.task {
+                attrs(&mut xml, "task", vec![("idref", &task)])?;
+            } else {
+                attrs(&mut xml, "task", vec![])?;
+            }
+
+            // write <added>{date}</added>
+            text(&mut xml, "added", &attachment.added.to_rfc3339_opts(
+                chrono::SecondsFormat::Millis,
+                true
+            ))?;
+
+            // write <modified>{date}</modified>
+            if let Some(modified) = attachment.modified {
+                text(&mut xml, "modified", &modified.to_rfc3339_opts(
+                    chrono::SecondsFormat::Millis,
+                    true
+                ))?;
+            }
+
+            // write <name>{file name}</name>
+            text(&mut xml, "name", &attachment.name)?;
+
+            // write <preview-image>{base64 png}</preview-image>
+            if let Some(preview) = attachment.preview_image {
+                text(&mut xml, "preview-image", &base64::encode(&preview))?;
+            }
+
+            // write <data-identifier>{identifier}</data-identifier>
+            if let Some(identifier) = attachment.data_identifier {
+                text(&mut xml, "data-identifier", &identifier)?;
+            }
+
+            unknown_elements(&mut xml, &attachment.unknown)?;
+
+            // </attachment>
+            end(&mut xml)?;
+        }
+
+        // iterate each perspective
+        for perspective in delta.perspectives {
+            // write <perspective id="{id}">
+            attrs_open(&mut xml, "perspective", with_unknown(
+                vec![("id", &perspective.id)],
+                &perspective.unknown,
+            ))?;
+
+            // write <added>{date}</added>
+            text(&mut xml, "added", &perspective.added.to_rfc3339_opts(
+                chrono::SecondsFormat::Millis,
+                true
+            ))?;
+
+            // write <modified>{date}</modified>
+            if let Some(modified) = perspective.modified {
+                text(&mut xml, "modified", &modified.to_rfc3339_opts(
+                    chrono::SecondsFormat::Millis,
+                    true
+                ))?;
+            }
+
+            // write <plist version="1.0"><dict>...</dict></plist>
+            let plist = PlistItem::Dict(perspective.to_plist()?);
+            write_plist(&mut xml, &plist)?;
+
+            // write <icon-attachment> and the rest as they were read
+            unknown_elements(&mut xml, &perspective.unknown)?;
+
+            // </perspective>
+            end(&mut xml)?;
+        }
+
+        // write out the records of kinds we don't know about
+        for record in delta.unknown.iter() {
+            write_element(&mut xml, record)?;
+        }
+
+        // write <{entity} id="{id}" op="delete"/> for each deleted entity
+        for tombstone in delta.tombstones {
+            attrs(&mut xml, tombstone.entity.element_name(), vec![
+                ("id", &tombstone.id),
+                ("op", "delete"),
+            ])?;
+        }
+
         // write </omnifocus>
         end(&mut xml)?;
 
         // write newline
-        xml.inner_mut().write(b"\n")?;
+        xml.inner_mut().write_all(b"\n")?;
 
-        // return the new archive to the database object
-        Ok(archive)
+        let mut zip = xml.into_inner();
+        zip.finish()?.sync_all()?;
+        Ok(())
     }
 
     // read the contents of this Archive from the underlying file
     fn read(&self) -> Result<Content, Error> {
-        parse(File::open(&self.file_path)?)
+        File::open(&self.file_path)
+            .map_err(Error::from)
+            .and_then(parse)
+            .map_err(|e| e.in_file(&self.file_path))
+    }
+
+    // reads as much of this Archive as can be, along with the errors for
+    // the records that couldn't be
+    fn read_lenient(&self) -> Result<(Content, Vec<Error>), Error> {
+        let (content, skipped) = File::open(&self.file_path)
+            .map_err(Error::from)
+            .and_then(parse_lenient)
+            .map_err(|e| e.in_file(&self.file_path))?;
+        let skipped = skipped.into_iter().map(|e| e.in_file(&self.file_path)).collect();
+        Ok((content, skipped))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::TestDir;
+    use crate::task::SubtaskOrder;
+    use crate::unknown::RawElement;
+    use crate::tag::TagStatus;
+    use crate::perspective::{Perspective, TaskProperty};
+    use crate::rule::{Rule, Status};
+
+    fn example_path() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("example.ofocus")
+    }
+
+    // keeps the client id written by the tests out of the real config
+    fn use_test_config() {
+        std::env::set_var("OPENFOCUS_CONFIG_DIR", std::env::temp_dir().join("openfocus-test-config"));
+    }
+
+    // copies the example database somewhere it can be safely written to. the
+    // copy is removed when the TestDir is dropped
+    fn example_copy() -> (TestDir, PathBuf) {
+        fn copy_dir(from: &Path, to: &Path) {
+            std::fs::create_dir_all(to).unwrap();
+            for entry in read_dir(from).unwrap() {
+                let path = entry.unwrap().path();
+                let dest = to.join(path.file_name().unwrap());
+                if path.is_dir() {
+                    copy_dir(&path, &dest);
+                } else {
+                    std::fs::copy(&path, &dest).unwrap();
+                }
+            }
+        }
+
+        use_test_config();
+        let dir = TestDir::default();
+        let dest = dir.path().join("example.ofocus");
+        copy_dir(&example_path(), &dest);
+        (dir, dest)
+    }
+
+    #[test]
+    fn test_partial_update_keeps_fields() {
+        let db = Database::new(example_path()).unwrap();
+        let task = db.content().tasks.iter()
+            .find(|t| t.id == "m0SsIGQYq83")
+            .unwrap();
+
+        // set by the op="update" delta
+        assert!(task.title == "This is a new item");
+        assert!(task.modified.is_some());
+        // only present in the original full record
+        assert!(task.order == Some(SubtaskOrder::Parallel));
+        assert!(task.inbox);
+    }
+
+    #[test]
+    fn test_load_tags() {
+        let db = Database::new(example_path()).unwrap();
+        let content = db.content();
+        assert!(content.tags.len() == 15);
+        assert!(content.tag_path("bW8AXd6N9Bw") == Some("Errands : Supermarket".into()));
+        assert!(content.tag_status("jaipxqh3bAv") == Some(TagStatus::OnHold));
+    }
+
+    #[test]
+    fn test_load_task_tags() {
+        let db = Database::new(example_path()).unwrap();
+        let task = db.content().tasks.iter()
+            .find(|t| t.id == "pbH-1Zw476y")
+            .unwrap();
+        let tags: Vec<&str> = db.task_tags(task).iter()
+            .map(|t| t.name.as_str())
+            .collect();
+        assert!(db.content().task_tags.len() == 11);
+        assert!(tags == vec!["Errands"]);
+    }
+
+    #[test]
+    fn test_add_and_remove_tag() {
+        let (_dir, path) = example_copy();
+        let mut db = Database::new(path.clone()).unwrap();
+        db.add_tag("pbH-1Zw476y", "mK-nCRLM9QS").unwrap();
+
+        let mut db = Database::new(path.clone()).unwrap();
+        let task = db.content().tasks.iter()
+            .find(|t| t.id == "pbH-1Zw476y")
+            .unwrap();
+        let tags: Vec<&str> = db.task_tags(task).iter()
+            .map(|t| t.id.as_str())
+            .collect();
+        assert!(tags == vec!["lNNNk7g-ipb", "mK-nCRLM9QS"]);
+
+        // removing the primary tag promotes the next one
+        db.remove_tag("pbH-1Zw476y", "lNNNk7g-ipb").unwrap();
+        let db = Database::new(path).unwrap();
+        let task = db.content().tasks.iter()
+            .find(|t| t.id == "pbH-1Zw476y")
+            .unwrap();
+        assert!(task.context == Some("mK-nCRLM9QS".into()));
+        assert!(db.task_tags(task).len() == 1);
+    }
+
+    #[test]
+    fn test_load_projects() {
+        let db = Database::new(example_path()).unwrap();
+        let projects = db.content().projects();
+        assert!(projects.len() == 4);
+
+        let servers = db.content().project("n0QKZl8CDEY").unwrap();
+        assert!(servers.info.singleton);
+        assert!(servers.info.review_interval == Some("@1w".into()));
+        assert!(servers.tasks(db.content()).len() == 2);
+    }
+
+    #[test]
+    fn test_project_survives_write() {
+        let (_dir, path) = example_copy();
+        let mut db = Database::new(path.clone()).unwrap();
+        let mut task = db.content().tasks.iter()
+            .find(|t| t.id == "iWjcZSsiWtL")
+            .unwrap()
+            .clone();
+        task.flagged = true;
+        db.write(Content::new_task(task.clone())).unwrap();
+
+        let db = Database::new(path).unwrap();
+        let project = db.content().project("iWjcZSsiWtL").unwrap();
+        assert!(project.task.flagged);
+        assert!(project.info == task.project.as_ref().unwrap());
+    }
+
+    #[test]
+    fn test_edit_writes_only_changed_fields() {
+        let (_dir, path) = example_copy();
+        let mut first = Database::new(path.clone()).unwrap();
+        let task = first.content().tasks.iter()
+            .find(|t| t.id == "pbH-1Zw476y")
+            .unwrap()
+            .clone();
+
+        let mut flagged = task.clone();
+        flagged.flagged = true;
+        first.write(Content::edit_task(&task, flagged)).unwrap();
+
+        // an edit made to the task as it was before it was flagged
+        let mut second = Database::new(path.clone()).unwrap();
+        let mut renamed = task.clone();
+        renamed.title = "Renamed".into();
+        renamed.modified = Some(Utc::now());
+        second.write(Content::edit_task(&task, renamed)).unwrap();
+        let delta = second.archives.last().unwrap().read().unwrap();
+        assert!(delta.tasks.is_empty());
+        assert!(delta.task_updates[0].fields == vec![TaskField::Title, TaskField::Modified]);
+
+        // neither edit overwrote the other
+        let db = Database::new(path).unwrap();
+        let task = db.content().tasks.iter()
+            .find(|t| t.id == "pbH-1Zw476y")
+            .unwrap();
+        assert!(task.flagged);
+        assert!(task.title == "Renamed");
+        assert!(task.note.is_some());
+    }
+
+    #[test]
+    fn test_unknown_elements_round_trip() {
+        let (_dir, path) = example_copy();
+        let mut db = Database::new(path.clone()).unwrap();
+        let task = db.content().tasks.iter()
+            .find(|t| t.id == "pbH-1Zw476y")
+            .unwrap()
+            .clone();
+        let names: Vec<&str> = task.unknown.elements.iter()
+            .map(|e| e.name.as_str())
+            .collect();
+        assert!(names.contains(&"hidden") && names.contains(&"due-date-alarm-policy"));
+
+        // <setting> isn't modeled at all
+        let setting = db.content().unknown.iter()
+            .find(|r| r.name == "setting" && r.attr("id") == Some("jdiDurD6FjA"))
+            .unwrap()
+            .clone();
+
+        db.write(Content {
+            tasks: vec![task.clone()],
+            unknown: vec![setting.clone()],
+            ..Content::default()
+        }).unwrap();
+
+        let db = Database::new(path).unwrap();
+        let reloaded = db.content().tasks.iter()
+            .find(|t| t.id == "pbH-1Zw476y")
+            .unwrap();
+        assert!(reloaded.unknown == task.unknown);
+        let settings: Vec<&RawElement> = db.content().unknown.iter()
+            .filter(|r| r.attr("id") == Some("jdiDurD6FjA"))
+            .collect();
+        assert!(settings == vec![&setting]);
+    }
+
+    #[test]
+    fn test_compact() {
+        let (_dir, path) = example_copy();
+        let mut db = Database::new(path.clone()).unwrap();
+        db.complete("eNJKbQtViaH").unwrap();
+        let head = db.head_id.clone().unwrap();
+
+        // a client that hasn't seen the last write holds up compaction
+        let client = path.join("20191124035738=behindclient.client");
+        std::fs::write(&client, r#"<?xml version="1.0" encoding="UTF-8"?>
+            <plist version="1.0"><dict>
+            <key>tailIdentifiers</key><array><string>oWItkEsdnz0</string></array>
+            </dict></plist>"#).unwrap();
+        assert!(db.compact().is_err());
+        std::fs::remove_file(&client).unwrap();
+
+        let before = Database::new(path.clone()).unwrap();
+        db.compact().unwrap();
+
+        let zips: Vec<String> = read_dir(&path).unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .filter(|n| n.ends_with(".zip"))
+            .collect();
+        assert!(zips.len() == 1);
+        assert!(zips[0].starts_with("00000000000000="));
+        assert!(zips[0].ends_with(&format!("+{}.zip", head)));
+
+        let mut after = Database::new(path.clone()).unwrap();
+        let (a, b) = (before.content(), after.content());
+        assert!(a.tasks == b.tasks);
+        assert!(a.tags == b.tags);
+        assert!(a.task_tags == b.task_tags);
+        assert!(a.folders == b.folders);
+        assert!(a.attachments == b.attachments);
+        assert!(a.perspectives == b.perspectives);
+        assert!(a.unknown == b.unknown);
+
+        // writes carry on from the same head
+        assert!(after.head_id == Some(head));
+        after.delete("eNJKbQtViaH").unwrap();
+        let db = Database::new(path).unwrap();
+        assert!(!db.content().tasks.iter().any(|t| t.id == "eNJKbQtViaH"));
+    }
+
+    #[test]
+    fn test_unknown_project_elements_survive_compact() {
+        let (_dir, path) = example_copy();
+        write_delta(&path, "aaaaaaaaaaa", r#"<task id="iWjcZSsiWtL" op="update" review-flag="1"><project><folder/><singleton>false</singleton><last-review/><next-review/><review-interval>@1w</review-interval><status>active</status><review-alarm>true</review-alarm></project></task>"#);
+
+        let mut db = Database::new(path.clone()).unwrap();
+        let task = db.content().tasks.iter()
+            .find(|t| t.id == "iWjcZSsiWtL")
+            .unwrap()
+            .clone();
+        let unknown = task.project.as_ref().unwrap().unknown.clone();
+        assert!(unknown.elements.len() == 1 && unknown.elements[0].name == "review-alarm");
+
+        // an update writes the task's unknown attributes back out
+        let mut flagged = task.clone();
+        flagged.flagged = true;
+        db.write(Content::edit_task(&task, flagged)).unwrap();
+        let delta = db.archives.last().unwrap().read().unwrap();
+        assert!(delta.task_updates[0].item.unknown.attributes == task.unknown.attributes);
+
+        db.compact().unwrap();
+        let db = Database::new(path).unwrap();
+        let task = db.content().tasks.iter()
+            .find(|t| t.id == "iWjcZSsiWtL")
+            .unwrap();
+        assert!(task.flagged);
+        assert!(task.project.as_ref().unwrap().unknown == unknown);
+        assert!(task.unknown.attributes.contains(&("review-flag".into(), "1".into())));
+    }
+
+    #[test]
+    fn test_write_registers_client() {
+        let (_dir, path) = example_copy();
+        std::fs::write(path.join("20191124035738=cm3GScgD_ET.client"), r#"<?xml version="1.0" encoding="UTF-8"?>
+            <plist version="1.0"><dict>
+            <key>HardwareModel</key><string>MacBookPro15,1</string>
+            <key>name</key><string>Laptop</string>
+            <key>tailIdentifiers</key><array><string>oWItkEsdnz0</string></array>
+            </dict></plist>"#).unwrap();
+
+        let mut db = Database::new(path.clone()).unwrap();
+        db.complete("eNJKbQtViaH").unwrap();
+        db.complete("pbH-1Zw476y").unwrap();
+
+        // only the other device is listed
+        let clients = db.clients().unwrap();
+        assert!(clients.len() == 1);
+        assert!(clients[0].name == Some("Laptop".into()));
+        assert!(clients[0].model == Some("MacBookPro15,1".into()));
+
+        // this device has a single client file pointing at its last write
+        let ours: Vec<Client> = read_clients(&path).unwrap()
+            .into_iter()
+            .filter(|c| c.id == db.client_id().unwrap())
+            .collect();
+        assert!(ours.len() == 1);
+        assert!(Some(&ours[0].tails[0]) == db.head_id.as_ref());
+
+        // reopening the database keeps the same client id
+        let mut reopened = Database::new(path).unwrap();
+        assert!(reopened.client_id().unwrap() == db.client_id().unwrap());
+    }
+
+    #[test]
+    fn test_write_rebases_on_new_head() {
+        use crate::error::OpenFocusErrorType;
+
+        let (_dir, path) = example_copy();
+        // two processes load the same head
+        let mut first = Database::new(path.clone()).unwrap();
+        let mut second = Database::new(path.clone()).unwrap();
+        let task = first.content().tasks.iter()
+            .find(|t| t.id == "pbH-1Zw476y")
+            .unwrap()
+            .clone();
+
+        let mut flagged = task.clone();
+        flagged.flagged = true;
+        first.write(Content::edit_task(&task, flagged)).unwrap();
+        first.delete("eNJKbQtViaH").unwrap();
+
+        // an edit to the task the first deleted can't go on top of its
+        // writes, so none of the delta is written
+        let mut renamed = task.clone();
+        renamed.title = "Renamed".into();
+        let deleted = second.content().tasks.iter()
+            .find(|t| t.id == "eNJKbQtViaH")
+            .unwrap()
+            .clone();
+        let mut edited = deleted.clone();
+        edited.flagged = true;
+        let e = second.write(Content {
+            task_updates: vec![
+                TaskUpdate::diff(&task, renamed.clone()),
+                TaskUpdate::diff(&deleted, edited),
+            ],
+            ..Content::default()
+        }).err().unwrap();
+        assert!(matches!(e.kind(), OpenFocusErrorType::NotFound));
+        assert!(e.entity() == Some("eNJKbQtViaH"));
+        assert!(second.head_id == first.head_id);
+
+        // the rest goes on top of the first's writes instead of forking
+        second.write(Content::edit_task(&task, renamed)).unwrap();
+        let head = second.archives.iter()
+            .find(|a| Some(&a.id) == second.head_id.as_ref())
+            .unwrap();
+        assert!(Some(&head.parent_id) == first.head_id.as_ref());
+        let written = head.read().unwrap();
+        assert!(written.task_updates.len() == 1);
+        assert!(written.task_updates[0].item.id == "pbH-1Zw476y");
+
+        let db = Database::new(path.clone()).unwrap();
+        assert!(db.conflicts().is_empty());
+        let task = db.content().tasks.iter()
+            .find(|t| t.id == "pbH-1Zw476y")
+            .unwrap();
+        assert!(task.flagged && task.title == "Renamed");
+        assert!(!db.content().tasks.iter().any(|t| t.id == "eNJKbQtViaH"));
+
+        // nothing half written is left behind
+        assert!(!read_dir(&path).unwrap().any(|e| {
+            e.unwrap().path().extension().and_then(|e| e.to_str()) == Some("tmp")
+        }));
+    }
+
+    #[test]
+    fn test_branches_are_merged() {
+        let (_dir, path) = example_copy();
+        let mut first = Database::new(path.clone()).unwrap();
+        let task = first.content().tasks.iter()
+            .find(|t| t.id == "pbH-1Zw476y")
+            .unwrap()
+            .clone();
+
+        let mut flagged = task.clone();
+        flagged.flagged = true;
+        first.write(Content::edit_task(&task, flagged)).unwrap();
+
+        // another device that hadn't synced the write above writes on top of
+        // the same head
+        let mut renamed = task.clone();
+        renamed.title = "Renamed".into();
+        let branch = Archive::save("oWItkEsdnz0", &path, Content::edit_task(&task, renamed))
+            .unwrap();
+        let tombstones = vec![Tombstone { entity: Entity::Task, id: "muZXMz_e48J".into() }];
+        Archive::save(&branch.id, &path, Content::delete(tombstones)).unwrap();
+
+        let db = Database::new(path).unwrap();
+        let task = db.content().tasks.iter()
+            .find(|t| t.id == "pbH-1Zw476y")
+            .unwrap();
+        assert!(task.flagged && task.title == "Renamed");
+        assert!(!db.content().tasks.iter().any(|t| t.id == "muZXMz_e48J"));
+
+        let conflicts = db.conflicts();
+        assert!(conflicts.len() == 1);
+        assert!(conflicts[0].parent == "oWItkEsdnz0");
+        assert!(conflicts[0].branches.len() == 2);
+        assert!(conflicts[0].ids == vec!["pbH-1Zw476y".to_string()]);
+    }
+
+    #[test]
+    fn test_interrupted_compaction() {
+        let (_dir, path) = example_copy();
+        let db = Database::new(path.clone()).unwrap();
+        let head = db.archives.iter()
+            .find(|a| Some(&a.id) == db.head_id.as_ref())
+            .unwrap();
+
+        // the new root was written but none of the old archives were removed
+        Archive::create(ROOT_DATE, &head.parent_id, &head.id, &path, db.content.clone())
+            .unwrap();
+
+        let reloaded = Database::new(path.clone()).unwrap();
+        assert!(reloaded.root().unwrap().id == head.id);
+        assert!(reloaded.chain().len() == 1);
+        assert!(reloaded.content().tasks == db.content().tasks);
+        assert!(reloaded.conflicts().is_empty());
+
+        // the head delta was removed but the rest of the old chain is still
+        // there. the new root still wins and nothing is lost
+        std::fs::remove_file(&head.file_path).unwrap();
+        let reloaded = Database::new(path).unwrap();
+        assert!(reloaded.root().unwrap().id == head.id);
+        assert!(reloaded.head_id == Some(head.id.clone()));
+        assert!(reloaded.content().tasks == db.content().tasks);
+    }
+
+    #[test]
+    fn test_unwritable_updates_are_rejected() {
+        use crate::error::OpenFocusErrorType;
+        use crate::parse::Update;
+        use crate::tag::TagField;
+
+        let (_dir, path) = example_copy();
+        let mut db = Database::new(path.clone()).unwrap();
+        let archives = db.archives.len();
+        let mut tag = db.content().tags[0].clone();
+        tag.name = "Renamed".into();
+        let delta = Content {
+            tag_updates: vec![Update { item: tag.clone(), fields: vec![TagField::Name] }],
+            ..Content::default()
+        };
+
+        // nothing is written or applied
+        let e = db.transaction(|tx| {
+            tx.apply(delta);
+            Ok(())
+        }).err().unwrap();
+        assert!(matches!(e.kind(), OpenFocusErrorType::InvalidArgument));
+        assert!(db.archives.len() == archives);
+        assert!(db.content().tag(&tag.id).unwrap().name != "Renamed");
+        assert!(scan_archives(&path, None).unwrap().len() == archives);
+    }
+
+    #[test]
+    fn test_failed_write_leaves_nothing_behind() {
+        let (_dir, path) = example_copy();
+        let mut db = Database::new(path.clone()).unwrap();
+        let files = read_dir(&path).unwrap().count();
+
+        // there's no JSON for this rule so writing stops part way through
+        let mut perspective = Perspective::new("Can't be written");
+        perspective.rules = vec![Rule::HasDate(crate::rule::DateField::Completed, true)];
+        assert!(db.write(Content::new_perspective(perspective)).is_err());
+        assert!(read_dir(&path).unwrap().count() == files);
+    }
+
+    #[test]
+    fn test_transaction() {
+        let (_dir, path) = example_copy();
+        let mut db = Database::new(path.clone()).unwrap();
+        let archives = db.archives.len();
+
+        // an error part way through leaves no trace
+        let failed = db.transaction(|tx| {
+            tx.complete("eNJKbQtViaH")?;
+            tx.delete("not a real id")
+        });
+        assert!(failed.is_err());
+        assert!(db.archives.len() == archives);
+        assert!(db.content().tasks.iter().any(|t| t.id == "eNJKbQtViaH" && t.completed.is_none()));
+
+        let id = db.transaction(|tx| {
+            let mut flagged = tx.content().tasks.iter()
+                .find(|t| t.id == "pbH-1Zw476y")
+                .unwrap()
+                .clone();
+            flagged.flagged = true;
+            tx.update(flagged)?;
+            let new = Task { title: "Added in a transaction".into(), ..Task::default() };
+            let id = new.id.clone();
+            tx.create(new)?;
+            tx.complete("eNJKbQtViaH")?;
+            Ok(id)
+        }).unwrap();
+
+        // everything went into one archive and is visible without reloading
+        assert!(db.archives.len() == archives + 1);
+        for content in [db.content(), Database::new(path.clone()).unwrap().content()].iter() {
+            assert!(content.tasks.iter().any(|t| t.id == "pbH-1Zw476y" && t.flagged));
+            assert!(content.tasks.iter().any(|t| t.id == id));
+            assert!(content.tasks.iter().any(|t| t.id == "eNJKbQtViaH" && t.completed.is_some()));
+        }
+    }
+
+    #[test]
+    fn test_write_updates_content() {
+        let (_dir, path) = example_copy();
+        let mut db = Database::new(path).unwrap();
+        let task = Task { title: "Written".into(), ..Task::default() };
+        db.write(Content::new_task(task.clone())).unwrap();
+        assert!(db.content().tasks.iter().any(|t| t == &task));
+
+        db.delete(&task.id).unwrap();
+        assert!(!db.content().tasks.iter().any(|t| t.id == task.id));
+    }
+
+    #[test]
+    fn test_write_cleared_fields() {
+        let (_dir, path) = example_copy();
+        let mut db = Database::new(path.clone()).unwrap();
+        let task = Task {
+            title: "Numbered".into(),
+            rank: Some(5),
+            next_clone_identifier: Some(2),
+            order: Some(SubtaskOrder::Sequential),
+            ..Task::default()
+        };
+        db.write(Content::new_task(task.clone())).unwrap();
+
+        // clearing a field in an update writes it out empty
+        let cleared = Task { rank: None, next_clone_identifier: None, order: None, ..task.clone() };
+        let delta = Content::edit_task(&task, cleared.clone());
+        assert!(delta.task_updates[0].fields.len() == 3);
+        db.write(delta).unwrap();
+
+        let db = Database::new(path).unwrap();
+        let read = db.content().tasks.iter().find(|t| t.id == task.id).unwrap();
+        assert!(read.rank.is_none());
+        assert!(read.next_clone_identifier.is_none());
+        assert!(read.order.is_none());
+    }
+
+    #[test]
+    fn test_reload() {
+        let (_dir, path) = example_copy();
+        let mut db = Database::new(path.clone()).unwrap();
+        let mut other = Database::new(path.clone()).unwrap();
+        assert!(!db.reload().unwrap());
+
+        // only shows up if what's loaded isn't read again
+        let marker = Task { title: "Only in memory".into(), ..Task::default() };
+        db.content.tasks.push(marker.clone());
+
+        let task = Task { title: "From another process".into(), ..Task::default() };
+        other.write(Content::new_task(task.clone())).unwrap();
+        assert!(db.reload().unwrap());
+        assert!(db.content().tasks.iter().any(|t| t.id == task.id));
+        assert!(db.content().tasks.iter().any(|t| t.id == marker.id));
+        assert!(db.head_id == other.head_id);
+
+        // compacting removes archives so everything is read again
+        other.compact().unwrap();
+        assert!(db.reload().unwrap());
+        assert!(!db.content().tasks.iter().any(|t| t.id == marker.id));
+        let fresh = Database::new(path).unwrap();
+        assert!(db.content().tasks == fresh.content().tasks);
+        assert!(db.head_id == other.head_id);
+    }
+
+    #[test]
+    fn test_reload_older_branch() {
+        let (_dir, path) = example_copy();
+        let mut db = Database::new(path.clone()).unwrap();
+        let mut other = Database::new(path.clone()).unwrap();
+
+        let old = other.content().tasks.iter().find(|t| t.id == "f23PngiKv2G").unwrap().clone();
+        let task = Task { title: "From the newer branch".into(), ..old.clone() };
+        other.write(Content::edit_task(&old, task)).unwrap();
+        assert!(db.reload().unwrap());
+
+        // a branch forked from the same archive but written earlier shows up
+        // late. it comes first in the chain so the newer title still wins
+        write_delta(&path, "aaaaaaaaaaa", r#"<task id="f23PngiKv2G" op="update"><name>From the older branch</name></task>"#);
+        assert!(db.reload().unwrap());
+        let task = db.content().tasks.iter().find(|t| t.id == "f23PngiKv2G").unwrap();
+        assert!(task.title == "From the newer branch");
+        assert!(db.conflicts().len() == 1);
+        let fresh = Database::new(path).unwrap();
+        assert!(db.content().tasks == fresh.content().tasks);
+        assert!(db.head_id == fresh.head_id);
+    }
+
+    #[test]
+    fn test_create() {
+        use_test_config();
+        let dir = TestDir::default();
+        let path = dir.path().join("new.ofocus");
+        // there's nothing to load yet
+        std::fs::create_dir_all(&path).unwrap();
+        assert!(Database::new(path.clone()).is_err());
+
+        let mut db = Database::create(path.clone()).unwrap();
+        assert!(db.archives.len() == 1);
+        let root = db.archives[0].read().unwrap();
+        assert!(root.ids().is_empty() && root.unknown.is_empty());
+        let task = Task { title: "First task".into(), inbox: true, ..Task::default() };
+        db.write(Content::new_task(task.clone())).unwrap();
+
+        let db = Database::new(path.clone()).unwrap();
+        assert!(db.content().tasks.iter().any(|t| t.id == task.id));
+        for name in CAPABILITIES {
+            assert!(path.join(format!("{}.capability", name)).exists());
+        }
+
+        // it won't overwrite an existing database
+        assert!(Database::create(path).is_err());
+    }
+
+    // writes a delta on top of the head with the given records
+    fn write_delta(path: &Path, id: &str, records: &str) -> PathBuf {
+        let file_path = path.join(format!("20191124035739=oWItkEsdnz0+{}.zip", id));
+        let mut zip = ZipWriter::new(File::create(&file_path).unwrap());
+        zip.start_file("contents.xml", zip::write::FileOptions::default()).unwrap();
+        write!(zip, concat!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>"#, "\n",
+            r#"<omnifocus xmlns="http://www.omnigroup.com/namespace/OmniFocus/v2">"#, "\n",
+            "{}\n</omnifocus>\n",
+        ), records).unwrap();
+        zip.finish().unwrap();
+        file_path
+    }
+
+    #[test]
+    fn test_malformed_archive_error() {
+        use crate::error::OpenFocusErrorType;
+
+        let (_dir, path) = example_copy();
+        let file_path = write_delta(&path, "aaaaaaaaaaa", r#"<task id="broken"><title>No added date</title></task>"#);
+        let e = Database::new(path.clone()).err().unwrap();
+        assert!(matches!(e.kind(), OpenFocusErrorType::Missing("added")));
+        assert!(e.element() == Some("omnifocus/task"));
+        assert!(e.entity() == Some("broken"));
+        assert!(e.path() == Some(file_path.as_path()));
+        assert!(e.position().map(|(line, _)| line) == Some(3));
+
+        std::fs::remove_file(&file_path).unwrap();
+        write_delta(&path, "bbbbbbbbbbb", r#"<task id="late" op="update"><due>tomorrow</due></task>"#);
+        let e = Database::new(path).err().unwrap();
+        assert!(matches!(e.kind(), OpenFocusErrorType::Date(_)));
+        assert!(e.entity() == Some("late"));
+        assert!(e.to_string().starts_with("Date Error"));
+    }
+
+    #[test]
+    fn test_recover() {
+        use crate::error::OpenFocusErrorType;
+
+        // a delta with one good task and one with a bad date part way
+        // through, then a delta on top of it cut off while syncing
+        let (_dir, path) = example_copy();
+        let broken = write_delta(&path, "aaaaaaaaaaa", concat!(
+            r#"<task id="broken"><added>2019-11-24T03:57:39.000Z</added><due>tomorrow</due>"#,
+            r#"<note><text><p><run><lit>nested</lit></run></p></text></note></task>"#,
+            r#"<task id="kept"><added>2019-11-24T03:57:39.000Z</added>"#,
+            r#"<name>Still here</name></task>"#,
+        ));
+        let truncated = path.join("20191124035740=aaaaaaaaaaa+bbbbbbbbbbb.zip");
+        let bytes = std::fs::read(&broken).unwrap();
+        std::fs::write(&truncated, &bytes[..bytes.len() / 2]).unwrap();
+        let misnamed = path.join("not an archive.zip");
+        std::fs::write(&misnamed, b"").unwrap();
+        assert!(Database::new(path.clone()).is_err());
+
+        let mut db = Database::recover(path.clone()).unwrap();
+        assert!(db.content().tasks.iter().any(|t| t.id == "kept"));
+        assert!(db.content().tasks.iter().any(|t| t.id == "eNJKbQtViaH"));
+        assert!(!db.content().tasks.iter().any(|t| t.id == "broken"));
+        assert!(db.head_id == Some("bbbbbbbbbbb".into()));
+
+        let skipped = db.skipped();
+        assert!(skipped.len() == 3);
+        let record = skipped.iter().find(|e| e.entity() == Some("broken")).unwrap();
+        assert!(matches!(record.kind(), OpenFocusErrorType::Date(_)));
+        assert!(record.path() == Some(broken.as_path()));
+        assert!(skipped.iter().any(|e| e.path() == Some(truncated.as_path())));
+        assert!(skipped.iter().any(|e| e.path() == Some(misnamed.as_path())));
+
+        // reloading doesn't report anything twice and writes go on the end
+        // of the chain as usual
+        db.complete("kept").unwrap();
+        assert!(db.skipped().len() == 3);
+        assert!(db.compact().is_err());
+        let db = Database::recover(path).unwrap();
+        assert!(db.content().tasks.iter().any(|t| t.id == "kept" && t.completed.is_some()));
+    }
+
+    #[test]
+    fn test_doctor() {
+        let (_dir, path) = example_copy();
+        let db = Database::new(path.clone()).unwrap();
+        assert!(db.check().unwrap().is_empty());
+
+        // a task inside one that doesn't exist, a task written twice, an
+        // archive whose parent is missing, a misnamed archive and a data file
+        // that's been changed
+        write_delta(&path, "aaaaaaaaaaa", concat!(
+            r#"<task id="stray"><task idref="nonexistent"/>"#,
+            r#"<added>2019-11-24T03:57:39.000Z</added><name>Stray</name></task>"#,
+            r#"<task id="twice"><added>2019-11-24T03:57:39.000Z</added><name>One</name></task>"#,
+            r#"<task id="twice"><added>2019-11-24T03:57:39.000Z</added><name>Two</name></task>"#,
+        ));
+        let orphan = path.join("20191124035740=nonexistent+bbbbbbbbbbb.zip");
+        std::fs::copy(path.join("20191124035738=iITATwF6-P3+oWItkEsdnz0.zip"), &orphan).unwrap();
+        let misnamed = path.join("copy of an archive.zip");
+        std::fs::write(&misnamed, b"").unwrap();
+        let data = path.join("data").join(format!("{}.zip", data_identifier(b"original")));
+        let mut zip = ZipWriter::new(File::create(&data).unwrap());
+        zip.start_file("file.txt", zip::write::FileOptions::default()).unwrap();
+        zip.write_all(b"changed").unwrap();
+        zip.finish().unwrap();
+
+        let mut db = Database::recover(path.clone()).unwrap();
+        let problems = db.check().unwrap();
+        assert!(problems.len() == 5);
+        assert!(problems.contains(&Problem::DuplicateId {
+            id: "twice".into(),
+            entities: vec![Entity::Task, Entity::Task],
+        }));
+        assert!(problems.contains(&Problem::BadArchiveName(misnamed.clone())));
+        assert!(problems.contains(&Problem::OrphanedArchive {
+            path: orphan,
+            parent: "nonexistent".into(),
+        }));
+        assert!(problems.contains(&Problem::BadData {
+            path: data,
+            identifier: Some(data_identifier(b"changed")),
+        }));
+        assert!(problems.contains(&Problem::DanglingReference {
+            entity: Entity::Task,
+            id: "stray".into(),
+            field: "parent",
+            target: "nonexistent".into(),
+        }));
+
+        // nothing is fixed while part of the database can't be read
+        assert!(db.repair(&problems).is_err());
+        std::fs::remove_file(&misnamed).unwrap();
+        let mut db = Database::recover(path.clone()).unwrap();
+
+        // only the stray task can be fixed with a delta
+        assert!(db.repair(&problems).unwrap());
+        let db = Database::recover(path).unwrap();
+        let stray = db.content().tasks.iter().find(|t| t.id == "stray").unwrap();
+        assert!(stray.parent.is_none() && stray.inbox);
+        assert!(db.check().unwrap().iter().all(|p| !p.fixable()));
+    }
+
+    #[test]
+    fn test_open_at() {
+        use crate::error::OpenFocusErrorType;
+
+        let (_dir, path) = example_copy();
+        let mut db = Database::new(path.clone()).unwrap();
+        let before = db.content().tasks.clone();
+        db.write(Content::new_task(Task { title: "Later".into(), ..Task::default() })).unwrap();
+
+        // going back to the old head undoes the write
+        let past = Database::open_at(path.clone(), At::Archive("oWItkEsdnz0".into())).unwrap();
+        assert!(past.head_id == Some("oWItkEsdnz0".into()));
+        assert!(past.content().tasks == before);
+
+        // a time between two archives stops at the earlier one, and a time
+        // before any of them is only the root
+        let date = Utc.with_ymd_and_hms(2019, 11, 24, 3, 57, 36).unwrap();
+        let mut past = Database::open_at(path.clone(), At::Date(date)).unwrap();
+        assert!(past.head_id == Some("iITATwF6-P3".into()));
+        let archive = Database::open_at(path.clone(), At::Archive("iITATwF6-P3".into())).unwrap();
+        assert!(past.content().tasks == archive.content().tasks);
+        let date = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let root = Database::open_at(path.clone(), At::Date(date)).unwrap();
+        assert!(root.head_id == Some("chN6WaF70I_".into()));
+
+        // the past is read only
+        let e = past.write(Content::new_task(Task::default())).err().unwrap();
+        assert!(matches!(e.kind(), OpenFocusErrorType::InvalidArgument));
+        assert!(past.compact().is_err());
+        assert!(Database::open_at(path, At::Archive("nonexistent".into())).is_err());
+    }
+
+    #[test]
+    fn test_note_round_trip() {
+        let (_dir, path) = example_copy();
+        let mut db = Database::new(path.clone()).unwrap();
+        let task = db.content().tasks.iter()
+            .find(|t| t.id == "pbH-1Zw476y")
+            .unwrap()
+            .clone();
+        let markdown = task.note.as_ref().unwrap().to_markdown();
+        assert!(markdown.contains("[Forecast perspective](omnifocus:///forecast)"));
+
+        db.write(Content::new_task(task.clone())).unwrap();
+        let db = Database::new(path).unwrap();
+        let reloaded = db.content().tasks.iter()
+            .find(|t| t.id == "pbH-1Zw476y")
+            .unwrap();
+        assert!(reloaded.note == task.note);
+    }
+
+    #[test]
+    fn test_delete_task_and_subtasks() {
+        let (_dir, path) = example_copy();
+        let mut db = Database::new(path.clone()).unwrap();
+        db.delete("iWjcZSsiWtL").unwrap();
+
+        let db = Database::new(path).unwrap();
+        let ids = ["iWjcZSsiWtL", "kuNxDxmJryU", "pbH-1Zw476y", "oRql4OdW0lz"];
+        assert!(!db.content().tasks.iter().any(|t| ids.contains(&t.id.as_str())));
+        assert!(db.content().tasks.iter().any(|t| t.id == "oUEK2p7ebP2"));
+    }
+
+    #[test]
+    fn test_records_apply_in_order() {
+        let (_dir, path) = example_copy();
+        write_delta(&path, "aaaaaaaaaaa", concat!(
+            // deleted and then written again
+            r#"<task id="pbH-1Zw476y" op="delete"/>"#,
+            r#"<task id="pbH-1Zw476y"><added>2019-11-24T03:57:39.000Z</added><name>Again</name></task>"#,
+            // flagged before being replaced by an unflagged task
+            r#"<task id="eNJKbQtViaH" op="update"><flagged>true</flagged></task>"#,
+            r#"<task id="eNJKbQtViaH"><added>2019-11-24T03:57:39.000Z</added><name>Replaced</name></task>"#,
+            // written and then deleted
+            r#"<task id="brandnewtsk"><added>2019-11-24T03:57:39.000Z</added><name>Gone</name></task>"#,
+            r#"<task id="brandnewtsk" op="delete"/>"#,
+        ));
+
+        let db = Database::new(path).unwrap();
+        let task = |id: &str| db.content().tasks.iter().find(|t| t.id == id);
+        assert!(task("pbH-1Zw476y").unwrap().title == "Again");
+        let replaced = task("eNJKbQtViaH").unwrap();
+        assert!(replaced.title == "Replaced" && !replaced.flagged);
+        assert!(task("brandnewtsk").is_none());
+    }
+
+    #[test]
+    fn test_complete_repeating_task() {
+        let (_dir, path) = example_copy();
+        let mut db = Database::new(path.clone()).unwrap();
+        db.complete("f23PngiKv2G").unwrap();
+
+        // the completed occurrence is a clone in the same project
+        let db = Database::new(path).unwrap();
+        let done = db.content().tasks.iter()
+            .find(|t| t.id == "f23PngiKv2G.0")
+            .unwrap();
+        assert!(done.completed.is_some());
+        assert!(done.title == "Update webserver");
+        assert!(done.parent == Some("n0QKZl8CDEY".into()));
+        assert!(done.repetition_rule.is_none());
+
+        // and the task itself is the next occurrence, due a week from now
+        let next = db.content().tasks.iter()
+            .find(|t| t.id == "f23PngiKv2G")
+            .unwrap();
+        assert!(next.completed.is_none());
+        assert!(next.repetition_rule == Some("FREQ=WEEKLY".parse().unwrap()));
+        assert!(next.due.unwrap() > Utc::now() + chrono::Duration::days(6));
+        assert!(next.next_clone_identifier == Some(1));
+    }
+
+    #[test]
+    fn test_read_attachment() {
+        let db = Database::new(example_path()).unwrap();
+        let task = db.content().tasks.iter()
+            .find(|t| t.id == "oUEK2p7ebP2")
+            .unwrap();
+        let attachments = db.content().attachments_for(&task.id);
+        assert!(attachments.len() == 2);
+        assert!(attachments[0].name == "Pasted_Graphic.tiff");
+        assert!(attachments[1].preview_image.as_ref().unwrap().starts_with(b"\x89PNG"));
+
+        let data = db.read_attachment("n7KQ-Q7cQvj").unwrap();
+        assert!(data.len() == 4016);
+        assert!(Some(data_identifier(&data)) == attachments[0].data_identifier);
+    }
+
+    #[test]
+    fn test_add_attachment() {
+        let (_dir, path) = example_copy();
+        let mut db = Database::new(path.clone()).unwrap();
+        let id = db.add_attachment("pbH-1Zw476y", "hello.txt", b"hello").unwrap();
+
+        let mut db = Database::new(path.clone()).unwrap();
+        let attachment = db.content().attachment(&id).unwrap().clone();
+        assert!(attachment.task == Some("pbH-1Zw476y".into()));
+        assert!(db.read_attachment(&id).unwrap() == b"hello");
+        let data: Vec<PathBuf> = read_dir(path.join("data")).unwrap()
+            .map(|e| e.unwrap().path())
+            .collect();
+        assert!(data.iter().all(|p| p.extension() == Some("zip".as_ref())));
+
+        // an attachment whose name isn't in the zip it points at can't be read
+        let other = Attachment { id: generate_id(), name: "other.txt".into(), ..attachment };
+        db.write(Content { attachments: vec![other.clone()], ..Content::default() }).unwrap();
+        let e = db.read_attachment(&other.id).err().unwrap();
+        assert!(matches!(e.kind(), crate::error::OpenFocusErrorType::NotFound));
+
+        // deleting the task deletes its attachments
+        db.delete("pbH-1Zw476y").unwrap();
+        let db = Database::new(db.file_path.clone()).unwrap();
+        assert!(db.content().attachment(&id).is_none());
+    }
+
+    #[test]
+    fn test_custom_perspective() {
+        let (_dir, path) = example_copy();
+        let mut db = Database::new(path.clone()).unwrap();
+        db.complete("eNJKbQtViaH").unwrap();
+
+        let db = Database::new(path).unwrap();
+        let content = db.content();
+        let completed = content.perspective("completed").unwrap();
+        assert!(completed.name == Some("Completed".into()));
+        let tasks = completed.tasks(content);
+        assert!(tasks.len() == 1 && tasks[0].id == "eNJKbQtViaH");
+
+        // "Changed" only has a disabled rule so it shows everything, most
+        // recently modified first
+        let changed = content.perspective("Changed").unwrap();
+        assert!(changed.icon == Some("perspectivelist_new_calendar".into()));
+        assert!(changed.version == Some("3.0.0.2".into()));
+        assert!(changed.view_state.collation == TaskProperty::Modified);
+        let tasks = changed.tasks(content);
+        assert!(tasks.len() == content.tasks.len());
+        assert!(tasks[0].modified >= tasks[1].modified);
+    }
+
+    #[test]
+    fn test_write_perspective() {
+        let (_dir, path) = example_copy();
+        let mut db = Database::new(path.clone()).unwrap();
+
+        // create a perspective of flagged tasks grouped by project
+        let mut perspective = Perspective::new("Flagged by project");
+        perspective.rules = vec![Rule::Status(Status::Flagged)];
+        perspective.view_state.collation = TaskProperty::Project;
+        let id = perspective.id.clone();
+        db.write(Content::new_perspective(perspective.clone())).unwrap();
+
+        // edit a builtin one, which keeps the keys we don't model
+        let mut changed = db.content().perspective("Changed").unwrap().clone();
+        changed.name = Some("Recently Changed".into());
+        changed.view_state.sort = TaskProperty::Title;
+        db.write(Content::new_perspective(changed)).unwrap();
+
+        let mut db = Database::new(path.clone()).unwrap();
+        let content = db.content();
+        let read = content.perspective("flagged by project").unwrap();
+        assert!(read.id == id);
+        assert!(read.rules == perspective.rules);
+        assert!(read.view_state == perspective.view_state);
+        // nothing is written that wasn't read, ex: an <icon-attachment>
+        assert!(read.unknown.elements.is_empty());
+        let changed = content.perspective("Recently Changed").unwrap();
+        assert!(changed.view_state.sort == TaskProperty::Title);
+        assert!(changed.view_state.collation == TaskProperty::Modified);
+        let first_responder = changed.plist.get("viewState")
+            .and_then(|v| v.get_path(&["viewModeState", "context", "firstResponder"]));
+        assert!(first_responder == Some(&PlistItem::String("actionOutline".into())));
+
+        db.delete(&id).unwrap();
+        let db = Database::new(path).unwrap();
+        assert!(db.content().perspective("flagged by project").is_none());
+    }
+
+    #[test]
+    fn test_perspective_updates() {
+        let (_dir, path) = example_copy();
+        let db = Database::new(path.clone()).unwrap();
+        let changed = db.content().perspective("Changed").unwrap().clone();
+
+        // updates only carry what changed, which can be an empty <modified/>
+        write_delta(&path, "aaaaaaaaaaa", &format!(concat!(
+            r#"<perspective id="{}" op="update"><modified/>"#,
+            r#"<plist version="1.0"><dict><key>name</key><string>Renamed</string></dict></plist>"#,
+            r#"</perspective>"#,
+        ), changed.id));
+
+        let db = Database::new(path).unwrap();
+        let renamed = db.content().perspective("Renamed").unwrap();
+        assert!(renamed.id == changed.id);
+        assert!(renamed.added == changed.added);
+        assert!(renamed.modified.is_none());
+        assert!(renamed.icon.is_none());
+        assert!(renamed.unknown == changed.unknown);
     }
 }
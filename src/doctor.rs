@@ -0,0 +1,361 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use chrono::prelude::*;
+
+use crate::util::ID;
+use crate::parse::{Content, Entity, Tombstone};
+use crate::task::Task;
+use crate::transaction::Transaction;
+
+// something wrong with a database, found by Database::check. only broken
+// references and cycles can be fixed by writing a delta, see repair()
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Problem {
+    // a .zip whose name isn't {date}={parent id}+{id}.zip
+    BadArchiveName(PathBuf),
+    // an archive that isn't loaded because its parent isn't in the database
+    OrphanedArchive { path: PathBuf, parent: String },
+    // the same id used by more than one entity, ex: two <task> records in
+    // one archive or a task and a tag
+    DuplicateId { id: ID, entities: Vec<Entity> },
+    // a reference to an entity that doesn't exist. field says which one,
+    // ex: "parent" for a task whose parent was deleted
+    DanglingReference { entity: Entity, id: ID, field: &'static str, target: ID },
+    // entities that are their own ancestors, ex: two tasks inside each other
+    Cycle { entity: Entity, ids: Vec<ID> },
+    // a file in data/ whose name isn't the hash of its contents. identifier
+    // is what it should be called, or None if it can't be read
+    BadData { path: PathBuf, identifier: Option<String> },
+}
+
+impl Problem {
+    // whether repair() does anything about this problem
+    pub fn fixable(&self) -> bool {
+        matches!(self, Problem::DanglingReference { .. } | Problem::Cycle { .. })
+    }
+}
+
+// ex: task kuNxDxmJryU has a parent iWjcZSsiWtL that doesn't exist
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Problem::BadArchiveName(path) => {
+                write!(f, "{} isn't named like an archive", path.display())
+            }
+            Problem::OrphanedArchive { path, parent } => {
+                write!(f, "{} is never loaded, its parent {} doesn't exist", path.display(), parent)
+            }
+            Problem::DuplicateId { id, entities } => {
+                let names: Vec<&str> = entities.iter().map(|e| e.element_name()).collect();
+                write!(f, "{} is the id of more than one entity: {}", id, names.join(", "))
+            }
+            Problem::DanglingReference { entity, id, field, target } => {
+                write!(
+                    f,
+                    "{} {} has a {} {} that doesn't exist",
+                    entity.element_name(), id, field, target,
+                )
+            }
+            Problem::Cycle { entity, ids } => {
+                write!(f, "{} {} are inside of each other", entity.element_name(), ids.join(", "))
+            }
+            Problem::BadData { path, identifier: Some(identifier) } => {
+                write!(f, "{} doesn't match its contents, which are {}", path.display(), identifier)
+            }
+            Problem::BadData { path, identifier: None } => {
+                write!(f, "{} can't be read", path.display())
+            }
+        }
+    }
+}
+
+// every full record by id, along with the ids in the order they were first
+// seen
+fn records_by_id(content: &Content) -> (HashMap<&ID, Vec<Entity>>, Vec<&ID>) {
+    let mut ids: HashMap<&ID, Vec<Entity>> = HashMap::new();
+    let mut order: Vec<&ID> = Vec::new();
+    let all = content.tasks.iter().map(|t| (&t.id, Entity::Task))
+        .chain(content.tags.iter().map(|t| (&t.id, Entity::Context)))
+        .chain(content.folders.iter().map(|f| (&f.id, Entity::Folder)))
+        .chain(content.task_tags.iter().map(|l| (&l.id, Entity::TaskToTag)))
+        .chain(content.attachments.iter().map(|a| (&a.id, Entity::Attachment)))
+        .chain(content.perspectives.iter().map(|p| (&p.id, Entity::Perspective)));
+    for (id, entity) in all {
+        let entities = ids.entry(id).or_insert_with(|| {
+            order.push(id);
+            Vec::new()
+        });
+        entities.push(entity);
+    }
+    (ids, order)
+}
+
+// the ids given to more than one record
+fn duplicate_ids(ids: &HashMap<&ID, Vec<Entity>>, order: &[&ID]) -> Vec<Problem> {
+    order.iter()
+        .filter(|id| ids[*id].len() > 1)
+        .map(|id| Problem::DuplicateId { id: (*id).clone(), entities: ids[*id].clone() })
+        .collect()
+}
+
+// finds the ids used more than once in the records of a single archive, as
+// read before they're merged into the rest. a second record of the same kind
+// would otherwise just replace the first
+pub fn check_archive(content: &Content) -> Vec<Problem> {
+    let (ids, order) = records_by_id(content);
+    duplicate_ids(&ids, &order)
+}
+
+// finds the problems in the loaded data. the files of the database are
+// checked by Database::check
+pub fn check_content(content: &Content) -> Vec<Problem> {
+    // ids used by more than one kind of entity, which may have been written
+    // in different archives
+    let (ids, order) = records_by_id(content);
+    let mut problems = duplicate_ids(&ids, &order);
+
+    // references to an entity of the given kind that isn't there
+    let exists = |entity: Entity, id: &ID| -> bool {
+        ids.get(id).map(|e| e.contains(&entity)).unwrap_or(false)
+    };
+    let mut dangling = |entity: Entity, id: &ID, field: &'static str, target: Option<&ID>, kind: Entity| {
+        if let Some(target) = target {
+            if !exists(kind, target) {
+                problems.push(Problem::DanglingReference {
+                    entity,
+                    id: id.clone(),
+                    field,
+                    target: target.clone(),
+                });
+            }
+        }
+    };
+    for task in content.tasks.iter() {
+        dangling(Entity::Task, &task.id, "parent", task.parent.as_ref(), Entity::Task);
+        dangling(Entity::Task, &task.id, "context", task.context.as_ref(), Entity::Context);
+        let folder = task.project.as_ref().and_then(|p| p.folder.as_ref());
+        dangling(Entity::Task, &task.id, "folder", folder, Entity::Folder);
+    }
+    for tag in content.tags.iter() {
+        dangling(Entity::Context, &tag.id, "parent", tag.parent.as_ref(), Entity::Context);
+    }
+    for folder in content.folders.iter() {
+        dangling(Entity::Folder, &folder.id, "parent", folder.parent.as_ref(), Entity::Folder);
+    }
+    for link in content.task_tags.iter() {
+        dangling(Entity::TaskToTag, &link.id, "task", Some(&link.task), Entity::Task);
+        dangling(Entity::TaskToTag, &link.id, "context", Some(&link.tag), Entity::Context);
+    }
+    for attachment in content.attachments.iter() {
+        dangling(Entity::Attachment, &attachment.id, "task", attachment.task.as_ref(), Entity::Task);
+    }
+
+    // trees that loop back on themselves
+    let trees = vec![
+        (Entity::Task, content.tasks.iter().map(|t| (&t.id, t.parent.as_ref())).collect()),
+        (Entity::Context, content.tags.iter().map(|t| (&t.id, t.parent.as_ref())).collect()),
+        (Entity::Folder, content.folders.iter().map(|f| (&f.id, f.parent.as_ref())).collect()),
+    ];
+    for (entity, parents) in trees {
+        for ids in find_cycles(parents) {
+            problems.push(Problem::Cycle { entity, ids });
+        }
+    }
+
+    problems
+}
+
+// the ids in each loop of a tree, sorted. parents maps each node to its parent
+fn find_cycles(parents: HashMap<&ID, Option<&ID>>) -> Vec<Vec<ID>> {
+    let mut nodes: Vec<&ID> = parents.keys().copied().collect();
+    nodes.sort();
+
+    let mut cycles: Vec<Vec<ID>> = Vec::new();
+    for node in nodes {
+        // follow the parents up until the top or somewhere already visited
+        let mut path: Vec<&ID> = vec![node];
+        let mut curr = parents[node];
+        while let Some(id) = curr {
+            if let Some(i) = path.iter().position(|p| *p == id) {
+                let mut cycle: Vec<ID> = path[i..].iter().map(|id| id.to_string()).collect();
+                cycle.sort();
+                if !cycles.contains(&cycle) {
+                    cycles.push(cycle);
+                }
+                break;
+            }
+            path.push(id);
+            curr = parents.get(id).copied().flatten();
+        }
+    }
+    cycles
+}
+
+// a delta fixing what can be fixed of problems. references to entities that
+// don't exist are removed, or for the primary tag of a task, replaced with
+// another of its tags. a loop is broken by moving its first entity to the top
+// of the tree. returns None if there's nothing to write
+pub fn repair(content: &Content, problems: &[Problem]) -> Option<Content> {
+    let mut tx = Transaction::new(content.clone());
+    let now = Utc::now();
+
+    for problem in problems {
+        let (entity, id, field) = match problem {
+            Problem::DanglingReference { entity, id, field, .. } => (*entity, id, *field),
+            Problem::Cycle { entity, ids } => (*entity, &ids[0], "parent"),
+            _ => continue,
+        };
+        let content = tx.content();
+
+        match entity {
+            Entity::Task => {
+                let old = match content.tasks.iter().find(|t| &t.id == id) {
+                    Some(t) => t.clone(),
+                    None => continue,
+                };
+                let mut task = old.clone();
+                match field {
+                    "parent" => remove_parent(&mut task),
+                    "context" => {
+                        task.context = content.tags_for(&old).iter()
+                            .map(|t| t.id.clone())
+                            .find(|t| Some(t) != old.context.as_ref());
+                    }
+                    _ => {
+                        if let Some(project) = task.project.as_mut() {
+                            project.folder = None;
+                        }
+                    }
+                }
+                task.modified = Some(now);
+                tx.apply(Content::edit_task(&old, task));
+            }
+            Entity::Context => {
+                if let Some(tag) = content.tag(id) {
+                    let mut tag = tag.clone();
+                    tag.parent = None;
+                    tag.modified = Some(now);
+                    tx.apply(Content { tags: vec![tag], ..Content::default() });
+                }
+            }
+            Entity::Folder => {
+                if let Some(folder) = content.folder(id) {
+                    let mut folder = folder.clone();
+                    folder.parent = None;
+                    folder.modified = Some(now);
+                    tx.apply(Content { folders: vec![folder], ..Content::default() });
+                }
+            }
+            // a link to a task or tag that's gone is no use to anything, and
+            // neither is an attachment to a task that's gone
+            Entity::TaskToTag => {
+                if content.task_tags.iter().any(|l| &l.id == id) {
+                    tx.apply(Content::delete(vec![Tombstone { entity, id: id.clone() }]));
+                }
+            }
+            Entity::Attachment => {
+                if content.attachments.iter().any(|a| &a.id == id) {
+                    tx.apply(Content::delete(vec![Tombstone { entity, id: id.clone() }]));
+                }
+            }
+            Entity::Perspective => {}
+        }
+    }
+
+    tx.into_delta()
+}
+
+// moves a task to the top level. a task at the top that isn't a project is in
+// the inbox
+fn remove_parent(task: &mut Task) {
+    task.parent = None;
+    if task.project.is_none() {
+        task.inbox = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tag::{Tag, TaskTag};
+    use crate::task::TaskField;
+    use crate::attachment::Attachment;
+
+    fn task(id: &str, parent: Option<&str>) -> Task {
+        Task { id: id.into(), parent: parent.map(|p| p.into()), ..Task::default() }
+    }
+
+    #[test]
+    fn test_check_and_repair() {
+        let content = Content {
+            tasks: vec![
+                task("a", Some("b")),
+                task("b", Some("a")),
+                task("c", Some("gone")),
+                Task { context: Some("gone tag".into()), ..task("d", None) },
+                task("same", None),
+            ],
+            tags: vec![
+                Tag { id: "tag".into(), ..Tag::default() },
+                Tag { id: "same".into(), ..Tag::default() },
+            ],
+            task_tags: vec![TaskTag::new("d", "tag"), TaskTag::new("missing", "tag")],
+            attachments: vec![
+                Attachment { id: "file".into(), task: Some("d".into()), ..Attachment::default() },
+                Attachment { id: "lost".into(), task: Some("gone".into()), ..Attachment::default() },
+            ],
+            ..Content::default()
+        };
+
+        let problems = check_content(&content);
+        assert!(problems.len() == 6);
+        assert!(problems.contains(&Problem::DuplicateId {
+            id: "same".into(),
+            entities: vec![Entity::Task, Entity::Context],
+        }));
+        assert!(problems.contains(&Problem::DanglingReference {
+            entity: Entity::Task,
+            id: "c".into(),
+            field: "parent",
+            target: "gone".into(),
+        }));
+        assert!(problems.contains(&Problem::Cycle {
+            entity: Entity::Task,
+            ids: vec!["a".into(), "b".into()],
+        }));
+        assert!(problems.contains(&Problem::DanglingReference {
+            entity: Entity::Attachment,
+            id: "lost".into(),
+            field: "task",
+            target: "gone".into(),
+        }));
+        assert!(problems.iter().filter(|p| p.fixable()).count() == 5);
+
+        // the fixed content has nothing left to fix
+        let delta = repair(&content, &problems).unwrap();
+        assert!(delta.tombstones == vec![
+            Tombstone { entity: Entity::TaskToTag, id: "missing.tag".into() },
+            Tombstone { entity: Entity::Attachment, id: "lost".into() },
+        ]);
+        let d = delta.task_updates.iter().find(|u| u.item.id == "d").unwrap();
+        assert!(d.item.context == Some("tag".into()));
+        assert!(d.fields.contains(&TaskField::Context));
+        let mut fixed = content.clone();
+        fixed.update(delta);
+        assert!(fixed.tasks.iter().find(|t| t.id == "c").unwrap().inbox);
+        assert!(check_content(&fixed).iter().all(|p| !p.fixable()));
+    }
+
+    #[test]
+    fn test_duplicates_in_one_archive() {
+        let archive = Content {
+            tasks: vec![task("a", None), task("a", None), task("b", None)],
+            tags: vec![Tag { id: "b".into(), ..Tag::default() }],
+            ..Content::default()
+        };
+        assert!(check_archive(&archive) == vec![
+            Problem::DuplicateId { id: "a".into(), entities: vec![Entity::Task, Entity::Task] },
+            Problem::DuplicateId { id: "b".into(), entities: vec![Entity::Task, Entity::Context] },
+        ]);
+    }
+}
@@ -1,51 +1,226 @@
-// polymorphic error type for convienence
-pub type Error = Box<dyn std::error::Error>;
+use std::path::{Path, PathBuf};
+use xml::common::Position;
 
-// shorthand macro to create an OpenFocusError including line number, file name,
-// and error type
+// the error type used throughout openfocus. along with what went wrong it
+// carries whatever is known about where: the file in the database being read,
+// the position in it, the element and the id of the record. it's boxed so
+// results stay small
+pub struct Error(Box<Details>);
+
+#[derive(Debug)]
+struct Details {
+    kind: OpenFocusErrorType,
+    path: Option<PathBuf>,          // ex: the archive being read
+    position: Option<(u64, u64)>,   // line and column, starting at 1
+    element: Option<String>,        // ex: "omnifocus/task/due"
+    entity: Option<String>,         // the id of the record being read
+    file: &'static str,             // where in openfocus the error was raised
+    line: u32,
+}
+
+// shorthand macro to create an Error including line number, file name, and
+// error type, ex: err!(NotFound) or err!(Missing("id"))
 #[macro_export]
 macro_rules! err {
     ($kind:ident) => {
-        Box::new(OpenFocusError {
-            file: file!(),
-            line: line!(),
-            kind: OpenFocusErrorType::$kind,
-        })
-    }
+        $crate::error::Error::new($crate::error::OpenFocusErrorType::$kind, file!(), line!())
+    };
+    ($kind:ident ( $($arg:expr),* )) => {
+        $crate::error::Error::new(
+            $crate::error::OpenFocusErrorType::$kind($($arg),*),
+            file!(),
+            line!(),
+        )
+    };
 }
 
+// error type specific to the project. the variants holding another error are
+// failures in one of the libraries openfocus uses
 #[derive(Debug)]
-pub struct OpenFocusError {
-    pub file: &'static str,
-    pub line: u32,
-    pub kind: OpenFocusErrorType,
+pub enum OpenFocusErrorType {
+    Parse, // the data isn't laid out the way OmniFocus writes it
+    Missing(&'static str), // a required element or attribute, ex: "id"
+    NotFound,
+    InvalidArgument,
+    Unsynced, // another client hasn't seen changes that would be removed
+    Unknown,
+    Io(std::io::Error),
+    Zip(zip::result::ZipError),
+    Xml(xml::reader::Error),
+    XmlWrite(xml::writer::Error),
+    Date(chrono::ParseError),
+    Bool(std::str::ParseBoolError),
+    Integer(std::num::ParseIntError),
+    Real(std::num::ParseFloatError),
+    Base64(base64::DecodeError),
+    Json(serde_json::Error),
+}
+
+impl Error {
+    pub fn new(kind: OpenFocusErrorType, file: &'static str, line: u32) -> Error {
+        Error(Box::new(Details {
+            kind,
+            path: None,
+            position: None,
+            element: None,
+            entity: None,
+            file,
+            line,
+        }))
+    }
+
+    pub fn kind(&self) -> &OpenFocusErrorType {
+        &self.0.kind
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.0.path.as_deref()
+    }
+
+    pub fn position(&self) -> Option<(u64, u64)> {
+        self.0.position
+    }
+
+    pub fn element(&self) -> Option<&str> {
+        self.0.element.as_deref()
+    }
+
+    pub fn entity(&self) -> Option<&str> {
+        self.0.entity.as_deref()
+    }
+
+    // the methods below add context as an error makes its way out. whatever
+    // was added closest to where it happened is kept
+
+    pub fn in_file(mut self, path: &Path) -> Error {
+        self.0.path.get_or_insert_with(|| path.to_path_buf());
+        self
+    }
+
+    pub fn at(mut self, line: u64, column: u64) -> Error {
+        self.0.position.get_or_insert((line, column));
+        self
+    }
+
+    pub fn in_element(mut self, element: &str) -> Error {
+        self.0.element.get_or_insert_with(|| element.to_string());
+        self
+    }
+
+    pub fn for_entity(mut self, id: &str) -> Error {
+        self.0.entity.get_or_insert_with(|| id.to_string());
+        self
+    }
 }
 
-impl std::error::Error for OpenFocusError {}
-impl std::fmt::Display for OpenFocusError {
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.0.kind {
+            OpenFocusErrorType::Io(e) => Some(e),
+            OpenFocusErrorType::Zip(e) => Some(e),
+            OpenFocusErrorType::Xml(e) => Some(e),
+            OpenFocusErrorType::XmlWrite(e) => Some(e),
+            OpenFocusErrorType::Date(e) => Some(e),
+            OpenFocusErrorType::Bool(e) => Some(e),
+            OpenFocusErrorType::Integer(e) => Some(e),
+            OpenFocusErrorType::Real(e) => Some(e),
+            OpenFocusErrorType::Base64(e) => Some(e),
+            OpenFocusErrorType::Json(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+// ex: Missing "added" in omnifocus/task (id eNJKbQtViaH) at line 1, column 530
+// of example.ofocus/00000000000000=h4xQU5Ux7GB+chN6WaF70I_.zip
+impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{} at {}:{}", self.kind, self.file, self.line)
+        write!(f, "{}", self.0.kind)?;
+        if let Some(element) = self.0.element.as_ref() {
+            write!(f, " in {}", element)?;
+        }
+        if let Some(entity) = self.0.entity.as_ref() {
+            write!(f, " (id {})", entity)?;
+        }
+        if let Some((line, column)) = self.0.position {
+            write!(f, " at line {}, column {}", line, column)?;
+        }
+        if let Some(path) = self.0.path.as_ref() {
+            write!(f, " of {}", path.display())?;
+        }
+        Ok(())
     }
 }
 
-// error type specific to the project
-#[derive(Debug)]
-pub enum OpenFocusErrorType {
-    Parse,
-    NotFound,
-    InvalidArgument,
-    Unknown,
+// returning an error from main prints it with Debug so this is kept readable
+impl std::fmt::Debug for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} [{}:{}]", self, self.0.file, self.0.line)
+    }
 }
 
 impl std::error::Error for OpenFocusErrorType {}
 impl std::fmt::Display for OpenFocusErrorType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let s = match self {
-            OpenFocusErrorType::Parse => "Parse Error",
-            OpenFocusErrorType::NotFound => "Item Not Found Error",
-            OpenFocusErrorType::InvalidArgument => "Invalid Argument",
-            OpenFocusErrorType::Unknown => "Unknown Error",
-        };
-        write!(f, "{}", s)
+        match self {
+            OpenFocusErrorType::Parse => write!(f, "Parse Error"),
+            OpenFocusErrorType::Missing(what) => write!(f, "Missing \"{}\"", what),
+            OpenFocusErrorType::NotFound => write!(f, "Item Not Found Error"),
+            OpenFocusErrorType::InvalidArgument => write!(f, "Invalid Argument"),
+            OpenFocusErrorType::Unsynced => write!(f, "Unsynced Client Error"),
+            OpenFocusErrorType::Unknown => write!(f, "Unknown Error"),
+            OpenFocusErrorType::Io(e) => write!(f, "IO Error: {}", e),
+            OpenFocusErrorType::Zip(e) => write!(f, "Zip Error: {}", e),
+            OpenFocusErrorType::Xml(e) => write!(f, "XML Error: {}", e.msg()),
+            OpenFocusErrorType::XmlWrite(e) => write!(f, "XML Error: {}", e),
+            OpenFocusErrorType::Date(e) => write!(f, "Date Error: {}", e),
+            OpenFocusErrorType::Bool(e) => write!(f, "Boolean Error: {}", e),
+            OpenFocusErrorType::Integer(e) => write!(f, "Integer Error: {}", e),
+            OpenFocusErrorType::Real(e) => write!(f, "Real Error: {}", e),
+            OpenFocusErrorType::Base64(e) => write!(f, "Base64 Error: {}", e),
+            OpenFocusErrorType::Json(e) => write!(f, "JSON Error: {}", e),
+        }
+    }
+}
+
+// conversions so ? works on the errors of the libraries openfocus uses. the
+// location is wherever the conversion happened
+macro_rules! from_error {
+    ($from:ty, $kind:ident) => {
+        impl From<$from> for Error {
+            #[track_caller]
+            fn from(e: $from) -> Error {
+                let caller = std::panic::Location::caller();
+                Error::new(OpenFocusErrorType::$kind(e), caller.file(), caller.line())
+            }
+        }
+    };
+}
+
+from_error!(std::io::Error, Io);
+from_error!(zip::result::ZipError, Zip);
+from_error!(xml::writer::Error, XmlWrite);
+from_error!(chrono::ParseError, Date);
+from_error!(std::str::ParseBoolError, Bool);
+from_error!(std::num::ParseIntError, Integer);
+from_error!(std::num::ParseFloatError, Real);
+from_error!(base64::DecodeError, Base64);
+from_error!(serde_json::Error, Json);
+
+// parsing a String can't fail, this lets it be parsed the same as anything else
+impl From<std::convert::Infallible> for Error {
+    fn from(e: std::convert::Infallible) -> Error {
+        match e {}
+    }
+}
+
+// xml errors already know where in the document they happened
+impl From<xml::reader::Error> for Error {
+    #[track_caller]
+    fn from(e: xml::reader::Error) -> Error {
+        let caller = std::panic::Location::caller();
+        let position = e.position();
+        Error::new(OpenFocusErrorType::Xml(e), caller.file(), caller.line())
+            .at(position.row + 1, position.column + 1)
     }
 }
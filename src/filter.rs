@@ -8,7 +8,7 @@ pub struct Filter {
     inbox: Toggle<bool>,
     flagged: Toggle<bool>,
     completed: Toggle<bool>,
-    has_project: Toggle<bool>,
+    is_project: Toggle<bool>,
     has_due_date: Toggle<bool>,
 }
 
@@ -19,7 +19,7 @@ impl Filter {
             inbox: None,
             flagged: None,
             completed: None,
-            has_project: None,
+            is_project: None,
             has_due_date: None,
         }
     }
@@ -30,7 +30,7 @@ impl Filter {
             inbox: None,
             flagged: None,
             completed: Some(false),
-            has_project: None,
+            is_project: None,
             has_due_date: None,
         }
     }
@@ -41,7 +41,7 @@ impl Filter {
             inbox: None,
             flagged: None,
             completed: Some(true),
-            has_project: None,
+            is_project: None,
             has_due_date: None,
         }
     }
@@ -60,10 +60,10 @@ impl Filter {
         f
     }
 
-    // a filter that shows items assigned to projects
+    // a filter that shows projects
     pub fn new_projects() -> Filter {
         let mut f = Filter::new_empty();
-        f.has_project = Some(true);
+        f.is_project = Some(true);
         f
     }
 
@@ -98,7 +98,7 @@ impl<'a, I> Iterator for FilterIter<'a, I> where I: Iterator<Item=&'a Task> {
     fn next(&mut self) -> Option<Self::Item> {
         // loop through tasks looking for the next one that satisfies all the
         // conditions
-        while let Some(task) = self.tasks.next() {
+        for task in self.tasks.by_ref() {
             // check if the item is in the inbox
             if let Some(inbox) = self.filter.inbox {
                 if task.inbox != inbox {
@@ -120,9 +120,9 @@ impl<'a, I> Iterator for FilterIter<'a, I> where I: Iterator<Item=&'a Task> {
                 }
             }
 
-            // check if the item is part of a project
-            if let Some(has_project) = self.filter.has_project {
-                if task.parent.is_some() != has_project {
+            // check if the item is a project
+            if let Some(is_project) = self.filter.is_project {
+                if task.project.is_some() != is_project {
                     continue;
                 }
             }
@@ -145,10 +145,11 @@ impl<'a, I> Iterator for FilterIter<'a, I> where I: Iterator<Item=&'a Task> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::project::ProjectInfo;
 
     #[test]
     fn test_filter_inbox() {
-        let mut tasks = vec![
+        let mut tasks = [
             Task::default(),
             Task::default(),
             Task::default(),
@@ -171,7 +172,7 @@ mod tests {
 
     #[test]
     fn test_filter_projects() {
-        let mut tasks = vec![
+        let mut tasks = [
             Task::default(),
             Task::default(),
             Task::default(),
@@ -181,8 +182,9 @@ mod tests {
         tasks[1].id = "bar".into();
         tasks[2].id = "baz".into();
 
-        tasks[0].parent = Some("someproj".into());
-        tasks[2].parent = Some("someproj".into());
+        tasks[0].project = Some(ProjectInfo::default());
+        tasks[1].parent = Some("foo".into());
+        tasks[2].project = Some(ProjectInfo::default());
 
         let iter = Filter::new_projects().into_iter(tasks.iter());
         let filtered: Vec<&Task> = iter.collect();
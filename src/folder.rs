@@ -0,0 +1,236 @@
+use chrono::prelude::*;
+
+use crate::util::{ID, generate_id};
+use crate::parse::Content;
+use crate::project::Project;
+use crate::note::Note;
+use crate::unknown::Unknown;
+
+// a struct to represent a folder of projects and other folders
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Folder {
+    // metadata
+    pub id: ID,
+    pub parent: Option<ID>,
+    pub rank: Option<i64>,
+    pub added: DateTime<Utc>,
+    pub modified: Option<DateTime<Utc>>,
+    // attributes
+    pub name: String,
+    pub note: Option<Note>,
+    pub hidden: Option<DateTime<Utc>>, // the date the folder was dropped
+    // everything else in the record, written back out as it was read
+    pub unknown: Unknown,
+}
+
+// self explanatory
+impl Default for Folder {
+    fn default() -> Self {
+        Folder {
+            id: generate_id(),
+            parent: None,
+            rank: None,
+            added: Utc::now(),
+            modified: Some(Utc::now()),
+            name: "".into(),
+            note: None,
+            hidden: None,
+            unknown: Unknown::default(),
+        }
+    }
+}
+
+// enumeration of the fields of a Folder as they appear in a <folder>. see
+// TaskField for why this exists
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum FolderField {
+    Parent,
+    Rank,
+    Added,
+    Modified,
+    Name,
+    Note,
+    Hidden,
+}
+
+impl Folder {
+    pub fn dropped(&self) -> bool {
+        self.hidden.is_some()
+    }
+
+    // copies only the given fields from other onto self
+    pub fn merge(&mut self, other: &Folder, fields: &[FolderField]) {
+        for field in fields {
+            match field {
+                FolderField::Parent => self.parent = other.parent.clone(),
+                FolderField::Rank => self.rank = other.rank,
+                FolderField::Added => self.added = other.added,
+                FolderField::Modified => self.modified = other.modified,
+                FolderField::Name => self.name = other.name.clone(),
+                FolderField::Note => self.note = other.note.clone(),
+                FolderField::Hidden => self.hidden = other.hidden,
+            }
+        }
+    }
+}
+
+// an entry in the sidebar. folders and projects are listed together, ordered
+// by rank
+#[derive(Debug, Clone, Copy)]
+pub enum SidebarItem<'a> {
+    Folder(&'a Folder),
+    Project(Project<'a>),
+}
+
+// queries over the folder tree
+impl Content {
+    // finds a folder by id
+    pub fn folder(&self, id: &str) -> Option<&Folder> {
+        self.folders.iter().find(|f| f.id == id)
+    }
+
+    // the direct children of a folder, or the top level folders if id is None,
+    // sorted by rank
+    pub fn folder_children(&self, id: Option<&str>) -> Vec<&Folder> {
+        let mut children: Vec<&Folder> = self.folders.iter()
+            .filter(|f| f.parent.as_deref() == id)
+            .collect();
+        children.sort_by_key(|f| f.rank);
+        children
+    }
+
+    // the projects directly inside of a folder, or the projects that aren't in
+    // any folder if id is None, sorted by rank
+    pub fn folder_projects(&self, id: Option<&str>) -> Vec<Project<'_>> {
+        self.projects().into_iter()
+            .filter(|p| p.folder().map(|f| f.as_str()) == id)
+            .collect()
+    }
+
+    // all of the projects inside of a folder and its subfolders, depth first
+    pub fn folder_projects_recursive(&self, id: &str) -> Vec<Project<'_>> {
+        let mut projects = Vec::new();
+        let mut visited: Vec<ID> = Vec::new();
+        self.collect_folder_projects(id, &mut visited, &mut projects);
+        projects
+    }
+
+    fn collect_folder_projects<'a>(
+        &'a self,
+        id: &str,
+        visited: &mut Vec<ID>,
+        projects: &mut Vec<Project<'a>>,
+    ) {
+        // guard against cycles in a corrupt tree
+        if visited.iter().any(|v| v == id) {
+            return;
+        }
+        visited.push(id.into());
+
+        projects.extend(self.folder_projects(Some(id)));
+        for child in self.folder_children(Some(id)) {
+            self.collect_folder_projects(&child.id, visited, projects);
+        }
+    }
+
+    // the folders and projects inside of a folder (or at the top level if id
+    // is None) in the order the sidebar shows them
+    pub fn sidebar(&self, id: Option<&str>) -> Vec<SidebarItem<'_>> {
+        let mut items: Vec<SidebarItem> = self.folder_children(id).into_iter()
+            .map(SidebarItem::Folder)
+            .chain(self.folder_projects(id).into_iter().map(SidebarItem::Project))
+            .collect();
+        items.sort_by_key(|item| match item {
+            SidebarItem::Folder(f) => f.rank,
+            SidebarItem::Project(p) => p.task.rank,
+        });
+        items
+    }
+
+    // reorders self.folders so parents come before their children and
+    // siblings are sorted by rank. folders whose parent is missing (or that
+    // are part of a cycle) are kept at the end in their original order
+    pub fn sort_folders(&mut self) {
+        fn visit(content: &Content, id: Option<&str>, order: &mut Vec<ID>) {
+            for child in content.folder_children(id) {
+                if !order.contains(&child.id) {
+                    order.push(child.id.clone());
+                    visit(content, Some(&child.id), order);
+                }
+            }
+        }
+
+        let mut order: Vec<ID> = Vec::new();
+        visit(self, None, &mut order);
+
+        let mut sorted: Vec<Folder> = Vec::with_capacity(self.folders.len());
+        for id in order.iter() {
+            if let Some(i) = self.folders.iter().position(|f| &f.id == id) {
+                sorted.push(self.folders.remove(i));
+            }
+        }
+        sorted.append(&mut self.folders);
+        self.folders = sorted;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task::Task;
+    use crate::project::ProjectInfo;
+
+    fn folder(id: &str, parent: Option<&str>, rank: i64) -> Folder {
+        Folder {
+            id: id.into(),
+            parent: parent.map(|p| p.into()),
+            rank: Some(rank),
+            ..Folder::default()
+        }
+    }
+
+    fn project(id: &str, folder: Option<&str>, rank: i64) -> Task {
+        Task {
+            id: id.into(),
+            rank: Some(rank),
+            project: Some(ProjectInfo {
+                folder: folder.map(|f| f.into()),
+                ..ProjectInfo::default()
+            }),
+            ..Task::default()
+        }
+    }
+
+    #[test]
+    fn test_folder_tree() {
+        let mut content = Content {
+            folders: vec![
+                folder("child", Some("root"), 0),
+                folder("root", None, 1),
+                folder("other", None, 0),
+            ],
+            tasks: vec![
+                project("a", Some("root"), 5),
+                project("b", Some("child"), 0),
+                project("c", None, 2),
+            ],
+            ..Content::default()
+        };
+
+        content.sort_folders();
+        let ids: Vec<&str> = content.folders.iter().map(|f| f.id.as_str()).collect();
+        assert!(ids == vec!["other", "root", "child"]);
+
+        let projects: Vec<&str> = content.folder_projects_recursive("root").iter()
+            .map(|p| p.task.id.as_str())
+            .collect();
+        assert!(projects == vec!["a", "b"]);
+
+        // other (0), root (1), c (2)
+        assert!(content.sidebar(None).len() == 3);
+        match content.sidebar(None)[2] {
+            SidebarItem::Project(p) => assert!(p.task.id == "c"),
+            _ => panic!("expected a project"),
+        }
+    }
+}
@@ -1,9 +1,20 @@
 pub mod parse; // TODO privatize
-mod plist;
+pub mod plist;
 
 pub mod util;
 pub mod db;
 pub mod task;
+pub mod tag;
+pub mod project;
+pub mod folder;
+pub mod note;
+pub mod repeat;
+pub mod attachment;
 pub mod filter;
+pub mod rule;
 pub mod error;
 pub mod perspective;
+pub mod unknown;
+pub mod client;
+pub mod transaction;
+pub mod doctor;
@@ -0,0 +1,362 @@
+use std::fmt;
+use std::io::Write;
+use colored::*;
+use xml::reader::XmlEvent;
+use xml::writer::{EventWriter, XmlEvent as WriterEvent};
+
+use crate::error::*;
+use crate::parse::{Reader, name_to_str};
+
+// helpers to handle OmniFocus's rich text notes. they look like
+// <text><p><run><style>...</style><lit>...</lit></run></p></text>
+
+// a rich text note made up of paragraphs
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Note {
+    pub paragraphs: Vec<Paragraph>,
+}
+
+// a single paragraph (<p>) made up of runs of identically styled text
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Paragraph {
+    pub runs: Vec<Run>,
+}
+
+// a run (<run>) of text that all has the same style
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Run {
+    pub style: Style,
+    pub fragments: Vec<Fragment>,
+}
+
+// the contents of a <lit>. mostly text but attachments are inlined as <cell>s
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Fragment {
+    Text(String),
+    Attachment { id: String, name: Option<String> },
+}
+
+// the <value key="...">...</value> pairs of a <style>, in file order
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Style {
+    pub values: Vec<(String, String)>,
+}
+
+impl Style {
+    // gets the value for a given key, ex: "font-family"
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.values.iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    // the url this run links to, if any
+    pub fn link(&self) -> Option<&str> {
+        self.get("link")
+    }
+
+    // font weights go from 0 to 15. regular is 5 and bold is 9
+    pub fn is_bold(&self) -> bool {
+        self.get("font-weight")
+            .and_then(|w| w.parse::<u32>().ok())
+            .is_some_and(|w| w >= 7)
+    }
+
+    pub fn is_italic(&self) -> bool {
+        self.get("font-italic")
+            .is_some_and(|i| i.eq_ignore_ascii_case("yes") || i == "1")
+    }
+}
+
+impl Run {
+    // the run's contents as plain text. attachments are shown as [name]
+    pub fn text(&self) -> String {
+        self.fragments.iter()
+            .map(|f| match f {
+                Fragment::Text(text) => text.clone(),
+                Fragment::Attachment { name, .. } => {
+                    format!("[{}]", name.as_deref().unwrap_or("attachment"))
+                }
+            })
+            .collect()
+    }
+}
+
+impl Note {
+    // creates an unstyled note with one paragraph per line of text
+    pub fn from_plain(text: &str) -> Note {
+        Note {
+            paragraphs: text.lines()
+                .map(|line| Paragraph {
+                    runs: if line.is_empty() {
+                        vec![]
+                    } else {
+                        vec![Run {
+                            style: Style::default(),
+                            fragments: vec![Fragment::Text(line.into())],
+                        }]
+                    },
+                })
+                .collect(),
+        }
+    }
+
+    // renders each paragraph with the given function and joins them with
+    // newlines
+    fn render(&self, run: impl Fn(&Run) -> String) -> String {
+        self.paragraphs.iter()
+            .map(|p| p.runs.iter().map(&run).collect::<String>())
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    // the note with all styling removed
+    pub fn to_plain(&self) -> String {
+        self.render(|run| run.text())
+    }
+
+    // the note as Markdown. bold, italic and links are kept
+    pub fn to_markdown(&self) -> String {
+        self.render(|run| {
+            let text = run.text();
+            // markdown emphasis can't start or end with whitespace so
+            // leave that outside of the markers
+            let trimmed = text.trim();
+            if trimmed.is_empty() {
+                return text;
+            }
+            let mut out = trimmed.to_string();
+            if run.style.is_italic() {
+                out = format!("*{}*", out);
+            }
+            if run.style.is_bold() {
+                out = format!("**{}**", out);
+            }
+            if let Some(link) = run.style.link() {
+                out = format!("[{}]({})", out, link);
+            }
+            let start = text.len() - text.trim_start().len();
+            let end = text.trim_end().len();
+            format!("{}{}{}", &text[..start], out, &text[end..])
+        })
+    }
+
+    // the note styled for a terminal
+    pub fn to_ansi(&self) -> String {
+        self.render(|run| {
+            let mut out = run.text().normal();
+            if run.style.is_bold() {
+                out = out.bold();
+            }
+            if run.style.is_italic() {
+                out = out.italic();
+            }
+            if run.style.link().is_some() {
+                out = out.underline().blue();
+            }
+            out.to_string()
+        })
+    }
+}
+
+// a Note is displayed as plain text
+impl fmt::Display for Note {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_plain())
+    }
+}
+
+// parses the inside of a <note> up to and including the </note>. an empty
+// <note/> gives None
+pub fn parse_note<'a>(
+    parser: &mut Reader<zip::read::ZipFile<'a>>,
+) -> Result<Option<Note>, Error> {
+    let mut note = Note::default();
+    let mut in_lit = false;
+    let mut value_key: Option<String> = None;
+
+    let mut depth = 1;
+    for evt in parser {
+        match evt {
+            Ok(XmlEvent::StartElement { name, attributes, .. }) => {
+                depth += 1;
+                let attr = |key: &str| {
+                    attributes.iter()
+                        .find(|a| name_to_str(&a.name) == key)
+                        .map(|a| a.value.clone())
+                };
+                match name_to_str(&name) {
+                    "p" => note.paragraphs.push(Paragraph::default()),
+                    "run" => {
+                        if note.paragraphs.is_empty() {
+                            note.paragraphs.push(Paragraph::default());
+                        }
+                        note.paragraphs.last_mut().unwrap().runs.push(Run::default());
+                    }
+                    "value" => value_key = attr("key"),
+                    "lit" => in_lit = true,
+                    // an attachment placed inline in the text
+                    "cell" if in_lit => {
+                        if let Some(run) = current_run(&mut note) {
+                            run.fragments.push(Fragment::Attachment {
+                                id: attr("refid").unwrap_or_default(),
+                                name: attr("name"),
+                            });
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Ok(XmlEvent::EndElement { name }) => {
+                depth -= 1;
+                if depth == 0 {
+                    break;
+                }
+                match name_to_str(&name) {
+                    "lit" => in_lit = false,
+                    "value" => value_key = None,
+                    _ => {}
+                }
+            }
+            // whitespace inside of a <lit> is part of the text so it's
+            // treated the same as any other characters
+            Ok(XmlEvent::Characters(text))
+            | Ok(XmlEvent::Whitespace(text))
+            | Ok(XmlEvent::CData(text)) => {
+                if let Some(run) = current_run(&mut note) {
+                    if let Some(key) = value_key.as_ref() {
+                        run.style.values.push((key.clone(), text));
+                    } else if in_lit {
+                        run.fragments.push(Fragment::Text(text));
+                    }
+                }
+            }
+            Err(e) => return Err(e.into()),
+            _ => {}
+        }
+    }
+
+    // OmniFocus pretty prints <cell>s onto their own line. that whitespace
+    // isn't part of the note
+    for p in note.paragraphs.iter_mut() {
+        for run in p.runs.iter_mut() {
+            let has_cell = run.fragments.iter()
+                .any(|f| matches!(f, Fragment::Attachment { .. }));
+            if has_cell {
+                run.fragments.retain(|f| match f {
+                    Fragment::Text(text) => !text.trim().is_empty(),
+                    _ => true,
+                });
+            }
+        }
+    }
+
+    if note.paragraphs.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(note))
+    }
+}
+
+// the run text is currently being added to
+fn current_run(note: &mut Note) -> Option<&mut Run> {
+    note.paragraphs.last_mut().and_then(|p| p.runs.last_mut())
+}
+
+// writes a note out as <note><text>...</text></note>
+pub fn write_note<W: Write>(
+    xml: &mut EventWriter<W>,
+    note: &Note,
+) -> Result<(), Error> {
+    xml.write(WriterEvent::start_element("note"))?;
+    xml.write(WriterEvent::start_element("text"))?;
+
+    for p in note.paragraphs.iter() {
+        xml.write(WriterEvent::start_element("p"))?;
+        for run in p.runs.iter() {
+            xml.write(WriterEvent::start_element("run"))?;
+
+            // <style><value key="...">...</value></style>
+            if !run.style.values.is_empty() {
+                xml.write(WriterEvent::start_element("style"))?;
+                for (key, value) in run.style.values.iter() {
+                    xml.write(WriterEvent::start_element("value").attr("key", key))?;
+                    xml.write(WriterEvent::characters(value))?;
+                    xml.write(WriterEvent::end_element())?;
+                }
+                xml.write(WriterEvent::end_element())?;
+            }
+
+            // <lit>...</lit>
+            xml.write(WriterEvent::start_element("lit"))?;
+            for fragment in run.fragments.iter() {
+                match fragment {
+                    Fragment::Text(text) => {
+                        xml.write(WriterEvent::characters(text))?;
+                    }
+                    Fragment::Attachment { id, name } => {
+                        let mut cell = WriterEvent::start_element("cell")
+                            .attr("refid", id);
+                        if let Some(name) = name {
+                            cell = cell.attr("name", name);
+                        }
+                        xml.write(cell)?;
+                        xml.write(WriterEvent::end_element())?;
+                    }
+                }
+            }
+            xml.write(WriterEvent::end_element())?;
+
+            xml.write(WriterEvent::end_element())?; // </run>
+        }
+        xml.write(WriterEvent::end_element())?; // </p>
+    }
+
+    xml.write(WriterEvent::end_element())?; // </text>
+    xml.write(WriterEvent::end_element())?; // </note>
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn styled(text: &str, values: &[(&str, &str)]) -> Run {
+        Run {
+            style: Style {
+                values: values.iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect(),
+            },
+            fragments: vec![Fragment::Text(text.into())],
+        }
+    }
+
+    #[test]
+    fn test_render_markdown() {
+        let note = Note {
+            paragraphs: vec![
+                Paragraph {
+                    runs: vec![
+                        styled("Use the ", &[]),
+                        styled("Forecast", &[("link", "omnifocus:///forecast")]),
+                        styled(" now", &[]),
+                    ],
+                },
+                Paragraph { runs: vec![] },
+                Paragraph { runs: vec![styled("Help ", &[("font-weight", "9")])] },
+            ],
+        };
+
+        assert!(note.to_plain() == "Use the Forecast now\n\nHelp ");
+        assert!(note.to_markdown()
+            == "Use the [Forecast](omnifocus:///forecast) now\n\n**Help** ");
+    }
+
+    #[test]
+    fn test_from_plain() {
+        let note = Note::from_plain("one\n\ntwo");
+        assert!(note.paragraphs.len() == 3);
+        assert!(note.to_plain() == "one\n\ntwo");
+    }
+}
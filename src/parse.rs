@@ -1,103 +1,633 @@
 use std::fs::File;
+use std::collections::BTreeMap;
 use zip::read::ZipArchive;
+use std::io::Read;
+use xml::common::Position;
 use xml::reader::{EventReader, XmlEvent};
 use xml::attribute::OwnedAttribute;
 use chrono::prelude::*;
 
 use crate::error::*;
 use crate::util::ID;
-use crate::task::{Task, SubtaskOrder};
-use crate::perspective::{Perspective};
-use crate::plist;
+use crate::task::{Task, TaskField, SubtaskOrder};
+use crate::project::ProjectInfo;
+use crate::folder::{Folder, FolderField};
+use crate::tag::{Tag, TagField, TaskTag, TaskTagField, Location};
+use crate::attachment::{Attachment, AttachmentField};
+use crate::perspective::{Perspective, PerspectiveField};
+use crate::plist::{self, PlistItem};
+use crate::note::{Note, parse_note};
+use crate::repeat::{RepetitionRule, RepetitionMethod};
+use crate::unknown::{self, Unknown, RawElement, read_element};
 
-#[derive(Debug)]
+#[derive(Debug, Default, Clone)]
 pub struct Content {
     pub tasks: Vec<Task>,
+    pub task_updates: Vec<TaskUpdate>,
+    pub tags: Vec<Tag>,
+    pub tag_updates: Vec<TagUpdate>,
+    pub task_tags: Vec<TaskTag>,
+    pub task_tag_updates: Vec<TaskTagUpdate>,
+    pub folders: Vec<Folder>,
+    pub folder_updates: Vec<FolderUpdate>,
+    pub attachments: Vec<Attachment>,
+    pub attachment_updates: Vec<AttachmentUpdate>,
     pub perspectives: Vec<Perspective>,
+    pub perspective_updates: Vec<PerspectiveUpdate>,
+    pub tombstones: Vec<Tombstone>,
+    // records of kinds we don't know about, ex: <setting>
+    pub unknown: Vec<RawElement>,
+}
+
+// a partial op="update" record. only the fields listed in `fields` were
+// present in the XML, the rest of `item` is filler and must be ignored
+#[derive(Debug, Clone)]
+pub struct Update<T, F> {
+    pub item: T,
+    pub fields: Vec<F>,
+}
+
+pub type TaskUpdate = Update<Task, TaskField>;
+pub type TagUpdate = Update<Tag, TagField>;
+pub type TaskTagUpdate = Update<TaskTag, TaskTagField>;
+pub type FolderUpdate = Update<Folder, FolderField>;
+pub type AttachmentUpdate = Update<Attachment, AttachmentField>;
+pub type PerspectiveUpdate = Update<Perspective, PerspectiveField>;
+
+impl TaskUpdate {
+    // an update with only the fields that are different between old and new
+    pub fn diff(old: &Task, new: Task) -> TaskUpdate {
+        let fields = old.changed_fields(&new);
+        Update { item: new, fields }
+    }
+}
+
+// the operation a record in an archive performs on its entity. records without
+// an op="..." attribute are full inserts (or replacements)
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Op {
+    Insert,
+    Update,
+    Delete,
+}
+
+// the kinds of top level records found in an archive
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Entity {
+    Task,
+    Context,
+    Folder,
+    TaskToTag,
+    Attachment,
+    Perspective,
+}
+
+impl Entity {
+    // the name of the XML element used for this kind of record
+    pub fn element_name(self) -> &'static str {
+        match self {
+            Entity::Task => "task",
+            Entity::Context => "context",
+            Entity::Folder => "folder",
+            Entity::TaskToTag => "task-to-tag",
+            Entity::Attachment => "attachment",
+            Entity::Perspective => "perspective",
+        }
+    }
+
+    fn from_element_name(name: &str) -> Option<Entity> {
+        match name {
+            "task" => Some(Entity::Task),
+            "context" => Some(Entity::Context),
+            "folder" => Some(Entity::Folder),
+            "task-to-tag" => Some(Entity::TaskToTag),
+            "attachment" => Some(Entity::Attachment),
+            "perspective" => Some(Entity::Perspective),
+            _ => None,
+        }
+    }
+}
+
+// an op="delete" record. the entity with this id no longer exists
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Tombstone {
+    pub entity: Entity,
+    pub id: ID,
 }
 
 impl Content {
-    pub fn new_task(task: Task) -> Content{
-        Content { tasks: vec![task], perspectives: vec![] }
+    pub fn new_task(task: Task) -> Content {
+        Content { tasks: vec![task], ..Content::default() }
+    }
+
+    // a delta for an edited task. only the fields that changed are written
+    // so the rest can't overwrite edits made elsewhere
+    pub fn edit_task(old: &Task, new: Task) -> Content {
+        Content { task_updates: vec![TaskUpdate::diff(old, new)], ..Content::default() }
+    }
+
+    pub fn new_perspective(perspective: Perspective) -> Content {
+        Content { perspectives: vec![perspective], ..Content::default() }
+    }
+
+    pub fn delete(tombstones: Vec<Tombstone>) -> Content {
+        Content { tombstones, ..Content::default() }
+    }
+
+    // the ids of every entity this delta adds, changes or deletes
+    pub fn ids(&self) -> Vec<ID> {
+        let mut ids: Vec<ID> = Vec::new();
+        ids.extend(self.tasks.iter().map(|t| t.id.clone()));
+        ids.extend(self.task_updates.iter().map(|u| u.item.id.clone()));
+        ids.extend(self.tags.iter().map(|t| t.id.clone()));
+        ids.extend(self.tag_updates.iter().map(|u| u.item.id.clone()));
+        ids.extend(self.task_tags.iter().map(|l| l.id.clone()));
+        ids.extend(self.task_tag_updates.iter().map(|u| u.item.id.clone()));
+        ids.extend(self.folders.iter().map(|f| f.id.clone()));
+        ids.extend(self.folder_updates.iter().map(|u| u.item.id.clone()));
+        ids.extend(self.attachments.iter().map(|a| a.id.clone()));
+        ids.extend(self.attachment_updates.iter().map(|u| u.item.id.clone()));
+        ids.extend(self.perspectives.iter().map(|p| p.id.clone()));
+        ids.extend(self.perspective_updates.iter().map(|u| u.item.id.clone()));
+        ids.extend(self.tombstones.iter().map(|t| t.id.clone()));
+        ids.extend(self.unknown.iter().filter_map(|r| r.attr("id").map(|s| s.to_string())));
+        ids
     }
 
     pub fn update(&mut self, delta: Content) {
-        // TODO is task-level merge rather than replace needed?
+        // full records replace the existing task entirely
         for task in delta.tasks {
-            let mut replaced = false;
-            for t in self.tasks.iter_mut() {
-                if &t.id == &task.id {
-                    *t = task.clone();
-                    replaced = true;
-                    break;
+            match self.tasks.iter_mut().find(|t| t.id == task.id) {
+                Some(t) => *t = task,
+                None => self.tasks.push(task),
+            }
+        }
+
+        // partial records only overwrite the fields they contain. an update
+        // for a task we have never seen has nothing to apply to so it's dropped
+        for update in delta.task_updates {
+            if let Some(t) = self.tasks.iter_mut().find(|t| t.id == update.item.id) {
+                t.merge(&update.item, &update.fields);
+                t.unknown.merge(&update.item.unknown);
+            }
+        }
+
+        // same as above for tags
+        for tag in delta.tags {
+            match self.tags.iter_mut().find(|t| t.id == tag.id) {
+                Some(t) => *t = tag,
+                None => self.tags.push(tag),
+            }
+        }
+        for update in delta.tag_updates {
+            if let Some(t) = self.tags.iter_mut().find(|t| t.id == update.item.id) {
+                t.merge(&update.item, &update.fields);
+                t.unknown.merge(&update.item.unknown);
+            }
+        }
+
+        // same as above for task-to-tag links
+        for link in delta.task_tags {
+            match self.task_tags.iter_mut().find(|l| l.id == link.id) {
+                Some(l) => *l = link,
+                None => self.task_tags.push(link),
+            }
+        }
+        for update in delta.task_tag_updates {
+            if let Some(l) = self.task_tags.iter_mut().find(|l| l.id == update.item.id) {
+                l.merge(&update.item, &update.fields);
+                l.unknown.merge(&update.item.unknown);
+            }
+        }
+
+        // same as above for folders
+        for folder in delta.folders {
+            match self.folders.iter_mut().find(|f| f.id == folder.id) {
+                Some(f) => *f = folder,
+                None => self.folders.push(folder),
+            }
+        }
+        for update in delta.folder_updates {
+            if let Some(f) = self.folders.iter_mut().find(|f| f.id == update.item.id) {
+                f.merge(&update.item, &update.fields);
+                f.unknown.merge(&update.item.unknown);
+            }
+        }
+
+        // same as above for attachments
+        for attachment in delta.attachments {
+            match self.attachments.iter_mut().find(|a| a.id == attachment.id) {
+                Some(a) => *a = attachment,
+                None => self.attachments.push(attachment),
+            }
+        }
+        for update in delta.attachment_updates {
+            if let Some(a) = self.attachments.iter_mut().find(|a| a.id == update.item.id) {
+                a.merge(&update.item, &update.fields);
+                a.unknown.merge(&update.item.unknown);
+            }
+        }
+
+        // same as above for perspectives
+        for perspective in delta.perspectives {
+            match self.perspectives.iter_mut().find(|p| p.id == perspective.id) {
+                Some(p) => *p = perspective,
+                None => self.perspectives.push(perspective),
+            }
+        }
+        for update in delta.perspective_updates {
+            if let Some(p) = self.perspectives.iter_mut().find(|p| p.id == update.item.id) {
+                p.merge(&update.item, &update.fields);
+                p.unknown.merge(&update.item.unknown);
+            }
+        }
+
+        // unknown records are matched up by their kind and id
+        for record in delta.unknown {
+            unknown::apply_record(&mut self.unknown, record);
+        }
+
+        // tombstones remove the entity entirely
+        for tombstone in delta.tombstones {
+            let id = &tombstone.id;
+            match tombstone.entity {
+                Entity::Task => self.tasks.retain(|t| &t.id != id),
+                Entity::Context => self.tags.retain(|t| &t.id != id),
+                Entity::TaskToTag => self.task_tags.retain(|l| &l.id != id),
+                Entity::Folder => self.folders.retain(|f| &f.id != id),
+                Entity::Attachment => self.attachments.retain(|a| &a.id != id),
+                Entity::Perspective => self.perspectives.retain(|p| &p.id != id),
+            }
+        }
+    }
+}
+
+impl Content {
+    // folds another delta into this one so both can be written as a single
+    // archive. each entity ends up with at most one record: an update to
+    // something this delta already has a record for is merged into it and a
+    // full record or tombstone replaces anything earlier
+    pub fn append(&mut self, delta: Content) {
+        append_records(&mut self.tasks, &mut self.task_updates, delta.tasks, |t| &t.id);
+        append_updates(&mut self.tasks, &mut self.task_updates, delta.task_updates,
+            |t| &t.id, |t, other, fields| { t.merge(other, fields); t.unknown.merge(&other.unknown) });
+        append_records(&mut self.tags, &mut self.tag_updates, delta.tags, |t| &t.id);
+        append_updates(&mut self.tags, &mut self.tag_updates, delta.tag_updates,
+            |t| &t.id, |t, other, fields| { t.merge(other, fields); t.unknown.merge(&other.unknown) });
+        append_records(&mut self.task_tags, &mut self.task_tag_updates, delta.task_tags, |l| &l.id);
+        append_updates(&mut self.task_tags, &mut self.task_tag_updates, delta.task_tag_updates,
+            |l| &l.id, |l, other, fields| { l.merge(other, fields); l.unknown.merge(&other.unknown) });
+        append_records(&mut self.folders, &mut self.folder_updates, delta.folders, |f| &f.id);
+        append_updates(&mut self.folders, &mut self.folder_updates, delta.folder_updates,
+            |f| &f.id, |f, other, fields| { f.merge(other, fields); f.unknown.merge(&other.unknown) });
+        append_records(&mut self.attachments, &mut self.attachment_updates, delta.attachments, |a| &a.id);
+        append_updates(&mut self.attachments, &mut self.attachment_updates, delta.attachment_updates,
+            |a| &a.id, |a, other, fields| { a.merge(other, fields); a.unknown.merge(&other.unknown) });
+
+        append_records(&mut self.perspectives, &mut self.perspective_updates, delta.perspectives, |p| &p.id);
+        append_updates(&mut self.perspectives, &mut self.perspective_updates, delta.perspective_updates,
+            |p| &p.id, |p, other, fields| { p.merge(other, fields); p.unknown.merge(&other.unknown) });
+
+        for record in delta.unknown {
+            unknown::apply_record(&mut self.unknown, record);
+        }
+
+        // nothing else needs to be written for a deleted entity
+        for tombstone in delta.tombstones {
+            let id = &tombstone.id;
+            match tombstone.entity {
+                Entity::Task => {
+                    self.tasks.retain(|t| &t.id != id);
+                    self.task_updates.retain(|u| &u.item.id != id);
+                }
+                Entity::Context => {
+                    self.tags.retain(|t| &t.id != id);
+                    self.tag_updates.retain(|u| &u.item.id != id);
+                }
+                Entity::TaskToTag => {
+                    self.task_tags.retain(|l| &l.id != id);
+                    self.task_tag_updates.retain(|u| &u.item.id != id);
+                }
+                Entity::Folder => {
+                    self.folders.retain(|f| &f.id != id);
+                    self.folder_updates.retain(|u| &u.item.id != id);
+                }
+                Entity::Attachment => {
+                    self.attachments.retain(|a| &a.id != id);
+                    self.attachment_updates.retain(|u| &u.item.id != id);
+                }
+                Entity::Perspective => {
+                    self.perspectives.retain(|p| &p.id != id);
+                    self.perspective_updates.retain(|u| &u.item.id != id);
                 }
             }
-            if !replaced {
-                self.tasks.push(task.clone());
+            if !self.tombstones.contains(&tombstone) {
+                self.tombstones.push(tombstone);
             }
         }
     }
 }
 
+// adds full records to a delta, replacing any earlier record or update of
+// the same entity
+fn append_records<T, F>(
+    records: &mut Vec<T>,
+    updates: &mut Vec<Update<T, F>>,
+    new: Vec<T>,
+    id: impl Fn(&T) -> &ID,
+) {
+    for record in new {
+        updates.retain(|u| id(&u.item) != id(&record));
+        match records.iter_mut().find(|r| id(r) == id(&record)) {
+            Some(r) => *r = record,
+            None => records.push(record),
+        }
+    }
+}
+
+// adds partial records to a delta. an update to an entity the delta already
+// has a full record for is applied to that record, otherwise it's combined
+// with any earlier update so only one is written
+fn append_updates<T, F: Copy + PartialEq>(
+    records: &mut [T],
+    updates: &mut Vec<Update<T, F>>,
+    new: Vec<Update<T, F>>,
+    id: impl Fn(&T) -> &ID,
+    merge: impl Fn(&mut T, &T, &[F]),
+) {
+    for update in new {
+        if let Some(r) = records.iter_mut().find(|r| id(r) == id(&update.item)) {
+            merge(r, &update.item, &update.fields);
+        } else if let Some(u) = updates.iter_mut().find(|u| id(&u.item) == id(&update.item)) {
+            merge(&mut u.item, &update.item, &update.fields);
+            for field in update.fields {
+                if !u.fields.contains(&field) {
+                    u.fields.push(field);
+                }
+            }
+        } else {
+            updates.push(update);
+        }
+    }
+}
+
+// reads XML events the same way as xml-rs's Events iterator while keeping
+// track of where it is in the document, so errors can say where they happened
+pub struct Reader<R: Read> {
+    reader: EventReader<R>,
+    finished: bool,
+    open: Vec<String>, // the elements that haven't been closed yet
+}
+
+impl<R: Read> Reader<R> {
+    pub fn new(source: R) -> Reader<R> {
+        Reader { reader: EventReader::new(source), finished: false, open: vec![] }
+    }
+
+    // the line and column the reader is at, starting at 1
+    pub fn position(&self) -> (u64, u64) {
+        let position = self.reader.position();
+        (position.row + 1, position.column + 1)
+    }
+
+    // the elements the reader is inside of, ex: ["omnifocus", "task"]
+    pub fn open_elements(&self) -> &[String] {
+        &self.open
+    }
+}
+
+impl<R: Read> Iterator for Reader<R> {
+    type Item = xml::reader::Result<XmlEvent>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        let evt = self.reader.next();
+        match &evt {
+            Ok(XmlEvent::StartElement { name, .. }) => self.open.push(name.local_name.clone()),
+            Ok(XmlEvent::EndElement { .. }) => {
+                self.open.pop();
+            }
+            Ok(XmlEvent::EndDocument) | Err(_) => self.finished = true,
+            _ => {}
+        }
+        Some(evt)
+    }
+}
+
 // entry point of parser. takes a File of zip data and extracts Tasks
 pub fn parse(f: File) -> Result<Content, Error> {
+    let mut content = Content::default();
+    parse_archive(f, &mut content, None)?;
+    Ok(content)
+}
+
+// parses an archive the same as parse() except that records which can't be
+// read are skipped. the errors for them are returned alongside what could be
+pub fn parse_lenient(f: File) -> Result<(Content, Vec<Error>), Error> {
+    let mut content = Content::default();
+    let mut skipped = Vec::new();
+    parse_archive(f, &mut content, Some(&mut skipped))?;
+    Ok((content, skipped))
+}
+
+fn parse_archive(
+    f: File,
+    content: &mut Content,
+    mut skipped: Option<&mut Vec<Error>>,
+) -> Result<(), Error> {
     // get the contents.xml from the zip file
     let mut zip = ZipArchive::new(f)?;
     let contents = zip.by_name("contents.xml")?;
-    assert!(contents.is_file());
+    if !contents.is_file() {
+        return Err(crate::err!(Missing("contents.xml")));
+    }
 
     // set up an XML parser
-    let mut parser = EventReader::new(contents).into_iter();
-
-    // create vector to store parsed tasks
-    let mut tasks: Vec<Task> = Vec::new();
-
-    // create vector to store parsed perspectives
-    let mut perspectives: Vec<Perspective> = Vec::new();
+    let mut parser = Reader::new(contents);
 
     // iterate over the XML events
     while let Some(evt) = parser.next() {
         match evt {
             Ok(XmlEvent::StartElement { name, attributes, .. }) => {
-                match name_to_str(&name) {
-                    // <task> found
-                    "task" => {
-                        let task = parse_task(&mut parser, attributes)?;
-                        tasks.push(task);
-                    }
-                    // <perspective>
-                    "perspective" => {
-                        let persp = parse_perspective(&mut parser, attributes)?;
-                        perspectives.push(persp);
+                let name = name_to_str(&name);
+                if name == "omnifocus" {
+                    continue;
+                }
+
+                let e = match parse_record(&mut parser, content, name, &attributes) {
+                    Ok(()) => continue,
+                    Err(e) => locate(e, &parser, Some((name, &attributes))),
+                };
+
+                // the rest of the record is passed over to carry on with the
+                // next one, unless the XML itself is broken and there's no
+                // telling where that is
+                match skipped.as_mut() {
+                    Some(skipped) if !matches!(e.kind(), OpenFocusErrorType::Xml(_)) => {
+                        skipped.push(e);
+                        while parser.open_elements().len() > 1 {
+                            match parser.next() {
+                                Some(Err(e)) => return Err(locate(e.into(), &parser, None)),
+                                Some(Ok(_)) => {}
+                                None => break,
+                            }
+                        }
                     }
-                    "omnifocus" => continue,
-                    _ => skip(&mut parser)?
+                    _ => return Err(e),
                 }
             }
-            Ok(XmlEvent::EndElement { name }) => {
-                // </omnifocus> denotes the end of the file
-                if name_to_str(&name) == "omnifocus" {
-                    break
-                }
+            // </omnifocus> denotes the end of the file
+            Ok(XmlEvent::EndElement { name }) if name_to_str(&name) == "omnifocus" => {
+                break
             }
             Err(e) => {
-                return Err(Box::new(e))
+                return Err(locate(e.into(), &parser, None))
             }
             _ => {}
         }
     }
 
-    // return parsed tasks
-    Ok(Content { tasks, perspectives })
+    Ok(())
+}
+
+// adds where the parser is to an error. the innermost element that's still
+// open is the most specific, then the record being read if there is one
+fn locate<R: Read>(
+    e: Error,
+    parser: &Reader<R>,
+    record: Option<(&str, &[OwnedAttribute])>,
+) -> Error {
+    let (line, column) = parser.position();
+    let mut e = e.at(line, column);
+    if parser.open_elements().len() > 1 {
+        e = e.in_element(&parser.open_elements().join("/"));
+    }
+    if let Some((name, attributes)) = record {
+        e = e.in_element(&format!("omnifocus/{}", name));
+        if let Some(id) = attrs_get_val(attributes, "id") {
+            e = e.for_entity(&id);
+        }
+    }
+    e
+}
+
+// reads a single top level record, ex: a <task>, into content
+fn parse_record<'a>(
+    parser: &mut Reader<zip::read::ZipFile<'a>>,
+    content: &mut Content,
+    name: &str,
+    attributes: &[OwnedAttribute],
+) -> Result<(), Error> {
+    // op="delete" records are empty tombstones for any kind of
+    // entity. deletes of unknown kinds are kept like any other
+    // unknown record
+    let op = parse_op(attributes)?;
+    if op == Op::Delete {
+        if let Some(entity) = Entity::from_element_name(name) {
+            let id = attrs_get_val(attributes, "id")
+                .ok_or_else(|| crate::err!(Missing("id")))?;
+            content.tombstones.push(Tombstone { entity, id });
+            skip(parser)?;
+            return Ok(());
+        }
+    }
+
+    match name {
+        // <task> found
+        "task" => {
+            let (task, fields) = parse_task(parser, attributes, op)?;
+            if op == Op::Update {
+                content.task_updates.push(Update { item: task, fields });
+            } else {
+                content.tasks.push(task);
+            }
+        }
+        // <context> found. these are tags
+        "context" => {
+            let (tag, fields) = parse_tag(parser, attributes, op)?;
+            if op == Op::Update {
+                content.tag_updates.push(Update { item: tag, fields });
+            } else {
+                content.tags.push(tag);
+            }
+        }
+        // <task-to-tag> found. links tasks to (possibly many) tags
+        "task-to-tag" => {
+            let (link, fields) = parse_task_tag(parser, attributes, op)?;
+            if op == Op::Update {
+                content.task_tag_updates.push(Update { item: link, fields });
+            } else {
+                content.task_tags.push(link);
+            }
+        }
+        // <folder> found
+        "folder" => {
+            let (folder, fields) = parse_folder(parser, attributes, op)?;
+            if op == Op::Update {
+                content.folder_updates.push(Update { item: folder, fields });
+            } else {
+                content.folders.push(folder);
+            }
+        }
+        // <attachment> found. the file itself is in data/
+        "attachment" => {
+            let (attachment, fields) =
+                parse_attachment(parser, attributes, op)?;
+            if op == Op::Update {
+                content.attachment_updates.push(Update { item: attachment, fields });
+            } else {
+                content.attachments.push(attachment);
+            }
+        }
+        // <perspective>
+        "perspective" => {
+            let (perspective, fields) = parse_perspective(parser, attributes, op)?;
+            if op == Op::Update {
+                content.perspective_updates.push(Update { item: perspective, fields });
+            } else {
+                content.perspectives.push(perspective);
+            }
+        }
+        // anything else is kept as-is to be written back out
+        other => content.unknown.push(read_element(other, attributes, parser)?),
+    }
+
+    // Content::update applies an archive's full records before its updates
+    // and tombstones. a full record replaces everything that came before it,
+    // so the updates and tombstones of the same entity earlier in the archive
+    // are dropped to keep the records in the order they were written, ex: a
+    // task that was deleted and then written again
+    if op == Op::Insert {
+        let entity = Entity::from_element_name(name);
+        if let (Some(entity), Some(id)) = (entity, attrs_get_val(attributes, "id")) {
+            supersede(content, entity, &id);
+        }
+    }
+    Ok(())
+}
+
+// drops the updates and tombstones of an entity from the records read so far
+fn supersede(content: &mut Content, entity: Entity, id: &str) {
+    content.tombstones.retain(|t| t.entity != entity || t.id != id);
+    match entity {
+        Entity::Task => content.task_updates.retain(|u| u.item.id != id),
+        Entity::Context => content.tag_updates.retain(|u| u.item.id != id),
+        Entity::TaskToTag => content.task_tag_updates.retain(|u| u.item.id != id),
+        Entity::Folder => content.folder_updates.retain(|u| u.item.id != id),
+        Entity::Attachment => content.attachment_updates.retain(|u| u.item.id != id),
+        Entity::Perspective => content.perspective_updates.retain(|u| u.item.id != id),
+    }
 }
 
 // skips over an arbitrary XML structure by keeping track of depth
 fn skip<'a>(
-    parser: &mut xml::reader::Events<zip::read::ZipFile<'a>>
+    parser: &mut Reader<zip::read::ZipFile<'a>>
 ) -> Result<(), Error> {
     let mut depth = 1;
 
-    while let Some(evt) = parser.next() {
+    for evt in parser {
         match evt {
             Ok(XmlEvent::StartElement { .. }) => {
                 depth += 1;
@@ -108,7 +638,7 @@ fn skip<'a>(
                     break;
                 }
             }
-            Err(e) => { return Err(Box::new(e)) }
+            Err(e) => { return Err(e.into()) }
             _ => {}
         }
     }
@@ -116,24 +646,164 @@ fn skip<'a>(
     Ok(())
 }
 
-// parses a single Task from a <task>
+// parses the op="..." attribute of a record
+fn parse_op(attrs: &[OwnedAttribute]) -> Result<Op, Error> {
+    match attrs_get_val(attrs, "op").as_deref() {
+        None => Ok(Op::Insert),
+        Some("update") => Ok(Op::Update),
+        Some("delete") => Ok(Op::Delete),
+        Some(_) => Err(crate::err!(Parse)),
+    }
+}
+
+// the parts of a record that every kind of record has, read by parse_children
+struct Record<F> {
+    id: ID,
+    fields: Vec<F>, // the fields that were present, in the order they were
+    added: Option<DateTime<Utc>>,
+    modified: Option<DateTime<Utc>>,
+    unknown: Unknown,
+}
+
+// reads the elements inside of a record up to its closing tag. <added> and
+// <modified> are read here, along with anything not known about which is kept
+// as-is to be written back out. everything else is given to child, which reads
+// the whole element including its closing tag and returns the field it was, or
+// None if it doesn't know the element
+fn parse_children<'a, F, C>(
+    parser: &mut Reader<zip::read::ZipFile<'a>>,
+    root_attrs: &[OwnedAttribute],
+    (added_field, modified_field): (F, F),
+    mut child: C,
+) -> Result<Record<F>, Error>
+where
+    F: Copy,
+    C: FnMut(&str, &[OwnedAttribute], &mut Reader<zip::read::ZipFile<'a>>) -> Result<Option<F>, Error>,
+{
+    let mut record = Record {
+        id: attrs_get_val(root_attrs, "id").ok_or_else(|| crate::err!(Missing("id")))?,
+        fields: Vec::new(),
+        added: None,
+        modified: None,
+        unknown: Unknown::from_attributes(root_attrs, &["id", "op"]),
+    };
+
+    record.unknown.elements = read_children(parser, |name, attributes, parser| {
+        let field = match name {
+            "added" => {
+                record.added = Some(read_value(parser)?);
+                Some(added_field)
+            }
+            "modified" => {
+                record.modified = read_optional(parser)?;
+                Some(modified_field)
+            }
+            other => child(other, attributes, parser)?,
+        };
+        Ok(field.map(|field| record.fields.push(field)).is_some())
+    })?;
+    Ok(record)
+}
+
+// reads the elements inside of an element up to its closing tag. child reads
+// the whole element including its closing tag and returns whether it knew the
+// element. the ones it didn't know are read as-is and returned
+fn read_children<'a, C>(
+    parser: &mut Reader<zip::read::ZipFile<'a>>,
+    mut child: C,
+) -> Result<Vec<RawElement>, Error>
+where
+    C: FnMut(&str, &[OwnedAttribute], &mut Reader<zip::read::ZipFile<'a>>) -> Result<bool, Error>,
+{
+    let mut unknown = Vec::new();
+    while let Some(evt) = parser.next() {
+        match evt {
+            Ok(XmlEvent::StartElement { name, attributes, .. }) => {
+                let known = child(name_to_str(&name), &attributes, parser)?;
+                if !known {
+                    unknown.push(read_element(name_to_str(&name), &attributes, parser)?);
+                }
+            }
+            // the closing tag of the element itself, as every element inside
+            // of it is read up to its own closing tag
+            Ok(XmlEvent::EndElement { .. }) => break,
+            Err(e) => { return Err(e.into()) }
+            _ => {}
+        }
+    }
+    Ok(unknown)
+}
+
+// full records must be complete. updates only carry the changed fields so
+// anything missing is filled in with a placeholder that merge() will ignore
+fn required<T>(
+    value: Option<T>,
+    op: Op,
+    name: &'static str,
+    placeholder: impl FnOnce() -> T,
+) -> Result<T, Error> {
+    match (value, op) {
+        (Some(value), _) => Ok(value),
+        (None, Op::Update) => Ok(placeholder()),
+        (None, _) => Err(crate::err!(Missing(name))),
+    }
+}
+
+// reads an element that may be empty, ex: <due/>, up to its closing tag and
+// parses its text
+fn read_optional<'a, T>(
+    parser: &mut Reader<zip::read::ZipFile<'a>>,
+) -> Result<Option<T>, Error>
+where
+    T: std::str::FromStr,
+    Error: From<T::Err>,
+{
+    match parser.next() {
+        Some(Ok(XmlEvent::Characters(text))) => {
+            let value = text.parse()?;
+            skip(parser)?;
+            Ok(Some(value))
+        }
+        Some(Ok(XmlEvent::EndElement { .. })) => Ok(None),
+        Some(Err(e)) => Err(e.into()),
+        _ => Err(crate::err!(Parse)),
+    }
+}
+
+// the same as read_optional() for an element that can't be empty
+fn read_value<'a, T>(parser: &mut Reader<zip::read::ZipFile<'a>>) -> Result<T, Error>
+where
+    T: std::str::FromStr,
+    Error: From<T::Err>,
+{
+    read_optional(parser)?.ok_or_else(|| crate::err!(Parse))
+}
+
+// reads a reference to another entity, ex: <context idref="..."/>
+fn read_idref<'a>(
+    attributes: &[OwnedAttribute],
+    parser: &mut Reader<zip::read::ZipFile<'a>>,
+) -> Result<Option<ID>, Error> {
+    skip(parser)?;
+    Ok(attrs_get_val(attributes, "idref"))
+}
+
+// parses a single Task from a <task> along with the list of fields that were
+// actually present in it
 fn parse_task<'a>(
-    parser: &mut xml::reader::Events<zip::read::ZipFile<'a>>,
-    root_attrs: Vec<OwnedAttribute>,
-) -> Result<Task, Error> {
+    parser: &mut Reader<zip::read::ZipFile<'a>>,
+    root_attrs: &[OwnedAttribute],
+    op: Op,
+) -> Result<(Task, Vec<TaskField>), Error> {
     // === data to be parsed ===
 
     // metadata
-    let id: ID = attrs_get_val(root_attrs, "id")
-        .expect("tasks must have IDs");
     let mut parent:   Option<ID> = None;
     let mut rank:     Option<i64> = None;
     let mut inbox:    bool = true;
-    let mut added:    Option<DateTime<Utc>> = None;
-    let mut modified: Option<DateTime<Utc>> = None;
     // attributes
     let mut title:    Option<String> = None;
-    let mut note:     Option<String> = None;
+    let mut note:     Option<Note> = None;
     let mut context:  Option<ID> = None;
     let mut order:    Option<SubtaskOrder> = None;
     let mut flagged:  bool = false;
@@ -142,138 +812,114 @@ fn parse_task<'a>(
     let mut start: Option<DateTime<Utc>> = None;
     let mut completed: Option<DateTime<Utc>> = None;
     let mut due: Option<DateTime<Utc>> = None;
+    let mut project: Option<ProjectInfo> = None;
+    let mut repetition_rule: Option<RepetitionRule> = None;
+    let mut repetition_method: Option<RepetitionMethod> = None;
+    let mut next_clone_identifier: Option<u64> = None;
 
-    let mut depth = 1;
-    while let Some(evt) = parser.next() {
-        match evt {
-            Ok(XmlEvent::StartElement { name, attributes, .. }) => {
-                // when a tag opens increase depth by 1
-                depth += 1;
-                match name_to_str(&name) {
-                    // a <task> inside a <task> is the pointer to the task's parent
-                    "task" => {
-                        parent = attrs_get_val(attributes, "idref");
-                    }
-                    // parse the "rank" of the task. used for sorting in some contexts
-                    "rank" => {
-                        let text = get_text_content(parser.next())?;
-                        rank = Some(text.parse()?);
-                    }
-                    // parse the inbox status of the task
-                    "inbox" => {
-                        let text = get_text_content(parser.next())?;
-                        inbox = text.parse()?;
-                    }
-                    // parse added date of a task
-                    "added" => {
-                        let text = get_text_content(parser.next())?;
-                        added = Some(text.parse()?);
-                    }
-                    // parses the modified date of a task
-                    "modified" => {
-                        let text = get_text_content(parser.next())?;
-                        modified = Some(text.parse()?);
-                    }
-                    // parses the starting "defer" date of a task
-                    "start" => {
-                        if let Ok(text) = get_text_content(parser.next()) {
-                            start = Some(text.parse()?);
-                        } else {
-                            depth -= 1;
-                        }
-                    }
-                    // parses the completed date of a task
-                    "completed" => {
-                        if let Ok(text) = get_text_content(parser.next()) {
-                            completed = Some(text.parse()?);
-                        } else {
-                            depth -= 1;
-                        }
-                    }
-                    // parses the due date of a task
-                    "due" => {
-                        if let Ok(text) = get_text_content(parser.next()) {
-                            due = Some(text.parse()?);
-                        } else {
-                            depth -= 1
-                        }
-                    }
-                    // parses the name of the task
-                    "name" => {
-                        if let Ok(text) = get_text_content(parser.next()) {
-                            title = Some(text);
-                        } else {
-                            title = Some(String::new());
-                            depth -= 1;
-                        }
-                    }
-                    // parses the additional notes attached to a task
-                    "note" => {
-                        // TODO
-                        skip(parser)?;
-                        depth -= 1;
-                        note = Some(String::new());
-                    },
-                    // parses a context/tag associated with a task
-                    "context" => {
-                        context = attrs_get_val(attributes, "idref");
-                    }
-                    // parses the order subtasks can be completed in
-                    // either Parallel or Sequential
-                    "order" => {
-                        let text = get_text_content(parser.next())?;
-                        order = Some(text.parse()?);
-                    }
-                    // parses the flagged status of a task
-                    "flagged" => {
-                        let text = get_text_content(parser.next())?;
-                        flagged = text.parse()?;
-                    }
-                    // parses the estimated minutes of a task
-                    "estimated-minutes" => {
-                        if let Ok(text)= get_text_content(parser.next()) {
-                            estimated_duration = Some(text.parse()?);
-                        } else {
-                            depth -= 1;
-                        }
-                    }
-                    // parses whether this task is auto complete when all of its
-                    // children are complete
-                    "completed-by-children" => {
-                        let text = get_text_content(parser.next())?;
-                        complete_by_children = text.parse()?;
-                    }
-                    // parses the project the task belongs to
-                    "project" => {
-                        // TODO
-                        skip(parser)?;
-                        depth -= 1;
-                    }
-                    _ => {/*println!("child {:?} {:?}", name, attributes)*/}
-                }
+    let fields = (TaskField::Added, TaskField::Modified);
+    let record = parse_children(parser, root_attrs, fields, |name, attributes, parser| {
+        Ok(Some(match name {
+            // a <task> inside a <task> is the pointer to the task's parent
+            "task" => {
+                parent = read_idref(attributes, parser)?;
+                TaskField::Parent
             }
-            // at each closing tag decrease depth
-            Ok(XmlEvent::EndElement { .. }) => {
-                depth -= 1;
-                // when depth is zero we're done parsing a <task>
-                if depth == 0 {
-                    break;
-                }
+            // parse the "rank" of the task. used for sorting in some contexts
+            "rank" => {
+                rank = read_optional(parser)?;
+                TaskField::Rank
             }
-            Err(e) => { return Err(Box::new(e)) }
-            _ => {}
-        }
-    }
+            // parse the inbox status of the task
+            "inbox" => {
+                inbox = read_value(parser)?;
+                TaskField::Inbox
+            }
+            // parses the starting "defer" date of a task
+            "start" => {
+                start = read_optional(parser)?;
+                TaskField::Start
+            }
+            // parses the completed date of a task
+            "completed" => {
+                completed = read_optional(parser)?;
+                TaskField::Completed
+            }
+            // parses the due date of a task
+            "due" => {
+                due = read_optional(parser)?;
+                TaskField::Due
+            }
+            // parses the name of the task
+            "name" => {
+                title = Some(read_optional(parser)?.unwrap_or_default());
+                TaskField::Title
+            }
+            // parses the additional notes attached to a task
+            "note" => {
+                note = parse_note(parser)?;
+                TaskField::Note
+            }
+            // parses a context/tag associated with a task
+            "context" => {
+                context = read_idref(attributes, parser)?;
+                TaskField::Context
+            }
+            // parses the order subtasks can be completed in
+            // either Parallel or Sequential
+            "order" => {
+                order = read_optional(parser)?;
+                TaskField::Order
+            }
+            // parses the flagged status of a task
+            "flagged" => {
+                flagged = read_value(parser)?;
+                TaskField::Flagged
+            }
+            // parses the estimated minutes of a task
+            "estimated-minutes" => {
+                estimated_duration = read_optional(parser)?;
+                TaskField::EstimatedDuration
+            }
+            // parses whether this task is auto complete when all of its
+            // children are complete
+            "completed-by-children" => {
+                complete_by_children = read_value(parser)?;
+                TaskField::CompleteByChildren
+            }
+            // parses the project data, which makes this task a project
+            "project" => {
+                project = parse_project(parser)?;
+                TaskField::Project
+            }
+            // parses the schedule a repeating task follows
+            "repetition-rule" => {
+                repetition_rule = read_optional(parser)?;
+                TaskField::RepetitionRule
+            }
+            // parses which date the repetition is counted from
+            "repetition-method" => {
+                repetition_method = read_optional(parser)?;
+                TaskField::RepetitionMethod
+            }
+            // parses the counter used when cloning a repeating task
+            "next-clone-identifier" => {
+                next_clone_identifier = read_optional(parser)?;
+                TaskField::NextCloneIdentifier
+            }
+            _ => return Ok(None),
+        }))
+    })?;
 
     // return parsed task
-    Ok(Task {
-        id,
+    Ok((Task {
+        id: record.id,
         parent,
         rank,
         inbox,
-        added: added.expect("Tasks must have an added datetime"),
-        modified,
-        title: title.expect("Tasks must have a name"),
+        added: required(record.added, op, "added", Utc::now)?,
+        modified: record.modified,
+        title: required(title, op, "title", String::new)?,
         note,
         completed,
         context,
@@ -283,68 +929,358 @@ fn parse_task<'a>(
         estimated_duration,
         complete_by_children,
         order,
-    })
+        project,
+        repetition_rule,
+        repetition_method,
+        next_clone_identifier,
+        unknown: record.unknown,
+    }, record.fields))
 }
 
-// parses a <perspective>
-fn parse_perspective<'a>(
-    mut parser: &mut xml::reader::Events<zip::read::ZipFile<'a>>,
-    _root_attrs: Vec<OwnedAttribute>,
-) -> Result<Perspective, Error> {
-    // TODO: depth purely for error handling? 
-    // TODO: perspective names...?
+// parses the <project> block inside of a <task>. regular tasks have an empty
+// <project/> which gives None
+fn parse_project<'a>(
+    parser: &mut Reader<zip::read::ZipFile<'a>>,
+) -> Result<Option<ProjectInfo>, Error> {
+    let mut is_project = false;
+    let mut info = ProjectInfo {
+        last_review: None,
+        review_interval: None,
+        ..ProjectInfo::default()
+    };
 
-    let mut added: Option<DateTime<Utc>> = None;
+    info.unknown.elements = read_children(parser, |name, attributes, parser| {
+        is_project = true;
+        match name {
+            // the folder the project is in
+            "folder" => info.folder = read_idref(attributes, parser)?,
+            // whether the project is a single action list
+            "singleton" => info.singleton = read_value(parser)?,
+            // when the project was last reviewed
+            "last-review" => info.last_review = read_optional(parser)?,
+            // when the project is next up for review
+            "next-review" => info.next_review = read_optional(parser)?,
+            // how often the project is reviewed
+            "review-interval" => info.review_interval = read_optional(parser)?,
+            // active, inactive (on hold), done, or dropped
+            "status" => info.status = read_value(parser)?,
+            _ => return Ok(false),
+        }
+        Ok(true)
+    })?;
 
-    while let Some(evt) = parser.next() {
-        match evt {
-            Ok(XmlEvent::StartElement { name, .. }) => {
-                match name_to_str(&name) {
-                    "added" => {
-                        let text = get_text_content(parser.next())?;
-                        added = Some(text.parse()?);
-                    }
+    Ok(if is_project { Some(info) } else { None })
+}
 
-                    // omnifocus encodes filter rules
-                    // in JSON
-                    // inside PLIST
-                    // inside XML
-                    // inside ZIP
-                    // inside a directory<b>.</b>
-                    // <i>sigh</i>
-                    "plist" => {
-                        let plist = plist::parse_plist(&mut parser)?;
-                        let plist = plist.unwrap_dict();
-                        #[allow(unused_variables)]
-                        let filter_json = plist.get("filterRules")
-                                              .unwrap()
-                                              .unwrap_string();
-                    }
-                    _ => {}
+// parses a single Tag from a <context> along with the list of fields that were
+// actually present in it
+fn parse_tag<'a>(
+    parser: &mut Reader<zip::read::ZipFile<'a>>,
+    root_attrs: &[OwnedAttribute],
+    op: Op,
+) -> Result<(Tag, Vec<TagField>), Error> {
+    let mut parent:   Option<ID> = None;
+    let mut rank:     Option<i64> = None;
+    let mut name:     Option<String> = None;
+    let mut note:     Option<Note> = None;
+    let mut hidden:   Option<DateTime<Utc>> = None;
+    let mut prohibits_next_action: bool = false;
+    let mut location: Option<Location> = None;
+    let mut tasks_user_ordered: bool = false;
+
+    let fields = (TagField::Added, TagField::Modified);
+    let record = parse_children(parser, root_attrs, fields, |elem, attributes, parser| {
+        Ok(Some(match elem {
+            // a <context> inside a <context> is the pointer to the parent
+            "context" => {
+                parent = read_idref(attributes, parser)?;
+                TagField::Parent
+            }
+            // parse the "rank" of the tag. used for sorting siblings
+            "rank" => {
+                rank = read_optional(parser)?;
+                TagField::Rank
+            }
+            // parses the name of the tag
+            "name" => {
+                name = Some(read_optional(parser)?.unwrap_or_default());
+                TagField::Name
+            }
+            // parses the notes attached to a tag
+            "note" => {
+                note = parse_note(parser)?;
+                TagField::Note
+            }
+            // parses the date the tag was dropped, if it was
+            "hidden" => {
+                hidden = read_optional(parser)?;
+                TagField::Hidden
+            }
+            // parses whether the tag is on hold
+            "prohibits-next-action" => {
+                prohibits_next_action = read_value(parser)?;
+                TagField::ProhibitsNextAction
+            }
+            // parses the location the tag is attached to. the data
+            // lives in the attributes of <location .../>
+            "location" => {
+                if !attributes.is_empty() {
+                    let float = |name| {
+                        attrs_get_val(attributes, name)
+                            .and_then(|v| v.parse().ok())
+                    };
+                    location = Some(Location {
+                        name: attrs_get_val(attributes, "name"),
+                        latitude: float("latitude"),
+                        longitude: float("longitude"),
+                        radius: float("radius"),
+                    });
                 }
+                skip(parser)?;
+                TagField::Location
+            }
+            // parses whether tasks with this tag are manually ordered
+            "tasks-user-ordered" => {
+                tasks_user_ordered = read_value(parser)?;
+                TagField::TasksUserOrdered
             }
-            Ok(XmlEvent::EndElement { name }) => {
-                if name_to_str(&name) == "perspective" {
-                    break
+            _ => return Ok(None),
+        }))
+    })?;
+
+    Ok((Tag {
+        id: record.id,
+        parent,
+        rank,
+        added: required(record.added, op, "added", Utc::now)?,
+        modified: record.modified,
+        name: required(name, op, "name", String::new)?,
+        note,
+        hidden,
+        prohibits_next_action,
+        location,
+        tasks_user_ordered,
+        unknown: record.unknown,
+    }, record.fields))
+}
+
+// parses a single Folder from a <folder> along with the list of fields that
+// were actually present in it
+fn parse_folder<'a>(
+    parser: &mut Reader<zip::read::ZipFile<'a>>,
+    root_attrs: &[OwnedAttribute],
+    op: Op,
+) -> Result<(Folder, Vec<FolderField>), Error> {
+    let mut parent:   Option<ID> = None;
+    let mut rank:     Option<i64> = None;
+    let mut name:     Option<String> = None;
+    let mut note:     Option<Note> = None;
+    let mut hidden:   Option<DateTime<Utc>> = None;
+
+    let fields = (FolderField::Added, FolderField::Modified);
+    let record = parse_children(parser, root_attrs, fields, |elem, attributes, parser| {
+        Ok(Some(match elem {
+            // a <folder> inside a <folder> is the pointer to the parent
+            "folder" => {
+                parent = read_idref(attributes, parser)?;
+                FolderField::Parent
+            }
+            // parse the "rank" of the folder. used for sorting siblings
+            "rank" => {
+                rank = read_optional(parser)?;
+                FolderField::Rank
+            }
+            // parses the name of the folder
+            "name" => {
+                name = Some(read_optional(parser)?.unwrap_or_default());
+                FolderField::Name
+            }
+            // parses the notes attached to a folder
+            "note" => {
+                note = parse_note(parser)?;
+                FolderField::Note
+            }
+            // parses the date the folder was dropped, if it was
+            "hidden" => {
+                hidden = read_optional(parser)?;
+                FolderField::Hidden
+            }
+            _ => return Ok(None),
+        }))
+    })?;
+
+    Ok((Folder {
+        id: record.id,
+        parent,
+        rank,
+        added: required(record.added, op, "added", Utc::now)?,
+        modified: record.modified,
+        name: required(name, op, "name", String::new)?,
+        note,
+        hidden,
+        unknown: record.unknown,
+    }, record.fields))
+}
+
+// parses a single Attachment from an <attachment> along with the list of
+// fields that were actually present in it
+fn parse_attachment<'a>(
+    parser: &mut Reader<zip::read::ZipFile<'a>>,
+    root_attrs: &[OwnedAttribute],
+    op: Op,
+) -> Result<(Attachment, Vec<AttachmentField>), Error> {
+    let mut task:     Option<ID> = None;
+    let mut name:     Option<String> = None;
+    let mut preview_image: Option<Vec<u8>> = None;
+    let mut data_identifier: Option<String> = None;
+
+    let fields = (AttachmentField::Added, AttachmentField::Modified);
+    let record = parse_children(parser, root_attrs, fields, |elem, attributes, parser| {
+        Ok(Some(match elem {
+            // the task the file is attached to
+            "task" => {
+                task = read_idref(attributes, parser)?;
+                AttachmentField::Task
+            }
+            // parses the file name
+            "name" => {
+                name = Some(read_optional(parser)?.unwrap_or_default());
+                AttachmentField::Name
+            }
+            // parses the base64 encoded thumbnail
+            "preview-image" => {
+                if let Some(text) = read_optional::<String>(parser)? {
+                    let text: String = text.split_whitespace().collect();
+                    preview_image = Some(base64::decode(&text)?);
                 }
+                AttachmentField::PreviewImage
             }
-            _ => {}
-        }
-    }
+            // parses the name of the file in data/ holding the contents
+            "data-identifier" => {
+                data_identifier = read_optional(parser)?;
+                AttachmentField::DataIdentifier
+            }
+            _ => return Ok(None),
+        }))
+    })?;
+
+    Ok((Attachment {
+        id: record.id,
+        task,
+        added: required(record.added, op, "added", Utc::now)?,
+        modified: record.modified,
+        name: required(name, op, "name", String::new)?,
+        preview_image,
+        data_identifier,
+        unknown: record.unknown,
+    }, record.fields))
+}
+
+// parses a single TaskTag from a <task-to-tag> along with the list of fields
+// that were actually present in it
+fn parse_task_tag<'a>(
+    parser: &mut Reader<zip::read::ZipFile<'a>>,
+    root_attrs: &[OwnedAttribute],
+    op: Op,
+) -> Result<(TaskTag, Vec<TaskTagField>), Error> {
+    let mut task:     Option<ID> = None;
+    let mut tag:      Option<ID> = None;
+    let mut rank_in_task: Option<String> = None;
+    let mut rank_in_tag:  Option<String> = None;
+
+    let fields = (TaskTagField::Added, TaskTagField::Modified);
+    let record = parse_children(parser, root_attrs, fields, |name, attributes, parser| {
+        Ok(Some(match name {
+            // the task side of the link
+            "task" => {
+                task = read_idref(attributes, parser)?;
+                TaskTagField::Task
+            }
+            // the tag side of the link
+            "context" => {
+                tag = read_idref(attributes, parser)?;
+                TaskTagField::Tag
+            }
+            // the order of the tag among the task's tags
+            "rank-in-task" => {
+                rank_in_task = read_optional(parser)?;
+                TaskTagField::RankInTask
+            }
+            // the order of the task among the tag's tasks
+            "rank-in-tag" => {
+                rank_in_tag = read_optional(parser)?;
+                TaskTagField::RankInTag
+            }
+            _ => return Ok(None),
+        }))
+    })?;
+
+    // the id of a link is "{task}.{tag}" so that's used as a fallback if
+    // either end is missing
+    let (task_end, tag_end) = record.id.split_once('.').unwrap_or((&record.id, ""));
+    let task = task.unwrap_or_else(|| task_end.into());
+    let tag = tag.unwrap_or_else(|| tag_end.into());
+
+    Ok((TaskTag {
+        added: required(record.added, op, "added", Utc::now)?,
+        modified: record.modified,
+        task,
+        tag,
+        rank_in_task,
+        rank_in_tag,
+        unknown: record.unknown,
+        id: record.id,
+    }, record.fields))
+}
+
+// parses a <perspective>
+fn parse_perspective<'a>(
+    parser: &mut Reader<zip::read::ZipFile<'a>>,
+    root_attrs: &[OwnedAttribute],
+    op: Op,
+) -> Result<(Perspective, Vec<PerspectiveField>), Error> {
+    let mut plist = BTreeMap::new();
+
+    let fields = (PerspectiveField::Added, PerspectiveField::Modified);
+    let record = parse_children(parser, root_attrs, fields, |elem, _, parser| {
+        Ok(Some(match elem {
+            // omnifocus encodes filter rules
+            // in JSON
+            // inside PLIST
+            // inside XML
+            // inside ZIP
+            // inside a directory<b>.</b>
+            // <i>sigh</i>
+            "plist" => {
+                plist = match plist::parse_plist(parser)? {
+                    PlistItem::Dict(d) => d,
+                    _ => return Err(crate::err!(Parse)),
+                };
+                // parse_plist stops at </dict>, the </plist> is next
+                skip(parser)?;
+                PerspectiveField::Plist
+            }
+            _ => return Ok(None),
+        }))
+    })?;
 
-    Ok(Perspective {
-        id: "foo".into(), // TODO grab id
-        added: added.expect("added is required"),
-    })
+    let mut perspective = Perspective::from_plist(
+        record.id,
+        required(record.added, op, "added", Utc::now)?,
+        plist,
+    )?;
+    perspective.modified = record.modified;
+    perspective.unknown = record.unknown;
+    Ok((perspective, record.fields))
 }
 
 // turns an OwnedName struct into a &str to make it actually useful
-pub fn name_to_str<'a>(name: &'a xml::name::OwnedName) -> &'a str {
+pub fn name_to_str(name: &xml::name::OwnedName) -> &str {
     name.local_name.as_str()
 }
 
 // get the value of an OwnedAttribute
-fn attrs_get_val(attrs: Vec<OwnedAttribute>, name: &str) -> Option<String> {
+fn attrs_get_val(attrs: &[OwnedAttribute], name: &str) -> Option<String> {
     attrs
         .iter()
         .find(|attr| name_to_str(&attr.name) == name)
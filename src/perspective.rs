@@ -1,12 +1,552 @@
+use std::collections::BTreeMap;
 use chrono::prelude::*;
 
-use crate::util::ID;
+use crate::util::{ID, generate_id};
+use crate::parse::Content;
+use crate::task::Task;
+use crate::plist::PlistItem;
+use crate::unknown::Unknown;
+use crate::rule::{self, Rule, Aggregate};
 
-// struct to represent a perspective. WORK IN PROGRESS
-#[derive(Debug, PartialEq, Eq)]
+// enumeration of the sidebars a perspective can be shown with ("viewMode")
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ViewMode {
+    Project,
+    Context,
+    Other(String),
+}
+
+impl From<&str> for ViewMode {
+    fn from(s: &str) -> Self {
+        match s {
+            "project" => ViewMode::Project,
+            "context" => ViewMode::Context,
+            _ => ViewMode::Other(s.into()),
+        }
+    }
+}
+
+impl ViewMode {
+    pub fn as_str(&self) -> &str {
+        match self {
+            ViewMode::Project => "project",
+            ViewMode::Context => "context",
+            ViewMode::Other(s) => s,
+        }
+    }
+}
+
+// enumeration of the task properties a perspective can group ("collation") or
+// sort ("sort") by. "none" keeps the order of the file
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum TaskProperty {
+    None,
+    Project,
+    Tag,
+    Due,
+    Defer,
+    Completed,
+    Added,
+    Modified,
+    Flagged,
+    Title,
+    Duration,
+    Other(String),
+}
+
+impl From<&str> for TaskProperty {
+    fn from(s: &str) -> Self {
+        match s {
+            "none" => TaskProperty::None,
+            "project" => TaskProperty::Project,
+            "context" => TaskProperty::Tag,
+            "due" => TaskProperty::Due,
+            "start" => TaskProperty::Defer,
+            "completed" => TaskProperty::Completed,
+            "added" => TaskProperty::Added,
+            "modified" => TaskProperty::Modified,
+            "flagged" => TaskProperty::Flagged,
+            "name" => TaskProperty::Title,
+            "duration" => TaskProperty::Duration,
+            _ => TaskProperty::Other(s.into()),
+        }
+    }
+}
+
+impl TaskProperty {
+    // the string used for this property in the view state
+    pub fn as_str(&self) -> &str {
+        match self {
+            TaskProperty::None => "none",
+            TaskProperty::Project => "project",
+            TaskProperty::Tag => "context",
+            TaskProperty::Due => "due",
+            TaskProperty::Defer => "start",
+            TaskProperty::Completed => "completed",
+            TaskProperty::Added => "added",
+            TaskProperty::Modified => "modified",
+            TaskProperty::Flagged => "flagged",
+            TaskProperty::Title => "name",
+            TaskProperty::Duration => "duration",
+            TaskProperty::Other(s) => s,
+        }
+    }
+}
+
+// how a perspective displays its tasks. these are the settings for the
+// current view mode, stored in viewState.viewModeState.{view mode}
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ViewState {
+    pub view_mode: ViewMode,
+    pub collation: TaskProperty,               // what tasks are grouped by
+    pub sort: TaskProperty,                    // the order inside of a group
+    pub completion_filter: Option<String>,     // "all", "complete", ...
+    pub flagged_filter: Option<String>,        // "all", "flagged", ...
+    pub duration_filter: Option<String>,       // "any" or a number of minutes
+}
+
+impl Default for ViewState {
+    fn default() -> Self {
+        ViewState {
+            view_mode: ViewMode::Project,
+            collation: TaskProperty::None,
+            sort: TaskProperty::None,
+            completion_filter: None,
+            flagged_filter: None,
+            duration_filter: None,
+        }
+    }
+}
+
+impl ViewState {
+    // reads the view state out of a perspective's "viewState" dict
+    pub fn from_plist(item: &PlistItem) -> ViewState {
+        let view_mode: ViewMode = item.get_path(&["viewMode"])
+            .and_then(|v| v.as_str())
+            .unwrap_or("project")
+            .into();
+        let mode = item.get_path(&["viewModeState", view_mode.as_str()]);
+        let get = |key: &str| {
+            mode.and_then(|m| m.get_path(&[key]))
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string())
+        };
+
+        ViewState {
+            collation: get("collation").as_deref().unwrap_or("none").into(),
+            sort: get("sort").as_deref().unwrap_or("none").into(),
+            completion_filter: get("actionCompletionFilter"),
+            flagged_filter: get("actionFlaggedFilter"),
+            duration_filter: get("actionDurationFilter"),
+            view_mode,
+        }
+    }
+
+    // writes the view state over an existing "viewState" dict, keeping the
+    // keys that aren't modeled like "firstResponder" and the other view modes
+    pub fn to_plist(&self, item: Option<&PlistItem>) -> PlistItem {
+        let mut view_state = item.and_then(|i| i.as_dict()).cloned().unwrap_or_default();
+        let string = |s: &str| PlistItem::String(s.into());
+
+        view_state.insert("viewMode".into(), string(self.view_mode.as_str()));
+        let modes = view_state.entry("viewModeState".to_string())
+            .or_insert_with(|| PlistItem::Dict(BTreeMap::new()));
+        if modes.as_dict().is_none() {
+            *modes = PlistItem::Dict(BTreeMap::new());
+        }
+        if let PlistItem::Dict(modes) = modes {
+            let mut mode = modes.get(self.view_mode.as_str())
+                .and_then(|m| m.as_dict())
+                .cloned()
+                .unwrap_or_default();
+            mode.insert("collation".into(), string(self.collation.as_str()));
+            mode.insert("sort".into(), string(self.sort.as_str()));
+            let filters = vec![
+                ("actionCompletionFilter", &self.completion_filter),
+                ("actionFlaggedFilter", &self.flagged_filter),
+                ("actionDurationFilter", &self.duration_filter),
+            ];
+            for (key, value) in filters {
+                match value {
+                    Some(v) => mode.insert(key.into(), string(v)),
+                    None => mode.remove(key),
+                };
+            }
+            modes.insert(self.view_mode.as_str().into(), PlistItem::Dict(mode));
+        }
+
+        PlistItem::Dict(view_state)
+    }
+
+    // whether a task passes the completion, flagged and duration filters
+    pub fn matches(&self, task: &Task) -> bool {
+        let completion = match self.completion_filter.as_deref() {
+            Some("complete") | Some("completed") => task.completed.is_some(),
+            Some("incomplete") | Some("remaining") | Some("available") => {
+                task.completed.is_none()
+            }
+            _ => true,
+        };
+        let flagged = match self.flagged_filter.as_deref() {
+            Some("flagged") => task.flagged,
+            Some("unflagged") => !task.flagged,
+            _ => true,
+        };
+        let duration = match self.duration_filter.as_deref().map(|d| d.parse::<u64>()) {
+            Some(Ok(max)) => task.estimated_duration.is_some_and(|e| e <= max),
+            _ => true,
+        };
+        completion && flagged && duration
+    }
+}
+
+// struct to represent a perspective. everything but the id and added date is
+// stored in a plist
+#[derive(Debug, PartialEq, Clone)]
 pub struct Perspective {
     // metadata
     pub id: ID,
-    pub added: DateTime<Utc>
+    pub added: DateTime<Utc>,
+    pub modified: Option<DateTime<Utc>>,
     // attributes
+    pub name: Option<String>,
+    pub icon: Option<String>,    // "iconNameInBundle"
+    pub uuid: Option<String>,
+    pub version: Option<String>, // the version of OmniFocus that saved it
+    pub rules: Vec<Rule>,        // the parsed "filterRules"
+    pub aggregate: Aggregate,    // how the rules are combined
+    pub view_state: ViewState,
+    // the plist as it was read, including keys not modeled above
+    pub plist: BTreeMap<String, PlistItem>,
+    // everything else in the record, ex: <icon-attachment>
+    pub unknown: Unknown,
+}
+
+// the version of OmniFocus new perspectives claim to be written by
+pub const PERSPECTIVE_VERSION: &str = "3.0.0.2";
+
+// the fields of a perspective that can be in an op="update" record, see
+// TaskField for why this exists. everything but the dates is in the plist
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum PerspectiveField {
+    Added,
+    Modified,
+    Plist,
+}
+
+impl Perspective {
+    // an empty perspective that shows every task
+    pub fn new(name: &str) -> Perspective {
+        let id = generate_id();
+        Perspective {
+            uuid: Some(id.clone()),
+            id,
+            added: Utc::now(),
+            modified: Some(Utc::now()),
+            name: Some(name.into()),
+            icon: None,
+            version: Some(PERSPECTIVE_VERSION.into()),
+            rules: vec![],
+            aggregate: Aggregate::All,
+            view_state: ViewState::default(),
+            plist: BTreeMap::new(),
+            unknown: Unknown::default(),
+        }
+    }
+
+    // copies only the given fields from other onto self
+    pub fn merge(&mut self, other: &Perspective, fields: &[PerspectiveField]) {
+        for field in fields {
+            match field {
+                PerspectiveField::Added => self.added = other.added,
+                PerspectiveField::Modified => self.modified = other.modified,
+                PerspectiveField::Plist => {
+                    self.name = other.name.clone();
+                    self.icon = other.icon.clone();
+                    self.uuid = other.uuid.clone();
+                    self.version = other.version.clone();
+                    self.rules = other.rules.clone();
+                    self.aggregate = other.aggregate;
+                    self.view_state = other.view_state.clone();
+                    self.plist = other.plist.clone();
+                }
+            }
+        }
+    }
+
+    // reads a perspective out of its plist
+    pub fn from_plist(
+        id: ID,
+        added: DateTime<Utc>,
+        plist: BTreeMap<String, PlistItem>,
+    ) -> Result<Perspective, crate::error::Error> {
+        let get = |key: &str| plist.get(key).and_then(|v| v.as_str()).map(|s| s.to_string());
+
+        let rules = match get("filterRules") {
+            Some(json) => rule::parse_rules(&json)?,
+            None => vec![],
+        };
+        let aggregate = match get("filterRulesAggregation") {
+            Some(a) => a.parse()?,
+            None => Aggregate::All,
+        };
+        let view_state = plist.get("viewState")
+            .map(ViewState::from_plist)
+            .unwrap_or_default();
+
+        Ok(Perspective {
+            id,
+            added,
+            modified: None,
+            name: get("name"),
+            icon: get("iconNameInBundle"),
+            uuid: get("uuid"),
+            version: get("version"),
+            rules,
+            aggregate,
+            view_state,
+            plist,
+            unknown: Unknown::default(),
+        })
+    }
+
+    // the plist to write for this perspective. the modeled fields are written
+    // over the plist it was read from so unknown keys survive an edit
+    pub fn to_plist(&self) -> Result<BTreeMap<String, PlistItem>, crate::error::Error> {
+        let rules = rule::write_rules(&self.rules)?;
+        let mut plist = self.plist.clone();
+        let mut set = |key: &str, value: Option<&String>| match value {
+            Some(v) => plist.insert(key.into(), PlistItem::String(v.clone())),
+            None => plist.remove(key),
+        };
+
+        set("name", self.name.as_ref());
+        set("iconNameInBundle", self.icon.as_ref());
+        set("uuid", self.uuid.as_ref());
+        set("version", self.version.as_ref());
+        set("filterRules", Some(&rules));
+        // "all" is the default and OmniFocus leaves it out
+        if self.aggregate != Aggregate::All || self.plist.contains_key("filterRulesAggregation") {
+            let aggregate = self.aggregate.as_str().to_string();
+            set("filterRulesAggregation", Some(&aggregate));
+        }
+        let view_state = self.view_state.to_plist(plist.get("viewState"));
+        plist.insert("viewState".into(), view_state);
+        Ok(plist)
+    }
+
+    // the tasks shown by this perspective, ungrouped but sorted
+    pub fn tasks<'a>(&self, content: &'a Content) -> Vec<&'a Task> {
+        let mut tasks: Vec<&Task> = rule::filter(&self.rules, self.aggregate, content)
+            .into_iter()
+            .filter(|t| self.view_state.matches(t))
+            .collect();
+        sort_tasks(&mut tasks, &self.view_state.sort, content);
+        tasks
+    }
+
+    // the tasks shown by this perspective grouped and sorted the way
+    // OmniFocus shows them
+    pub fn apply<'a>(&self, content: &'a Content) -> Vec<Group<'a>> {
+        let mut groups: Vec<(GroupKey, Group<'a>)> = Vec::new();
+        for task in self.tasks(content) {
+            let (key, title) = group_of(task, &self.view_state.collation, content);
+            match groups.iter_mut().find(|(k, _)| *k == key) {
+                Some((_, group)) => group.tasks.push(task),
+                None => groups.push((key, Group { title, tasks: vec![task] })),
+            }
+        }
+        groups.sort_by(|(a, _), (b, _)| a.cmp(b));
+        groups.into_iter().map(|(_, g)| g).collect()
+    }
+}
+
+// a heading in a perspective and the tasks under it
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Group<'a> {
+    pub title: String,
+    pub tasks: Vec<&'a Task>,
+}
+
+// used to put groups in order. missing values go last
+type GroupKey = (bool, i64, String);
+
+// the group a task belongs in and its title
+fn group_of(task: &Task, by: &TaskProperty, content: &Content) -> (GroupKey, String) {
+    // days are grouped in the same order sort_tasks puts them in
+    let date = |date: Option<DateTime<Utc>>, none: &str| match date {
+        Some(d) => {
+            let day = d.with_timezone(&Local).date_naive();
+            let mut key = day.and_time(NaiveTime::MIN).and_utc().timestamp();
+            if matches!(by, TaskProperty::Completed | TaskProperty::Added | TaskProperty::Modified) {
+                key = -key;
+            }
+            ((false, key, String::new()), day.format("%a, %b %-d %Y").to_string())
+        }
+        None => ((true, 0, String::new()), none.to_string()),
+    };
+
+    match by {
+        TaskProperty::Project => match content.project_for(task) {
+            Some(p) => {
+                let key = (false, p.task.rank.unwrap_or(0), p.task.id.clone());
+                (key, p.task.title.clone())
+            }
+            None => ((true, 0, String::new()), "Inbox".into()),
+        },
+        TaskProperty::Tag => match content.tags_for(task).first() {
+            Some(tag) => {
+                let path = content.tag_path(&tag.id).unwrap_or_default();
+                ((false, 0, path.clone()), path)
+            }
+            None => ((true, 0, String::new()), "No Tags".into()),
+        },
+        TaskProperty::Due => date(task.due, "No Due Date"),
+        TaskProperty::Defer => date(task.start, "No Defer Date"),
+        TaskProperty::Completed => date(task.completed, "Remaining"),
+        TaskProperty::Added => date(Some(task.added), ""),
+        TaskProperty::Modified => date(task.modified, ""),
+        TaskProperty::Flagged => {
+            let title = if task.flagged { "Flagged" } else { "Unflagged" };
+            ((!task.flagged, 0, String::new()), title.into())
+        }
+        _ => ((false, 0, String::new()), String::new()),
+    }
+}
+
+// sorts tasks in place. dates that show recent activity put the newest first,
+// upcoming dates put the soonest first. tasks without a value go last
+fn sort_tasks(tasks: &mut [&Task], by: &TaskProperty, content: &Content) {
+    fn last<T: Ord>(v: Option<T>) -> (bool, Option<T>) {
+        (v.is_none(), v)
+    }
+    fn newest(v: Option<DateTime<Utc>>) -> (bool, Option<std::cmp::Reverse<DateTime<Utc>>>) {
+        (v.is_none(), v.map(std::cmp::Reverse))
+    }
+
+    match by {
+        TaskProperty::Due => tasks.sort_by_key(|t| last(t.due)),
+        TaskProperty::Defer => tasks.sort_by_key(|t| last(t.start)),
+        TaskProperty::Completed => tasks.sort_by_key(|t| newest(t.completed)),
+        TaskProperty::Added => tasks.sort_by_key(|t| newest(Some(t.added))),
+        TaskProperty::Modified => tasks.sort_by_key(|t| newest(t.modified)),
+        TaskProperty::Flagged => tasks.sort_by_key(|t| !t.flagged),
+        TaskProperty::Title => tasks.sort_by_key(|t| t.title.to_lowercase()),
+        TaskProperty::Duration => tasks.sort_by_key(|t| last(t.estimated_duration)),
+        TaskProperty::Project => tasks.sort_by_key(|t| {
+            last(content.project_for(t).map(|p| p.task.rank.unwrap_or(0)))
+        }),
+        TaskProperty::Tag => tasks.sort_by_key(|t| {
+            last(content.tags_for(t).first().and_then(|tag| content.tag_path(&tag.id)))
+        }),
+        TaskProperty::None | TaskProperty::Other(_) => {}
+    }
+}
+
+// queries over perspectives
+impl Content {
+    // finds a perspective by its name, ignoring case
+    pub fn perspective(&self, name: &str) -> Option<&Perspective> {
+        self.perspectives.iter()
+            .find(|p| p.name.as_deref().is_some_and(|n| n.eq_ignore_ascii_case(name)))
+    }
+
+    // all of the perspectives sorted by name
+    pub fn perspectives_by_name(&self) -> Vec<&Perspective> {
+        let mut perspectives: Vec<&Perspective> = self.perspectives.iter().collect();
+        perspectives.sort_by_key(|p| p.name.as_deref().unwrap_or("").to_lowercase());
+        perspectives
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::project::ProjectInfo;
+
+    fn plist(collation: &str, sort: &str) -> BTreeMap<String, PlistItem> {
+        let string = |s: &str| PlistItem::String(s.into());
+        let dict = |pairs: Vec<(&str, PlistItem)>| {
+            PlistItem::Dict(pairs.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+        };
+
+        let view_state = dict(vec![
+            ("viewMode", string("project")),
+            ("viewModeState", dict(vec![("project", dict(vec![
+                ("collation", string(collation)),
+                ("sort", string(sort)),
+                ("actionCompletionFilter", string("incomplete")),
+            ]))])),
+        ]);
+        vec![
+            ("name".to_string(), string("Test")),
+            ("filterRules".to_string(), string("[]")),
+            ("viewState".to_string(), view_state),
+        ].into_iter().collect()
+    }
+
+    #[test]
+    fn test_group_and_sort() {
+        let project = |id: &str, rank: i64| Task {
+            id: id.into(),
+            title: id.to_uppercase(),
+            rank: Some(rank),
+            project: Some(ProjectInfo::default()),
+            ..Task::default()
+        };
+        let task = |id: &str, parent: &str| Task {
+            id: id.into(),
+            title: id.into(),
+            parent: Some(parent.into()),
+            ..Task::default()
+        };
+        let content = Content {
+            tasks: vec![
+                project("b", 2),
+                project("a", 1),
+                task("zeta", "a"),
+                task("alpha", "a"),
+                task("beta", "b"),
+                Task { completed: Some(Utc::now()), ..task("done", "b") },
+            ],
+            ..Content::default()
+        };
+
+        let perspective = Perspective::from_plist(
+            "test".into(),
+            Utc::now(),
+            plist("project", "name"),
+        ).unwrap();
+        assert!(perspective.view_state.view_mode == ViewMode::Project);
+        assert!(perspective.view_state.sort == TaskProperty::Title);
+
+        let groups = perspective.apply(&content);
+        let titles: Vec<&str> = groups.iter().map(|g| g.title.as_str()).collect();
+        assert!(titles == vec!["A", "B"]);
+        let ids: Vec<&str> = groups[0].tasks.iter().map(|t| t.id.as_str()).collect();
+        assert!(ids == vec!["a", "alpha", "zeta"]);
+        assert!(groups[1].tasks.len() == 2);
+    }
+
+    #[test]
+    fn test_to_plist_keeps_unknown_keys() {
+        let mut plist = plist("project", "name");
+        plist.insert("somethingNew".into(), PlistItem::Integer(1));
+        let mut perspective = Perspective::from_plist("test".into(), Utc::now(), plist)
+            .unwrap();
+
+        perspective.name = Some("Renamed".into());
+        perspective.view_state.sort = TaskProperty::Due;
+        perspective.rules = vec![Rule::Status(rule::Status::Flagged)];
+        perspective.aggregate = Aggregate::Any;
+
+        let written = perspective.to_plist().unwrap();
+        assert!(written.get("somethingNew") == Some(&PlistItem::Integer(1)));
+        let read = Perspective::from_plist("test".into(), perspective.added, written)
+            .unwrap();
+        assert!(read.name == Some("Renamed".into()));
+        assert!(read.view_state == perspective.view_state);
+        assert!(read.rules == perspective.rules);
+        assert!(read.aggregate == Aggregate::Any);
+    }
 }
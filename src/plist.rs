@@ -1,74 +1,270 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use chrono::prelude::*;
 use xml::reader::XmlEvent;
+use xml::writer::{EmitterConfig, EventWriter, XmlEvent as WriterEvent};
 
 use crate::error::*;
-use crate::parse::{name_to_str, get_text_content};
+use crate::parse::{Reader, name_to_str};
 
 // helpers to handle OmniFocus's use of Apple's Property List (plist) format
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum PlistItem {
     String(String),
-    Dict(HashMap<String, PlistItem>),
+    Dict(BTreeMap<String, PlistItem>),
+    Array(Vec<PlistItem>),
+    Date(DateTime<Utc>),
+    Integer(i64),
+    Real(f64),
+    Bool(bool),
+    Data(Vec<u8>),
 }
 
 impl PlistItem {
-    pub fn unwrap_string<'a>(&'a self) -> &'a String {
+    pub fn as_str(&self) -> Option<&str> {
         match self {
-            PlistItem::String(s) => s,
-            _ => panic!()
+            PlistItem::String(s) => Some(s),
+            _ => None,
         }
     }
 
-    pub fn unwrap_dict<'a>(&'a self) -> &'a HashMap<String, PlistItem> {
+    pub fn as_dict(&self) -> Option<&BTreeMap<String, PlistItem>> {
         match self {
-            PlistItem::Dict(d) => d,
-            _ => panic!()
+            PlistItem::Dict(d) => Some(d),
+            _ => None,
         }
     }
+
+    pub fn as_array(&self) -> Option<&[PlistItem]> {
+        match self {
+            PlistItem::Array(a) => Some(a),
+            _ => None,
+        }
+    }
+
+    pub fn as_date(&self) -> Option<DateTime<Utc>> {
+        match self {
+            PlistItem::Date(d) => Some(*d),
+            _ => None,
+        }
+    }
+
+    pub fn as_integer(&self) -> Option<i64> {
+        match self {
+            PlistItem::Integer(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    // integers are also valid reals
+    pub fn as_real(&self) -> Option<f64> {
+        match self {
+            PlistItem::Real(r) => Some(*r),
+            PlistItem::Integer(i) => Some(*i as f64),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            PlistItem::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_data(&self) -> Option<&[u8]> {
+        match self {
+            PlistItem::Data(d) => Some(d),
+            _ => None,
+        }
+    }
+
+    // looks up a value inside of nested dicts, ex: ["viewState", "viewMode"]
+    pub fn get_path(&self, path: &[&str]) -> Option<&PlistItem> {
+        path.iter().try_fold(self, |item, key| item.as_dict()?.get(*key))
+    }
 }
 
-// TODO: use dynamic something or other do decouple this from ZipFile
-pub fn parse_plist<'a>(
-    mut parser: &mut xml::reader::Events<zip::read::ZipFile<'a>>,
-) -> Result<PlistItem, Error> {
-    if let Some(Ok(XmlEvent::StartElement { name, .. })) = parser.next() {
-        match name_to_str(&name) {
-            "string" => {
-                Ok(PlistItem::String(get_text_content(parser.next())?))
-            }
-            "dict" => {
-                Ok(PlistItem::Dict(parse_plist_dict(&mut parser)?))
+// the next start or end tag, skipping over the whitespace between them
+fn next_tag<R: Read>(
+    parser: &mut Reader<R>,
+) -> Result<XmlEvent, Error> {
+    for evt in parser {
+        match evt? {
+            XmlEvent::Whitespace(_) | XmlEvent::Comment(_) => {}
+            evt => return Ok(evt),
+        }
+    }
+    Err(crate::err!(Parse))
+}
+
+// the text inside of an element up to and including its closing tag. unlike
+// get_text_content this handles empty elements like <string></string>
+fn read_text<R: Read>(
+    parser: &mut Reader<R>,
+) -> Result<String, Error> {
+    let mut text = String::new();
+    for evt in parser {
+        match evt? {
+            XmlEvent::Characters(t) | XmlEvent::Whitespace(t) | XmlEvent::CData(t) => {
+                text.push_str(&t)
             }
-            _ => Err(crate::err!(Parse))
+            XmlEvent::EndElement { .. } => return Ok(text),
+            _ => return Err(crate::err!(Parse)),
         }
-    } else {
-        Err(crate::err!(Parse))
     }
+    Err(crate::err!(Parse))
 }
 
-// turns <dict><key>...</key><whatever>...</whatever></dict> into a HashMap
-pub fn parse_plist_dict<'a>(
-    parser: &mut xml::reader::Events<zip::read::ZipFile<'a>>,
-) -> Result<HashMap<String, PlistItem>, Error> {
-    let mut map = HashMap::new();
+// reads a standalone plist document, ex: a .client file
+pub fn read_plist<R: Read>(reader: R) -> Result<PlistItem, Error> {
+    let mut parser = Reader::new(reader);
+    // skip the XML declaration and doctype up to <plist>
     while let Some(evt) = parser.next() {
-        match evt {
-            Ok(XmlEvent::StartElement { name, .. }) => {
-                assert!(name_to_str(&name) == "key");
-                let key = get_text_content(parser.next())?;
-                parser.next();
+        if let XmlEvent::StartElement { name, .. } = evt? {
+            if name_to_str(&name) == "plist" {
+                return parse_plist(&mut parser);
+            }
+            return Err(crate::err!(Parse));
+        }
+    }
+    Err(crate::err!(Parse))
+}
+
+pub fn parse_plist<R: Read>(
+    parser: &mut Reader<R>,
+) -> Result<PlistItem, Error> {
+    match next_tag(parser)? {
+        XmlEvent::StartElement { name, .. } => parse_plist_value(name_to_str(&name), parser),
+        _ => Err(crate::err!(Parse)),
+    }
+}
+
+// parses the value of an element whose start tag has already been read
+fn parse_plist_value<R: Read>(
+    tag: &str,
+    parser: &mut Reader<R>,
+) -> Result<PlistItem, Error> {
+    match tag {
+        "string" => Ok(PlistItem::String(read_text(parser)?)),
+        "dict" => Ok(PlistItem::Dict(parse_plist_dict(parser)?)),
+        "array" => Ok(PlistItem::Array(parse_plist_array(parser)?)),
+        "date" => Ok(PlistItem::Date(read_text(parser)?.trim().parse()?)),
+        "integer" => Ok(PlistItem::Integer(read_text(parser)?.trim().parse()?)),
+        "real" => Ok(PlistItem::Real(read_text(parser)?.trim().parse()?)),
+        "data" => {
+            let text: String = read_text(parser)?.split_whitespace().collect();
+            Ok(PlistItem::Data(base64::decode(&text)?))
+        }
+        // <true/> and <false/>
+        "true" | "false" => {
+            read_text(parser)?;
+            Ok(PlistItem::Bool(tag == "true"))
+        }
+        _ => Err(crate::err!(Parse)),
+    }
+}
+
+// turns <dict><key>...</key><whatever>...</whatever></dict> into a map
+pub fn parse_plist_dict<R: Read>(
+    parser: &mut Reader<R>,
+) -> Result<BTreeMap<String, PlistItem>, Error> {
+    let mut map = BTreeMap::new();
+    loop {
+        match next_tag(parser)? {
+            XmlEvent::StartElement { name, .. } if name_to_str(&name) == "key" => {
+                let key = read_text(parser)?;
                 // recursively call parse_plist on the value
                 let value = parse_plist(parser)?;
                 map.insert(key, value);
-            },
-            Ok(XmlEvent::EndElement { name, .. }) => {
-                if name_to_str(&name) == "dict" {
-                    break
-                }
-            },
+            }
+            XmlEvent::EndElement { name } if name_to_str(&name) == "dict" => break,
             _ => return Err(crate::err!(Parse)),
         }
     }
     Ok(map)
 }
+
+// turns <array><whatever>...</whatever>...</array> into a Vec
+fn parse_plist_array<R: Read>(
+    parser: &mut Reader<R>,
+) -> Result<Vec<PlistItem>, Error> {
+    let mut items = Vec::new();
+    loop {
+        match next_tag(parser)? {
+            XmlEvent::StartElement { name, .. } => {
+                items.push(parse_plist_value(name_to_str(&name), parser)?);
+            }
+            XmlEvent::EndElement { name } if name_to_str(&name) == "array" => break,
+            _ => return Err(crate::err!(Parse)),
+        }
+    }
+    Ok(items)
+}
+
+// writes a standalone plist document with the same header OmniFocus uses,
+// ex: a .client file
+pub fn write_plist_file<W: Write>(mut writer: W, item: &PlistItem) -> Result<(), Error> {
+    writer.write_all(concat!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+        "<!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" ",
+        "\"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n",
+    ).as_bytes())?;
+    let mut xml = EmitterConfig::new()
+        .perform_indent(true)
+        .write_document_declaration(false)
+        .create_writer(writer);
+    write_plist(&mut xml, item)?;
+    Ok(())
+}
+
+// writes <plist version="1.0">{item}</plist>
+pub fn write_plist<W: Write>(xml: &mut EventWriter<W>, item: &PlistItem) -> Result<(), Error> {
+    xml.write(WriterEvent::start_element("plist").attr("version", "1.0"))?;
+    write_plist_value(xml, item)?;
+    xml.write(WriterEvent::end_element())?;
+    Ok(())
+}
+
+// writes a single value, recursing into dicts and arrays. dict keys come out
+// sorted which is also how OmniFocus writes them
+fn write_plist_value<W: Write>(xml: &mut EventWriter<W>, item: &PlistItem) -> Result<(), Error> {
+    // write the form <tag>text</tag>
+    fn text<W: Write>(xml: &mut EventWriter<W>, tag: &str, text: &str) -> Result<(), Error> {
+        xml.write(WriterEvent::start_element(tag))?;
+        xml.write(WriterEvent::characters(text))?;
+        xml.write(WriterEvent::end_element())?;
+        Ok(())
+    }
+
+    match item {
+        PlistItem::String(s) => text(xml, "string", s)?,
+        PlistItem::Dict(d) => {
+            xml.write(WriterEvent::start_element("dict"))?;
+            for (key, value) in d.iter() {
+                text(xml, "key", key)?;
+                write_plist_value(xml, value)?;
+            }
+            xml.write(WriterEvent::end_element())?;
+        }
+        PlistItem::Array(a) => {
+            xml.write(WriterEvent::start_element("array"))?;
+            for value in a.iter() {
+                write_plist_value(xml, value)?;
+            }
+            xml.write(WriterEvent::end_element())?;
+        }
+        PlistItem::Date(d) => {
+            text(xml, "date", &d.to_rfc3339_opts(SecondsFormat::Secs, true))?
+        }
+        PlistItem::Integer(i) => text(xml, "integer", &i.to_string())?,
+        PlistItem::Real(r) => text(xml, "real", &r.to_string())?,
+        PlistItem::Bool(b) => {
+            xml.write(WriterEvent::start_element(if *b { "true" } else { "false" }))?;
+            xml.write(WriterEvent::end_element())?;
+        }
+        PlistItem::Data(d) => text(xml, "data", &base64::encode(d))?,
+    }
+    Ok(())
+}
@@ -0,0 +1,187 @@
+use chrono::prelude::*;
+
+use crate::util::ID;
+use crate::error::*;
+use crate::parse::Content;
+use crate::task::{Task, Item, SubtaskOrder};
+use crate::note::Note;
+use crate::unknown::Unknown;
+use crate::tag::Tag;
+
+// enumeration of the states a project can be in
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ProjectStatus {
+    Active,
+    OnHold,
+    Done,
+    Dropped,
+}
+
+impl std::str::FromStr for ProjectStatus {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "active" => Ok(ProjectStatus::Active),
+            "inactive" => Ok(ProjectStatus::OnHold),
+            "done" => Ok(ProjectStatus::Done),
+            "dropped" => Ok(ProjectStatus::Dropped),
+            _ => Err(crate::err!(Parse)),
+        }
+    }
+}
+
+impl ProjectStatus {
+    // the string used for this status in <status>
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ProjectStatus::Active => "active",
+            ProjectStatus::OnHold => "inactive",
+            ProjectStatus::Done => "done",
+            ProjectStatus::Dropped => "dropped",
+        }
+    }
+}
+
+// the extra data a task has when it is a project. this is the <project> block
+// inside of a <task>, which is empty for regular tasks
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ProjectInfo {
+    pub folder: Option<ID>,
+    pub singleton: bool, // single action lists are "singleton" projects
+    pub last_review: Option<DateTime<Utc>>,
+    pub next_review: Option<DateTime<Utc>>,
+    pub review_interval: Option<String>, // ex: "@1w"
+    pub status: ProjectStatus,
+    pub unknown: Unknown, // elements inside of <project> that aren't modeled
+}
+
+// self explanatory
+impl Default for ProjectInfo {
+    fn default() -> Self {
+        ProjectInfo {
+            folder: None,
+            singleton: false,
+            last_review: Some(Utc::now()),
+            next_review: None,
+            review_interval: Some("@1w".into()),
+            status: ProjectStatus::Active,
+            unknown: Unknown::default(),
+        }
+    }
+}
+
+// a view of a task that is known to be a project
+#[derive(Debug, Clone, Copy)]
+pub struct Project<'a> {
+    pub task: &'a Task,
+    pub info: &'a ProjectInfo,
+}
+
+impl<'a> Project<'a> {
+    // creates a project view of a task if the task is a project
+    pub fn new(task: &'a Task) -> Option<Project<'a>> {
+        task.project.as_ref().map(|info| Project { task, info })
+    }
+
+    pub fn status(&self) -> ProjectStatus {
+        self.info.status
+    }
+
+    pub fn folder(&self) -> Option<&'a ID> {
+        self.info.folder.as_ref()
+    }
+
+    // the direct children of the project, sorted by rank
+    pub fn tasks(&self, content: &'a Content) -> Vec<&'a Task> {
+        let mut tasks: Vec<&Task> = content.tasks.iter()
+            .filter(|t| t.parent.as_ref() == Some(&self.task.id))
+            .collect();
+        tasks.sort_by_key(|t| t.rank);
+        tasks
+    }
+}
+
+// a project is everything a task is
+impl<'a> Item for Project<'a> {
+    fn id(&self) -> &ID { self.task.id() }
+    fn parent(&self) -> Option<&ID> { self.task.parent() }
+    fn rank(&self) -> Option<i64> { self.task.rank() }
+    fn added(&self) -> DateTime<Utc> { self.task.added() }
+    fn modified(&self) -> Option<DateTime<Utc>> { self.task.modified() }
+    fn title(&self) -> &str { self.task.title() }
+    fn note(&self) -> Option<&Note> { self.task.note() }
+    fn context(&self) -> Option<&ID> { self.task.context() }
+    fn flagged(&self) -> bool { self.task.flagged() }
+    fn due(&self) -> Option<DateTime<Utc>> { self.task.due() }
+    fn start(&self) -> Option<DateTime<Utc>> { self.task.start() }
+    fn completed(&self) -> Option<DateTime<Utc>> { self.task.completed() }
+    fn order(&self) -> Option<&SubtaskOrder> { self.task.order() }
+    fn tags<'b>(&self, content: &'b Content) -> Vec<&'b Tag> { self.task.tags(content) }
+    fn is_repeating(&self) -> bool { self.task.is_repeating() }
+}
+
+// queries over projects
+impl Content {
+    // all of the projects, sorted by rank
+    pub fn projects(&self) -> Vec<Project<'_>> {
+        let mut projects: Vec<Project<'_>> =
+            self.tasks.iter().filter_map(Project::new).collect();
+        projects.sort_by_key(|p| p.task.rank);
+        projects
+    }
+
+    // finds a project by id
+    pub fn project(&self, id: &str) -> Option<Project<'_>> {
+        self.tasks.iter().find(|t| t.id == id).and_then(Project::new)
+    }
+
+    // the project a task is inside of, which is the task itself for projects.
+    // None for inbox tasks or if the parents form a cycle
+    pub fn project_for<'a>(&'a self, task: &'a Task) -> Option<Project<'a>> {
+        let mut visited: Vec<&ID> = Vec::new();
+        let mut curr = task;
+        loop {
+            if let Some(project) = Project::new(curr) {
+                return Some(project);
+            }
+            if visited.contains(&&curr.id) {
+                return None;
+            }
+            visited.push(&curr.id);
+            let parent = curr.parent.as_ref()?;
+            curr = self.tasks.iter().find(|t| &t.id == parent)?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tag::TaskTag;
+
+    #[test]
+    fn test_project_tasks() {
+        let project = Task {
+            id: "proj".into(),
+            project: Some(ProjectInfo::default()),
+            ..Task::default()
+        };
+        let child = Task { parent: Some("proj".into()), ..Task::default() };
+        let content = Content {
+            tasks: vec![project, child, Task::default()],
+            tags: vec![Tag { id: "tag".into(), ..Tag::default() }],
+            task_tags: vec![TaskTag::new("proj", "tag")],
+            ..Content::default()
+        };
+
+        let projects = content.projects();
+        assert!(projects.len() == 1);
+        assert!(projects[0].tasks(&content).len() == 1);
+        assert!(content.project("proj").unwrap().status() == ProjectStatus::Active);
+        assert!(projects[0].tags(&content)[0].id == "tag");
+        assert!(Project::new(&content.tasks[1]).is_none());
+        assert!(content.project_for(&content.tasks[1]).unwrap().task.id == "proj");
+        assert!(content.project_for(&content.tasks[2]).is_none());
+    }
+}
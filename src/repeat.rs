@@ -0,0 +1,799 @@
+use std::fmt;
+use chrono::prelude::*;
+use chrono::{Duration, Months};
+
+use crate::error::*;
+use crate::parse::{Content, TaskUpdate};
+use crate::task::Task;
+use crate::tag::TaskTag;
+
+// helpers for repeating tasks. a repeating task has a <repetition-rule> which
+// is the subset of an RFC 5545 RRULE that OmniFocus uses, ex:
+// "FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,FR", and a <repetition-method> which says
+// which date the next occurrence is counted from
+
+// enumeration of the units a rule can repeat in
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Frequency {
+    Minutely,
+    Hourly,
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+impl std::str::FromStr for Frequency {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "MINUTELY" => Ok(Frequency::Minutely),
+            "HOURLY" => Ok(Frequency::Hourly),
+            "DAILY" => Ok(Frequency::Daily),
+            "WEEKLY" => Ok(Frequency::Weekly),
+            "MONTHLY" => Ok(Frequency::Monthly),
+            "YEARLY" => Ok(Frequency::Yearly),
+            _ => Err(crate::err!(Parse)),
+        }
+    }
+}
+
+impl Frequency {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Frequency::Minutely => "MINUTELY",
+            Frequency::Hourly => "HOURLY",
+            Frequency::Daily => "DAILY",
+            Frequency::Weekly => "WEEKLY",
+            Frequency::Monthly => "MONTHLY",
+            Frequency::Yearly => "YEARLY",
+        }
+    }
+}
+
+// an entry in BYDAY. "MO" is every monday, "2MO" the second monday of the
+// month and "-1FR" the last friday
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct WeekdayNum {
+    pub ordinal: Option<i32>,
+    pub weekday: Weekday,
+}
+
+impl std::str::FromStr for WeekdayNum {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() < 2 || !s.is_char_boundary(s.len() - 2) {
+            return Err(crate::err!(Parse));
+        }
+        let (ordinal, day) = s.split_at(s.len() - 2);
+        let weekday = match day {
+            "MO" => Weekday::Mon,
+            "TU" => Weekday::Tue,
+            "WE" => Weekday::Wed,
+            "TH" => Weekday::Thu,
+            "FR" => Weekday::Fri,
+            "SA" => Weekday::Sat,
+            "SU" => Weekday::Sun,
+            _ => return Err(crate::err!(Parse)),
+        };
+        let ordinal = if ordinal.is_empty() {
+            None
+        } else {
+            Some(ordinal.trim_start_matches('+').parse()?)
+        };
+        Ok(WeekdayNum { ordinal, weekday })
+    }
+}
+
+impl fmt::Display for WeekdayNum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(ordinal) = self.ordinal {
+            write!(f, "{}", ordinal)?;
+        }
+        let day = match self.weekday {
+            Weekday::Mon => "MO",
+            Weekday::Tue => "TU",
+            Weekday::Wed => "WE",
+            Weekday::Thu => "TH",
+            Weekday::Fri => "FR",
+            Weekday::Sat => "SA",
+            Weekday::Sun => "SU",
+        };
+        write!(f, "{}", day)
+    }
+}
+
+// a parsed <repetition-rule>
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct RepetitionRule {
+    pub freq: Frequency,
+    pub interval: u32,
+    pub by_day: Vec<WeekdayNum>,
+    pub by_month_day: Vec<i32>,
+    pub by_month: Vec<u32>,
+    pub by_set_pos: Vec<i32>,
+    pub count: Option<u32>,
+    pub until: Option<DateTime<Utc>>,
+    // parts we don't understand, kept so the rule is written back unchanged
+    pub other: Vec<(String, String)>,
+}
+
+impl RepetitionRule {
+    // a rule that repeats every interval units
+    pub fn new(freq: Frequency, interval: u32) -> RepetitionRule {
+        RepetitionRule {
+            freq,
+            interval,
+            by_day: vec![],
+            by_month_day: vec![],
+            by_month: vec![],
+            by_set_pos: vec![],
+            count: None,
+            until: None,
+            other: vec![],
+        }
+    }
+}
+
+// parses a list of values separated by commas, ex: "MO,WE,FR"
+fn parse_list<T: std::str::FromStr>(s: &str) -> Result<Vec<T>, Error> {
+    s.split(',')
+        .map(|v| v.parse().map_err(|_| crate::err!(Parse) as Error))
+        .collect()
+}
+
+// UNTIL is either a date ("20191231") or a UTC date time ("20191231T120000Z")
+fn parse_until(s: &str) -> Result<DateTime<Utc>, Error> {
+    if let Ok(dt) = NaiveDateTime::parse_from_str(s, "%Y%m%dT%H%M%SZ") {
+        return Ok(Utc.from_utc_datetime(&dt));
+    }
+    let date = NaiveDate::parse_from_str(s, "%Y%m%d")?;
+    Ok(Utc.from_utc_datetime(&date.and_time(NaiveTime::MIN)))
+}
+
+impl std::str::FromStr for RepetitionRule {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut freq: Option<Frequency> = None;
+        let mut rule = RepetitionRule::new(Frequency::Daily, 1);
+
+        for part in s.split(';').filter(|p| !p.is_empty()) {
+            let (key, value) = match part.split_once('=') {
+                Some(kv) => kv,
+                None => return Err(crate::err!(Parse)),
+            };
+            match key {
+                "FREQ" => freq = Some(value.parse()?),
+                "INTERVAL" => rule.interval = value.parse()?,
+                "BYDAY" => rule.by_day = parse_list(value)?,
+                "BYMONTHDAY" => rule.by_month_day = parse_list(value)?,
+                "BYMONTH" => rule.by_month = parse_list(value)?,
+                "BYSETPOS" => rule.by_set_pos = parse_list(value)?,
+                "COUNT" => rule.count = Some(value.parse()?),
+                "UNTIL" => rule.until = Some(parse_until(value)?),
+                _ => rule.other.push((key.into(), value.into())),
+            }
+        }
+
+        match freq {
+            Some(freq) => {
+                rule.freq = freq;
+                Ok(rule)
+            }
+            None => Err(crate::err!(Parse)),
+        }
+    }
+}
+
+// turns a rule back into the string used in <repetition-rule>
+impl fmt::Display for RepetitionRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn join<T: ToString>(values: &[T]) -> String {
+            values.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(",")
+        }
+
+        let mut parts = vec![format!("FREQ={}", self.freq.as_str())];
+        if self.interval != 1 {
+            parts.push(format!("INTERVAL={}", self.interval));
+        }
+        if !self.by_day.is_empty() {
+            parts.push(format!("BYDAY={}", join(&self.by_day)));
+        }
+        if !self.by_month_day.is_empty() {
+            parts.push(format!("BYMONTHDAY={}", join(&self.by_month_day)));
+        }
+        if !self.by_month.is_empty() {
+            parts.push(format!("BYMONTH={}", join(&self.by_month)));
+        }
+        if !self.by_set_pos.is_empty() {
+            parts.push(format!("BYSETPOS={}", join(&self.by_set_pos)));
+        }
+        if let Some(count) = self.count {
+            parts.push(format!("COUNT={}", count));
+        }
+        if let Some(until) = self.until {
+            parts.push(format!("UNTIL={}", until.format("%Y%m%dT%H%M%SZ")));
+        }
+        for (key, value) in self.other.iter() {
+            parts.push(format!("{}={}", key, value));
+        }
+        write!(f, "{}", parts.join(";"))
+    }
+}
+
+// the number of days in the given month
+fn days_in_month(year: i32, month: u32) -> u32 {
+    let first = NaiveDate::from_ymd_opt(year, month, 1).unwrap();
+    let next = first.checked_add_months(Months::new(1)).unwrap();
+    next.signed_duration_since(first).num_days() as u32
+}
+
+impl RepetitionRule {
+    // checks that the rule only uses the parts next_after() knows how to
+    // schedule, so a task is never moved to a date OmniFocus wouldn't pick
+    pub fn check(&self) -> Result<(), Error> {
+        let by_date = !self.by_day.is_empty()
+            || !self.by_month_day.is_empty()
+            || !self.by_set_pos.is_empty();
+        let unschedulable = match self.freq {
+            Frequency::Minutely | Frequency::Hourly => by_date || !self.by_month.is_empty(),
+            Frequency::Daily | Frequency::Weekly => {
+                !self.by_month_day.is_empty()
+                    || !self.by_set_pos.is_empty()
+                    || !self.by_month.is_empty()
+                    || self.by_day.iter().any(|b| b.ordinal.is_some())
+            }
+            Frequency::Monthly => false,
+            // days of the week in a year are counted by week number, which
+            // isn't handled, so they have to be narrowed down to a month
+            Frequency::Yearly => !self.by_day.is_empty() && self.by_month.is_empty(),
+        };
+        // the rest of the BY* parts pick times and days that aren't handled
+        let unknown = self.other.iter().any(|(key, _)| key.starts_with("BY"));
+        if unschedulable || unknown {
+            return Err(crate::err!(InvalidArgument));
+        }
+        Ok(())
+    }
+
+    // the first occurrence strictly after the given date. the time of day is
+    // kept from the given date. None once the rule has ended. COUNT depends on
+    // how many times the task has repeated so is left to next_repetition()
+    pub fn next_after(&self, after: DateTime<Utc>) -> Result<Option<DateTime<Utc>>, Error> {
+        self.check()?;
+        let interval = self.interval.max(1);
+        let plain = self.by_day.is_empty()
+            && self.by_month_day.is_empty()
+            && self.by_month.is_empty();
+        let next = match self.freq {
+            Frequency::Minutely => after.checked_add_signed(Duration::minutes(interval.into())),
+            Frequency::Hourly => after.checked_add_signed(Duration::hours(interval.into())),
+            Frequency::Daily if plain => {
+                after.checked_add_signed(Duration::days(interval.into()))
+            }
+            Frequency::Weekly if plain => {
+                after.checked_add_signed(Duration::weeks(interval.into()))
+            }
+            Frequency::Monthly if plain => after.checked_add_months(Months::new(interval)),
+            Frequency::Yearly if plain => after.checked_add_months(Months::new(interval * 12)),
+            Frequency::Daily | Frequency::Weekly => self.next_day(after, interval),
+            Frequency::Monthly => self.next_in_month(after, interval),
+            Frequency::Yearly => self.next_in_year(after, interval),
+        };
+
+        Ok(match (next, self.until) {
+            (Some(next), Some(until)) if next > until => None,
+            _ => next,
+        })
+    }
+
+    // steps through the days after `after` looking for one on an allowed
+    // weekday. weekly rules only count weeks that are a multiple of the
+    // interval away from the starting week, and daily rules days
+    fn next_day(&self, after: DateTime<Utc>, interval: u32) -> Option<DateTime<Utc>> {
+        let week_start = |d: NaiveDate| {
+            d - Duration::days(d.weekday().num_days_from_monday().into())
+        };
+        let start_week = week_start(after.date_naive());
+
+        (1..=(7 * interval as i64 + 7))
+            .map(|i| after.date_naive() + Duration::days(i))
+            .find(|d| {
+                let steps = match self.freq {
+                    Frequency::Weekly => (week_start(*d) - start_week).num_weeks(),
+                    _ => (*d - after.date_naive()).num_days(),
+                };
+                self.by_day.iter().any(|b| b.weekday == d.weekday())
+                    && steps % interval as i64 == 0
+            })
+            .map(|d| Utc.from_utc_datetime(&d.and_time(after.time())))
+    }
+
+    // checks each month that is a multiple of the interval away for matching
+    // days. gives up after 48 tries in case the rule can never match
+    fn next_in_month(&self, after: DateTime<Utc>, interval: u32) -> Option<DateTime<Utc>> {
+        let first = after.date_naive().with_day(1)?;
+        let months = (0..=48)
+            .map(|i| first.checked_add_months(Months::new(i * interval)))
+            .take_while(|m| m.is_some())
+            .flatten()
+            .filter(|m| self.by_month.is_empty() || self.by_month.contains(&m.month()));
+        self.first_in(after, months)
+    }
+
+    // the same as next_in_month() for the months of BYMONTH in every interval
+    // years, or the month of `after` if there's no BYMONTH
+    fn next_in_year(&self, after: DateTime<Utc>, interval: u32) -> Option<DateTime<Utc>> {
+        let mut by_month = self.by_month.clone();
+        if by_month.is_empty() {
+            by_month.push(after.month());
+        }
+        by_month.sort_unstable();
+
+        let months = (0..=48)
+            .map(|i| after.year() + (i * interval) as i32)
+            .flat_map(|year| {
+                by_month.clone().into_iter().filter_map(move |m| NaiveDate::from_ymd_opt(year, m, 1))
+            });
+        self.first_in(after, months)
+    }
+
+    // the first matching day after `after` in the given months
+    fn first_in(
+        &self,
+        after: DateTime<Utc>,
+        months: impl Iterator<Item = NaiveDate>,
+    ) -> Option<DateTime<Utc>> {
+        for month in months {
+            let found = self.days_in(month.year(), month.month(), after.date_naive()).into_iter()
+                .map(|d| Utc.from_utc_datetime(&d.and_time(after.time())))
+                .find(|d| *d > after);
+            if found.is_some() {
+                return found;
+            }
+        }
+        None
+    }
+
+    // the days of a month the rule matches, in order. with no BYDAY or
+    // BYMONTHDAY it's the same day of the month as `after`
+    fn days_in(&self, year: i32, month: u32, after: NaiveDate) -> Vec<NaiveDate> {
+        if self.by_day.is_empty() && self.by_month_day.is_empty() {
+            return NaiveDate::from_ymd_opt(year, month, after.day()).into_iter().collect();
+        }
+        let len = days_in_month(year, month) as i32;
+        let all = (1..=len).filter_map(|d| NaiveDate::from_ymd_opt(year, month, d as u32));
+
+        // the 1-based position of a day amongst the days in the month with the
+        // same weekday, counting from the start and from the end
+        let position = |d: &NaiveDate| {
+            let from_start = (d.day() as i32 - 1) / 7 + 1;
+            let from_end = -((len - d.day() as i32) / 7 + 1);
+            (from_start, from_end)
+        };
+
+        let mut days: Vec<NaiveDate> = all
+            .filter(|d| {
+                let day = d.day() as i32;
+                let month_day = self.by_month_day.is_empty()
+                    || self.by_month_day.iter()
+                        .any(|&m| m == day || m == day - len - 1);
+                let weekday = self.by_day.is_empty()
+                    || self.by_day.iter().any(|b| {
+                        let (from_start, from_end) = position(d);
+                        b.weekday == d.weekday() && match b.ordinal {
+                            Some(n) => n == from_start || n == from_end,
+                            None => true,
+                        }
+                    });
+                month_day && weekday
+            })
+            .collect();
+
+        // BYSETPOS picks from the matching days, ex: the last weekday
+        if !self.by_set_pos.is_empty() {
+            let len = days.len() as i32;
+            let picked: Vec<NaiveDate> = (1..=len)
+                .filter(|i| self.by_set_pos.iter().any(|&p| p == *i || p == *i - len - 1))
+                .map(|i| days[(i - 1) as usize])
+                .collect();
+            days = picked;
+        }
+
+        days
+    }
+}
+
+// enumeration of the ways the next occurrence can be scheduled
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum RepetitionMethod {
+    Fixed,                // repeats on a schedule regardless of completion
+    StartAfterCompletion, // defer again counted from the completion date
+    DueAfterCompletion,   // due again counted from the completion date
+}
+
+impl std::str::FromStr for RepetitionMethod {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fixed" => Ok(RepetitionMethod::Fixed),
+            "start-after-completion" => Ok(RepetitionMethod::StartAfterCompletion),
+            "due-after-completion" => Ok(RepetitionMethod::DueAfterCompletion),
+            _ => Err(crate::err!(Parse)),
+        }
+    }
+}
+
+impl RepetitionMethod {
+    // the string used for this method in <repetition-method>
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RepetitionMethod::Fixed => "fixed",
+            RepetitionMethod::StartAfterCompletion => "start-after-completion",
+            RepetitionMethod::DueAfterCompletion => "due-after-completion",
+        }
+    }
+}
+
+// sets the date and time of day of `date` to the day of `day`
+fn on_day(date: DateTime<Utc>, day: DateTime<Utc>) -> DateTime<Utc> {
+    Utc.from_utc_datetime(&day.date_naive().and_time(date.time()))
+}
+
+impl Task {
+    // the next occurrence of a repeating task completed at the given time, or
+    // None if the task doesn't repeat (or its rule has ended). it's the same
+    // task, incomplete again with its defer and due dates moved forward and
+    // the number for its next completed clone counted up. an error if the
+    // rule can't be scheduled
+    pub fn next_repetition(&self, completed: DateTime<Utc>) -> Result<Option<Task>, Error> {
+        let rule = match self.repetition_rule.as_ref() {
+            Some(rule) => rule,
+            None => return Ok(None),
+        };
+        rule.check()?;
+
+        // next-clone-identifier counts the occurrences already completed, so
+        // this one is the last when it reaches COUNT
+        let done = self.next_clone_identifier.unwrap_or(0);
+        if rule.count.is_some_and(|count| done + 1 >= count.into()) {
+            return Ok(None);
+        }
+
+        let method = self.repetition_method.unwrap_or(RepetitionMethod::Fixed);
+
+        // the date the schedule is counted from. due dates take priority over
+        // defer dates as that's what OmniFocus shows
+        let (anchor, due_anchored) = match (method, self.due, self.start) {
+            (RepetitionMethod::StartAfterCompletion, _, Some(start)) => {
+                (Some(on_day(start, completed)), false)
+            }
+            (RepetitionMethod::StartAfterCompletion, _, None) => (Some(completed), false),
+            (RepetitionMethod::DueAfterCompletion, Some(due), _) => {
+                (Some(on_day(due, completed)), true)
+            }
+            (RepetitionMethod::DueAfterCompletion, None, _) => (Some(completed), true),
+            (RepetitionMethod::Fixed, Some(due), _) => (Some(due), true),
+            (RepetitionMethod::Fixed, None, Some(start)) => (Some(start), false),
+            (RepetitionMethod::Fixed, None, None) => (None, false),
+        };
+
+        let mut next = Task {
+            modified: Some(completed),
+            completed: None,
+            next_clone_identifier: Some(done + 1),
+            ..self.clone()
+        };
+
+        // move whichever date the schedule is counted from and keep the
+        // other one the same distance away from it
+        if let Some(anchor) = anchor {
+            let date = match rule.next_after(anchor)? {
+                Some(date) => date,
+                None => return Ok(None),
+            };
+            if due_anchored {
+                next.start = self.start.zip(self.due).map(|(s, d)| date - (d - s));
+                next.due = Some(date);
+            } else {
+                next.due = self.due.zip(self.start).map(|(d, s)| date + (d - s));
+                next.start = Some(date);
+            }
+        }
+
+        Ok(Some(next))
+    }
+}
+
+impl Content {
+    // the delta for completing a task at the given time. the database has
+    // stable repeats, so a repeating task keeps its id and becomes its next
+    // occurrence, and what was completed is written as a clone of it and its
+    // subtasks. a clone's id is the id of what it was cloned from and the
+    // task's next-clone-identifier, ex: "f23PngiKv2G.0", which means two
+    // clients completing the same occurrence write the same clone
+    pub fn complete_task(&self, task: &Task, completed: DateTime<Utc>) -> Result<Content, Error> {
+        let mut delta = Content::default();
+
+        let next = match task.next_repetition(completed)? {
+            Some(next) => next,
+            None => {
+                let mut done = task.clone();
+                done.completed = Some(completed);
+                done.modified = Some(completed);
+
+                // the completed task already exists so only what changed is
+                // written. tasks that haven't been saved yet are written in full
+                match self.tasks.iter().find(|t| t.id == task.id) {
+                    Some(old) => delta.task_updates.push(TaskUpdate::diff(old, done)),
+                    None => delta.tasks.push(done),
+                }
+                return Ok(delta);
+            }
+        };
+
+        // the subtasks move forward as far as the task did
+        let shift = match (task.due, next.due, task.start, next.start) {
+            (Some(old), Some(new), _, _) | (_, _, Some(old), Some(new)) => new - old,
+            _ => Duration::zero(),
+        };
+        let n = task.next_clone_identifier.unwrap_or(0);
+        let clone_id = |id: &str| format!("{}.{}", id, n);
+
+        // the task followed by everything inside of it
+        let mut subtree: Vec<&Task> = vec![task];
+        let mut i = 0;
+        while i < subtree.len() {
+            let parent = &subtree[i].id;
+            let children: Vec<&Task> = self.tasks.iter()
+                .filter(|t| t.parent.as_ref() == Some(parent) && !subtree.contains(t))
+                .collect();
+            subtree.extend(children);
+            i += 1;
+        }
+
+        for (i, old) in subtree.iter().enumerate() {
+            // the completed clone. only the task at the top stops repeating,
+            // the rest are as they were apart from being done
+            let mut done = Task {
+                id: clone_id(&old.id),
+                added: completed,
+                modified: Some(completed),
+                completed: old.completed.or(Some(completed)),
+                ..(*old).clone()
+            };
+            if i == 0 {
+                done.repetition_rule = None;
+                done.repetition_method = None;
+                done.next_clone_identifier = None;
+            } else {
+                done.parent = old.parent.as_ref().map(|p| clone_id(p));
+            }
+            delta.tasks.push(done);
+
+            for link in self.task_tags.iter().filter(|l| l.task == old.id) {
+                delta.task_tags.push(TaskTag {
+                    rank_in_task: link.rank_in_task.clone(),
+                    rank_in_tag: link.rank_in_tag.clone(),
+                    ..TaskTag::new(&clone_id(&old.id), &link.tag)
+                });
+            }
+
+            // the originals are the next occurrence, so are left to do again
+            let new = if i == 0 {
+                next.clone()
+            } else {
+                Task {
+                    modified: Some(completed),
+                    completed: None,
+                    start: old.start.map(|d| d + shift),
+                    due: old.due.map(|d| d + shift),
+                    ..(*old).clone()
+                }
+            };
+            match self.tasks.iter().find(|t| t.id == old.id) {
+                Some(saved) => delta.task_updates.push(TaskUpdate::diff(saved, new)),
+                None => delta.tasks.push(new),
+            }
+        }
+        Ok(delta)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task::TaskField;
+
+    fn date(s: &str) -> DateTime<Utc> {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_rule() {
+        let rule: RepetitionRule = "FREQ=MONTHLY;INTERVAL=2;BYDAY=-1FR,2MO;X-FOO=1"
+            .parse().unwrap();
+        assert!(rule.freq == Frequency::Monthly);
+        assert!(rule.interval == 2);
+        assert!(rule.by_day[0] == WeekdayNum { ordinal: Some(-1), weekday: Weekday::Fri });
+        assert!(rule.by_day[1] == WeekdayNum { ordinal: Some(2), weekday: Weekday::Mon });
+        assert!(rule.to_string() == "FREQ=MONTHLY;INTERVAL=2;BYDAY=-1FR,2MO;X-FOO=1");
+        assert!("INTERVAL=2".parse::<RepetitionRule>().is_err());
+    }
+
+    #[test]
+    fn test_next_after() {
+        let next = |rule: &str, after: &str| {
+            rule.parse::<RepetitionRule>().unwrap().next_after(date(after)).unwrap()
+        };
+
+        // 2019-11-20 is a wednesday
+        assert!(next("FREQ=WEEKLY", "2019-11-20T17:00:00Z")
+            == Some(date("2019-11-27T17:00:00Z")));
+        assert!(next("FREQ=WEEKLY;BYDAY=MO,FR", "2019-11-20T17:00:00Z")
+            == Some(date("2019-11-22T17:00:00Z")));
+        assert!(next("FREQ=WEEKLY;INTERVAL=2;BYDAY=MO", "2019-11-20T17:00:00Z")
+            == Some(date("2019-12-02T17:00:00Z")));
+        assert!(next("FREQ=MONTHLY;BYMONTHDAY=-1", "2019-11-20T09:00:00Z")
+            == Some(date("2019-11-30T09:00:00Z")));
+        assert!(next("FREQ=MONTHLY;BYDAY=1MO", "2019-11-20T09:00:00Z")
+            == Some(date("2019-12-02T09:00:00Z")));
+        assert!(next("FREQ=MONTHLY;BYDAY=MO,TU,WE,TH,FR;BYSETPOS=-1", "2019-11-29T09:00:00Z")
+            == Some(date("2019-12-31T09:00:00Z")));
+        assert!(next("FREQ=MONTHLY", "2019-01-31T09:00:00Z")
+            == Some(date("2019-02-28T09:00:00Z")));
+        assert!(next("FREQ=DAILY;UNTIL=20191121", "2019-11-21T09:00:00Z").is_none());
+
+        // every other day, skipping the weekend. friday the 22nd is two days
+        // on but the next weekday two days on from there is tuesday
+        assert!(next("FREQ=DAILY;INTERVAL=2;BYDAY=MO,TU,WE,TH,FR", "2019-11-20T09:00:00Z")
+            == Some(date("2019-11-22T09:00:00Z")));
+        assert!(next("FREQ=DAILY;INTERVAL=2;BYDAY=MO,TU,WE,TH,FR", "2019-11-22T09:00:00Z")
+            == Some(date("2019-11-26T09:00:00Z")));
+        assert!(next("FREQ=DAILY;INTERVAL=3;BYDAY=SA", "2019-11-20T09:00:00Z")
+            == Some(date("2019-11-23T09:00:00Z")));
+
+        assert!(next("FREQ=YEARLY;BYMONTH=3,9", "2019-11-20T09:00:00Z")
+            == Some(date("2020-03-20T09:00:00Z")));
+        assert!(next("FREQ=YEARLY;INTERVAL=2;BYMONTH=11;BYDAY=4TH", "2019-11-20T09:00:00Z")
+            == Some(date("2019-11-28T09:00:00Z")));
+        assert!(next("FREQ=YEARLY;INTERVAL=2;BYMONTH=11;BYDAY=4TH", "2019-11-28T09:00:00Z")
+            == Some(date("2021-11-25T09:00:00Z")));
+        assert!(next("FREQ=MONTHLY;BYMONTH=1,7;BYMONTHDAY=1", "2019-11-20T09:00:00Z")
+            == Some(date("2020-01-01T09:00:00Z")));
+    }
+
+    #[test]
+    fn test_unschedulable_rules() {
+        let after = date("2019-11-20T09:00:00Z");
+        for rule in &[
+            "FREQ=YEARLY;BYDAY=MO",
+            "FREQ=WEEKLY;BYMONTH=3",
+            "FREQ=DAILY;BYMONTHDAY=1",
+            "FREQ=HOURLY;BYDAY=MO",
+            "FREQ=DAILY;BYHOUR=9",
+        ] {
+            let rule: RepetitionRule = rule.parse().unwrap();
+            assert!(rule.next_after(after).is_err());
+
+            let task = Task { due: Some(after), repetition_rule: Some(rule), ..Task::default() };
+            assert!(task.next_repetition(after).is_err());
+        }
+    }
+
+    #[test]
+    fn test_repeat_count() {
+        let task = Task {
+            id: "repeat".into(),
+            due: Some(date("2019-11-20T17:00:00Z")),
+            repetition_rule: Some("FREQ=DAILY;COUNT=3".parse().unwrap()),
+            next_clone_identifier: Some(0),
+            ..Task::default()
+        };
+
+        // the first two occurrences are followed by another
+        let second = task.next_repetition(date("2019-11-20T12:00:00Z")).unwrap().unwrap();
+        let third = second.next_repetition(date("2019-11-21T12:00:00Z")).unwrap().unwrap();
+        assert!(third.due == Some(date("2019-11-22T17:00:00Z")));
+        assert!(third.next_clone_identifier == Some(2));
+
+        // but completing the third completes the task for good
+        assert!(third.next_repetition(date("2019-11-22T12:00:00Z")).unwrap().is_none());
+        let content = Content { tasks: vec![third.clone()], ..Content::default() };
+        let delta = content.complete_task(&third, date("2019-11-22T12:00:00Z")).unwrap();
+        assert!(delta.tasks.is_empty());
+        assert!(delta.task_updates[0].item.id == "repeat");
+        assert!(delta.task_updates[0].item.completed == Some(date("2019-11-22T12:00:00Z")));
+    }
+
+    #[test]
+    fn test_complete_repeating_task() {
+        let task = Task {
+            id: "repeat".into(),
+            start: Some(date("2019-11-18T09:00:00Z")),
+            due: Some(date("2019-11-20T17:00:00Z")),
+            repetition_rule: Some("FREQ=WEEKLY".parse().unwrap()),
+            repetition_method: Some(RepetitionMethod::DueAfterCompletion),
+            next_clone_identifier: Some(0),
+            ..Task::default()
+        };
+        let content = Content {
+            tasks: vec![task.clone()],
+            task_tags: vec![TaskTag::new("repeat", "tag")],
+            ..Content::default()
+        };
+
+        let delta = content.complete_task(&task, date("2019-11-25T12:00:00Z")).unwrap();
+
+        // the completed occurrence is a clone numbered by next-clone-identifier
+        // that doesn't repeat
+        let done = &delta.tasks[0];
+        assert!(done.id == "repeat.0");
+        assert!(done.completed == Some(date("2019-11-25T12:00:00Z")));
+        assert!(done.due == task.due);
+        assert!(done.repetition_rule.is_none());
+        assert!(delta.task_tags[0].task == "repeat.0");
+
+        // the task itself is due a week after it was completed and still
+        // deferred two days before
+        let next = &delta.task_updates[0];
+        assert!(next.item.id == task.id);
+        assert!(next.item.completed.is_none());
+        assert!(next.item.due == Some(date("2019-12-02T17:00:00Z")));
+        assert!(next.item.start == Some(date("2019-11-30T09:00:00Z")));
+        assert!(next.item.next_clone_identifier == Some(1));
+        assert!(next.fields == vec![
+            TaskField::Start,
+            TaskField::Due,
+            TaskField::Modified,
+            TaskField::NextCloneIdentifier,
+        ]);
+
+        // fixed schedules don't care when the task was completed
+        let fixed = Task { repetition_method: Some(RepetitionMethod::Fixed), ..task };
+        let next = fixed.next_repetition(date("2019-11-25T12:00:00Z")).unwrap().unwrap();
+        assert!(next.due == Some(date("2019-11-27T17:00:00Z")));
+    }
+
+    #[test]
+    fn test_complete_repeating_project() {
+        let project = Task {
+            id: "project".into(),
+            due: Some(date("2019-11-20T17:00:00Z")),
+            repetition_rule: Some("FREQ=WEEKLY".parse().unwrap()),
+            next_clone_identifier: Some(3),
+            ..Task::default()
+        };
+        let group = Task { id: "group".into(), parent: Some("project".into()), ..Task::default() };
+        let action = Task {
+            id: "action".into(),
+            parent: Some("group".into()),
+            due: Some(date("2019-11-19T17:00:00Z")),
+            completed: Some(date("2019-11-19T10:00:00Z")),
+            ..Task::default()
+        };
+        let content = Content {
+            tasks: vec![project.clone(), group, action],
+            ..Content::default()
+        };
+
+        // the whole project is cloned, keeping the shape of the original
+        let delta = content.complete_task(&project, date("2019-11-20T12:00:00Z")).unwrap();
+        let ids: Vec<&str> = delta.tasks.iter().map(|t| t.id.as_str()).collect();
+        assert!(ids == vec!["project.3", "group.3", "action.3"]);
+        assert!(delta.tasks[1].parent == Some("project.3".into()));
+        assert!(delta.tasks[2].parent == Some("group.3".into()));
+        assert!(delta.tasks[2].completed == Some(date("2019-11-19T10:00:00Z")));
+        assert!(delta.tasks.iter().all(|t| t.completed.is_some()));
+
+        // and the originals are reset, moved forward as far as the project
+        let action = delta.task_updates.iter().find(|u| u.item.id == "action").unwrap();
+        assert!(action.item.completed.is_none());
+        assert!(action.item.due == Some(date("2019-11-26T17:00:00Z")));
+        assert!(delta.task_updates.len() == 3);
+    }
+}
@@ -0,0 +1,665 @@
+use chrono::prelude::*;
+use chrono::{Duration, Months};
+use serde_json::{json, Value};
+
+use crate::error::*;
+use crate::util::ID;
+use crate::parse::Content;
+use crate::task::{Task, SubtaskOrder};
+use crate::project::ProjectStatus;
+use crate::tag::TagStatus;
+
+// helpers to handle the rules of custom perspectives. OmniFocus stores them as
+// a JSON array in the perspective's "filterRules", ex:
+// [{"actionAvailability":"remaining"},{"actionHasAnyOfTags":["lWCMT0TFh2x"]}]
+// each object is one rule and the array is combined by the perspective's
+// "filterRulesAggregation"
+
+// enumeration of the values of "actionAvailability"
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Availability {
+    Remaining,      // not completed or dropped
+    Available,      // remaining and not deferred, blocked or on hold
+    FirstAvailable, // the first available action of each project
+    Completed,
+    Dropped,
+}
+
+impl std::str::FromStr for Availability {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "remaining" => Ok(Availability::Remaining),
+            "available" => Ok(Availability::Available),
+            "firstAvailable" => Ok(Availability::FirstAvailable),
+            "completed" => Ok(Availability::Completed),
+            "dropped" => Ok(Availability::Dropped),
+            _ => Err(crate::err!(Parse)),
+        }
+    }
+}
+
+impl Availability {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Availability::Remaining => "remaining",
+            Availability::Available => "available",
+            Availability::FirstAvailable => "firstAvailable",
+            Availability::Completed => "completed",
+            Availability::Dropped => "dropped",
+        }
+    }
+}
+
+// enumeration of the values of "actionStatus"
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Status {
+    Flagged,
+    Due, // overdue or due soon
+}
+
+impl std::str::FromStr for Status {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "flagged" => Ok(Status::Flagged),
+            "due" => Ok(Status::Due),
+            _ => Err(crate::err!(Parse)),
+        }
+    }
+}
+
+impl Status {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Status::Flagged => "flagged",
+            Status::Due => "due",
+        }
+    }
+}
+
+// enumeration of the dates a date rule can look at ("actionDateField")
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum DateField {
+    Due,
+    Defer,
+    Completed,
+    Added,
+    Modified,
+}
+
+impl std::str::FromStr for DateField {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "due" => Ok(DateField::Due),
+            "defer" => Ok(DateField::Defer),
+            "completed" => Ok(DateField::Completed),
+            "added" => Ok(DateField::Added),
+            "modified" => Ok(DateField::Modified),
+            _ => Err(crate::err!(Parse)),
+        }
+    }
+}
+
+impl DateField {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DateField::Due => "due",
+            DateField::Defer => "defer",
+            DateField::Completed => "completed",
+            DateField::Added => "added",
+            DateField::Modified => "modified",
+        }
+    }
+
+    // the value of this date on a task
+    pub fn get(self, task: &Task) -> Option<DateTime<Utc>> {
+        match self {
+            DateField::Due => task.due,
+            DateField::Defer => task.start,
+            DateField::Completed => task.completed,
+            DateField::Added => Some(task.added),
+            DateField::Modified => task.modified,
+        }
+    }
+}
+
+// enumeration of the units of a relative date range ("relativeComponent")
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Unit {
+    Day,
+    Week,
+    Month,
+    Year,
+}
+
+impl std::str::FromStr for Unit {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "day" => Ok(Unit::Day),
+            "week" => Ok(Unit::Week),
+            "month" => Ok(Unit::Month),
+            "year" => Ok(Unit::Year),
+            _ => Err(crate::err!(Parse)),
+        }
+    }
+}
+
+impl Unit {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Unit::Day => "day",
+            Unit::Week => "week",
+            Unit::Month => "month",
+            Unit::Year => "year",
+        }
+    }
+
+    // moves a date by amount units. negative amounts go backwards
+    fn shift(self, date: DateTime<Utc>, amount: i64) -> Option<DateTime<Utc>> {
+        match self {
+            Unit::Day => date.checked_add_signed(Duration::days(amount)),
+            Unit::Week => date.checked_add_signed(Duration::weeks(amount)),
+            Unit::Month | Unit::Year => {
+                let months = if self == Unit::Year { amount * 12 } else { amount };
+                let delta = Months::new(months.unsigned_abs() as u32);
+                if months < 0 {
+                    date.checked_sub_months(delta)
+                } else {
+                    date.checked_add_months(delta)
+                }
+            }
+        }
+    }
+}
+
+// the ranges a date rule can check for
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum DateRange {
+    Today,
+    Yesterday,
+    Tomorrow,
+    Next(u32, Unit), // from now until amount units from now
+    Last(u32, Unit), // from amount units ago until now
+    OnOrAfter(DateTime<Utc>),
+    OnOrBefore(DateTime<Utc>),
+}
+
+// enumeration of the ways a group of rules can be combined
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Aggregate {
+    All,
+    Any,
+    None,
+}
+
+impl std::str::FromStr for Aggregate {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "all" => Ok(Aggregate::All),
+            "any" => Ok(Aggregate::Any),
+            "none" => Ok(Aggregate::None),
+            _ => Err(crate::err!(Parse)),
+        }
+    }
+}
+
+impl Aggregate {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Aggregate::All => "all",
+            Aggregate::Any => "any",
+            Aggregate::None => "none",
+        }
+    }
+}
+
+// a single rule of a custom perspective
+#[derive(Debug, PartialEq, Clone)]
+pub enum Rule {
+    Availability(Availability),
+    Status(Status),
+    HasAnyOfTags(Vec<ID>),
+    HasAllOfTags(Vec<ID>),
+    Untagged,
+    IsProject(bool),
+    Repeats(bool),
+    HasDate(DateField, bool),
+    Date(DateField, DateRange),
+    MatchingSearch(Vec<String>), // every term must be in the title or note
+    WithinFocus(Vec<ID>),        // inside one of these projects or folders
+    Aggregate(Aggregate, Vec<Rule>),
+    Disabled(Box<Rule>),         // turned off in the editor, ignored
+    Unknown(Value),              // a rule we don't understand, ignored
+}
+
+// parses the "filterRules" JSON of a perspective
+pub fn parse_rules(json: &str) -> Result<Vec<Rule>, Error> {
+    match serde_json::from_str(json)? {
+        Value::Array(rules) => Ok(rules.iter().map(Rule::from_json).collect()),
+        _ => Err(crate::err!(Parse)),
+    }
+}
+
+// turns rules back into the "filterRules" JSON of a perspective
+pub fn write_rules(rules: &[Rule]) -> Result<String, Error> {
+    let rules = rules.iter().map(Rule::to_json).collect::<Result<Vec<Value>, Error>>()?;
+    Ok(Value::Array(rules).to_string())
+}
+
+// a list of strings, ex: tag ids
+fn strings(value: &Value) -> Option<Vec<String>> {
+    value.as_array()?.iter()
+        .map(|v| v.as_str().map(|s| s.to_string()))
+        .collect()
+}
+
+// the range part of a date rule, ex: "actionDateIsInTheNext"
+fn date_range(rule: &serde_json::Map<String, Value>) -> Option<DateRange> {
+    let relative = |v: &Value| -> Option<(u32, Unit)> {
+        let amount = v.get("relativeAmount")?.as_u64()? as u32;
+        let unit = v.get("relativeComponent")?.as_str()?.parse().ok()?;
+        Some((amount, unit))
+    };
+    let date = |v: &Value| -> Option<DateTime<Utc>> {
+        v.get("date")?.as_str()?.parse().ok()
+    };
+
+    if rule.get("actionDateIsToday").is_some() {
+        Some(DateRange::Today)
+    } else if rule.get("actionDateIsYesterday").is_some() {
+        Some(DateRange::Yesterday)
+    } else if rule.get("actionDateIsTomorrow").is_some() {
+        Some(DateRange::Tomorrow)
+    } else if let Some(v) = rule.get("actionDateIsInTheNext") {
+        relative(v).map(|(n, u)| DateRange::Next(n, u))
+    } else if let Some(v) = rule.get("actionDateIsInTheLast") {
+        relative(v).map(|(n, u)| DateRange::Last(n, u))
+    } else if let Some(v) = rule.get("actionDateIsOnOrAfterDateSpec") {
+        date(v).map(DateRange::OnOrAfter)
+    } else if let Some(v) = rule.get("actionDateIsOnOrBeforeDateSpec") {
+        date(v).map(DateRange::OnOrBefore)
+    } else {
+        None
+    }
+}
+
+impl Rule {
+    // turns one of the objects in the filterRules array into a Rule. anything
+    // that doesn't look like a rule we know becomes Rule::Unknown
+    pub fn from_json(value: &Value) -> Rule {
+        Rule::try_from_json(value).unwrap_or_else(|| Rule::Unknown(value.clone()))
+    }
+
+    fn try_from_json(value: &Value) -> Option<Rule> {
+        let rule = value.as_object()?;
+
+        if let Some(inner) = rule.get("disabledRule") {
+            return Some(Rule::Disabled(Box::new(Rule::from_json(inner))));
+        }
+        if let Some(kind) = rule.get("aggregateType") {
+            let rules = rule.get("aggregateRules")?.as_array()?;
+            return Some(Rule::Aggregate(
+                kind.as_str()?.parse().ok()?,
+                rules.iter().map(Rule::from_json).collect(),
+            ));
+        }
+        if let Some(field) = rule.get("actionDateField") {
+            let field = field.as_str()?.parse().ok()?;
+            return Some(Rule::Date(field, date_range(rule)?));
+        }
+
+        // the rest are objects with a single key
+        let (key, value) = rule.iter().next()?;
+        match key.as_str() {
+            "actionAvailability" => {
+                Some(Rule::Availability(value.as_str()?.parse().ok()?))
+            }
+            "actionStatus" => Some(Rule::Status(value.as_str()?.parse().ok()?)),
+            "actionHasAnyOfTags" => Some(Rule::HasAnyOfTags(strings(value)?)),
+            "actionHasAllOfTags" => Some(Rule::HasAllOfTags(strings(value)?)),
+            "actionIsUntagged" => Some(Rule::Untagged),
+            "actionIsProject" => Some(Rule::IsProject(value.as_bool()?)),
+            "actionRepeats" => Some(Rule::Repeats(value.as_bool()?)),
+            "actionHasDueDate" => Some(Rule::HasDate(DateField::Due, value.as_bool()?)),
+            "actionHasDeferDate" => {
+                Some(Rule::HasDate(DateField::Defer, value.as_bool()?))
+            }
+            "actionMatchingSearch" => Some(Rule::MatchingSearch(strings(value)?)),
+            "actionWithinFocus" => Some(Rule::WithinFocus(strings(value)?)),
+            _ => None,
+        }
+    }
+
+    // the inverse of from_json. unknown rules are written back as they were
+    // read so rules from newer versions of OmniFocus aren't lost. a rule
+    // OmniFocus has no JSON for, ex: HasDate(Completed, _), is an
+    // InvalidArgument error
+    pub fn to_json(&self) -> Result<Value, Error> {
+        let strings = |v: &[String]| Value::from(v.to_vec());
+        Ok(match self {
+            Rule::Availability(a) => json!({"actionAvailability": a.as_str()}),
+            Rule::Status(s) => json!({"actionStatus": s.as_str()}),
+            Rule::HasAnyOfTags(ids) => json!({"actionHasAnyOfTags": strings(ids)}),
+            Rule::HasAllOfTags(ids) => json!({"actionHasAllOfTags": strings(ids)}),
+            Rule::Untagged => json!({"actionIsUntagged": true}),
+            Rule::IsProject(b) => json!({"actionIsProject": b}),
+            Rule::Repeats(b) => json!({"actionRepeats": b}),
+            Rule::HasDate(DateField::Defer, b) => json!({"actionHasDeferDate": b}),
+            Rule::HasDate(DateField::Due, b) => json!({"actionHasDueDate": b}),
+            Rule::HasDate(_, _) => return Err(crate::err!(InvalidArgument)),
+            Rule::Date(field, range) => {
+                let relative = |n: u32, u: Unit| {
+                    json!({"relativeAmount": n, "relativeComponent": u.as_str()})
+                };
+                let date = |d: DateTime<Utc>| {
+                    json!({"date": d.to_rfc3339_opts(SecondsFormat::Millis, true)})
+                };
+                let (key, value) = match *range {
+                    DateRange::Today => ("actionDateIsToday", json!(true)),
+                    DateRange::Yesterday => ("actionDateIsYesterday", json!(true)),
+                    DateRange::Tomorrow => ("actionDateIsTomorrow", json!(true)),
+                    DateRange::Next(n, u) => ("actionDateIsInTheNext", relative(n, u)),
+                    DateRange::Last(n, u) => ("actionDateIsInTheLast", relative(n, u)),
+                    DateRange::OnOrAfter(d) => ("actionDateIsOnOrAfterDateSpec", date(d)),
+                    DateRange::OnOrBefore(d) => ("actionDateIsOnOrBeforeDateSpec", date(d)),
+                };
+                json!({"actionDateField": field.as_str(), key: value})
+            }
+            Rule::MatchingSearch(terms) => json!({"actionMatchingSearch": strings(terms)}),
+            Rule::WithinFocus(ids) => json!({"actionWithinFocus": strings(ids)}),
+            Rule::Aggregate(kind, rules) => json!({
+                "aggregateType": kind.as_str(),
+                "aggregateRules": rules.iter().map(Rule::to_json).collect::<Result<Vec<_>, _>>()?,
+            }),
+            Rule::Disabled(rule) => json!({"disabledRule": rule.to_json()?}),
+            Rule::Unknown(value) => value.clone(),
+        })
+    }
+
+    // whether a task passes this rule. disabled and unknown rules let
+    // everything through
+    pub fn matches(&self, task: &Task, content: &Content, now: DateTime<Utc>) -> bool {
+        match self {
+            Rule::Availability(a) => match a {
+                Availability::Remaining => is_remaining(task, content),
+                Availability::Available => is_available(task, content, now),
+                Availability::FirstAvailable => is_first_available(task, content, now),
+                Availability::Completed => task.completed.is_some(),
+                Availability::Dropped => is_dropped(task, content),
+            },
+            Rule::Status(Status::Flagged) => task.flagged,
+            Rule::Status(Status::Due) => {
+                task.completed.is_none()
+                    && task.due.is_some_and(|d| d <= now + Duration::days(2))
+            }
+            Rule::HasAnyOfTags(ids) => {
+                content.tags_for(task).iter().any(|t| ids.contains(&t.id))
+            }
+            Rule::HasAllOfTags(ids) => {
+                let tags = content.tags_for(task);
+                ids.iter().all(|id| tags.iter().any(|t| &t.id == id))
+            }
+            Rule::Untagged => content.tags_for(task).is_empty(),
+            Rule::IsProject(is_project) => task.project.is_some() == *is_project,
+            Rule::Repeats(repeats) => task.repetition_rule.is_some() == *repeats,
+            Rule::HasDate(field, has) => field.get(task).is_some() == *has,
+            Rule::Date(field, range) => {
+                field.get(task).is_some_and(|d| in_range(d, *range, now))
+            }
+            Rule::MatchingSearch(terms) => {
+                let note = task.note.as_ref().map(|n| n.to_plain()).unwrap_or_default();
+                let text = format!("{}\n{}", task.title, note).to_lowercase();
+                terms.iter().all(|t| text.contains(&t.to_lowercase()))
+            }
+            Rule::WithinFocus(ids) => is_within(task, ids, content),
+            Rule::Aggregate(kind, rules) => kind.matches(rules, task, content, now),
+            Rule::Disabled(_) | Rule::Unknown(_) => true,
+        }
+    }
+}
+
+impl Aggregate {
+    // combines the results of a list of rules. disabled rules and rules that
+    // aren't understood don't count, otherwise an unknown rule would make any
+    // match everything and none match nothing
+    pub fn matches(
+        self,
+        rules: &[Rule],
+        task: &Task,
+        content: &Content,
+        now: DateTime<Utc>,
+    ) -> bool {
+        let mut enabled = rules.iter()
+            .filter(|r| !matches!(r, Rule::Disabled(_) | Rule::Unknown(_)))
+            .peekable();
+        if enabled.peek().is_none() {
+            return true;
+        }
+        match self {
+            Aggregate::All => enabled.all(|r| r.matches(task, content, now)),
+            Aggregate::Any => enabled.any(|r| r.matches(task, content, now)),
+            Aggregate::None => !enabled.any(|r| r.matches(task, content, now)),
+        }
+    }
+}
+
+// whether a date is inside of a range. days are counted in local time
+fn in_range(date: DateTime<Utc>, range: DateRange, now: DateTime<Utc>) -> bool {
+    let day = |d: DateTime<Utc>| d.with_timezone(&Local).date_naive();
+    let today = day(now);
+    match range {
+        DateRange::Today => day(date) == today,
+        DateRange::Yesterday => today.pred_opt() == Some(day(date)),
+        DateRange::Tomorrow => today.succ_opt() == Some(day(date)),
+        DateRange::Next(n, unit) => {
+            date >= now && unit.shift(now, n.into()).is_some_and(|end| date <= end)
+        }
+        DateRange::Last(n, unit) => {
+            date <= now && unit.shift(now, -i64::from(n)).is_some_and(|start| date >= start)
+        }
+        DateRange::OnOrAfter(start) => date >= start,
+        DateRange::OnOrBefore(end) => date <= end,
+    }
+}
+
+// the parents of a task, closest first. stops early on a cycle
+fn ancestors<'a>(task: &Task, content: &'a Content) -> Vec<&'a Task> {
+    let mut ancestors: Vec<&Task> = Vec::new();
+    let mut curr = task.parent.as_ref();
+    while let Some(id) = curr {
+        match content.tasks.iter().find(|t| &t.id == id) {
+            Some(parent) if parent.id != task.id
+                && !ancestors.iter().any(|a| a.id == parent.id) =>
+            {
+                ancestors.push(parent);
+                curr = parent.parent.as_ref();
+            }
+            _ => break,
+        }
+    }
+    ancestors
+}
+
+// inside of a dropped project (or a dropped project itself)
+fn is_dropped(task: &Task, content: &Content) -> bool {
+    content.project_for(task)
+        .is_some_and(|p| p.status() == ProjectStatus::Dropped)
+}
+
+// neither completed nor dropped, and not inside of a finished project
+fn is_remaining(task: &Task, content: &Content) -> bool {
+    task.completed.is_none() && !content.project_for(task).is_some_and(|p| {
+        p.status() == ProjectStatus::Done || p.status() == ProjectStatus::Dropped
+    })
+}
+
+// remaining and not waiting on anything: its project is active, neither it
+// nor its parents are deferred, none of its tags are on hold and it isn't
+// behind an incomplete task in a sequential group
+fn is_available(task: &Task, content: &Content, now: DateTime<Utc>) -> bool {
+    if !is_remaining(task, content) {
+        return false;
+    }
+    if content.project_for(task).is_some_and(|p| p.status() != ProjectStatus::Active) {
+        return false;
+    }
+    let on_hold = content.tags_for(task).iter()
+        .any(|t| content.tag_status(&t.id) != Some(TagStatus::Active));
+    if on_hold {
+        return false;
+    }
+
+    let mut curr = task;
+    for parent in std::iter::once(task).chain(ancestors(task, content)) {
+        if parent.start.is_some_and(|s| s > now) {
+            return false;
+        }
+        // only the first incomplete child of a sequential parent is available
+        if parent.id != task.id {
+            if parent.order == Some(SubtaskOrder::Sequential) {
+                let blocked = content.tasks.iter().any(|t| {
+                    t.parent.as_ref() == Some(&parent.id)
+                        && t.id != curr.id
+                        && t.completed.is_none()
+                        && t.rank < curr.rank
+                });
+                if blocked {
+                    return false;
+                }
+            }
+            curr = parent;
+        }
+    }
+
+    true
+}
+
+// available and the first available task amongst its siblings
+fn is_first_available(task: &Task, content: &Content, now: DateTime<Utc>) -> bool {
+    is_available(task, content, now) && !content.tasks.iter().any(|t| {
+        t.parent.is_some()
+            && t.parent == task.parent
+            && t.id != task.id
+            && t.rank < task.rank
+            && is_available(t, content, now)
+    })
+}
+
+// inside of (or one of) the given projects, tasks or folders
+fn is_within(task: &Task, ids: &[ID], content: &Content) -> bool {
+    if ids.contains(&task.id) || ancestors(task, content).iter().any(|a| ids.contains(&a.id)) {
+        return true;
+    }
+    let mut folder = content.project_for(task).and_then(|p| p.folder());
+    let mut visited: Vec<&ID> = Vec::new();
+    while let Some(id) = folder {
+        if ids.contains(id) {
+            return true;
+        }
+        if visited.contains(&id) {
+            break;
+        }
+        visited.push(id);
+        folder = content.folder(id).and_then(|f| f.parent.as_ref());
+    }
+    false
+}
+
+// the tasks of content that pass a list of rules, in file order
+pub fn filter<'a>(
+    rules: &[Rule],
+    aggregate: Aggregate,
+    content: &'a Content,
+) -> Vec<&'a Task> {
+    let now = Utc::now();
+    content.tasks.iter()
+        .filter(|t| aggregate.matches(rules, t, content, now))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tag::{Tag, TaskTag};
+
+    #[test]
+    fn test_parse_rules() {
+        let rules = parse_rules(r#"[
+            {"disabledRule":{"actionAvailability":"remaining"}},
+            {"aggregateType":"any","aggregateRules":[
+                {"actionStatus":"flagged"},
+                {"actionHasAnyOfTags":["a","b"]}
+            ]},
+            {"actionDateField":"due","actionDateIsInTheNext":
+                {"relativeAmount":3,"relativeComponent":"day"}},
+            {"actionSomethingNew":1}
+        ]"#).unwrap();
+
+        let remaining = Rule::Availability(Availability::Remaining);
+        assert!(rules[0] == Rule::Disabled(Box::new(remaining)));
+        assert!(rules[1] == Rule::Aggregate(Aggregate::Any, vec![
+            Rule::Status(Status::Flagged),
+            Rule::HasAnyOfTags(vec!["a".into(), "b".into()]),
+        ]));
+        assert!(rules[2] == Rule::Date(DateField::Due, DateRange::Next(3, Unit::Day)));
+        assert!(matches!(rules[3], Rule::Unknown(_)));
+        assert!(parse_rules("{}").is_err());
+
+        // writing them out and reading them back gives the same rules
+        assert!(parse_rules(&write_rules(&rules).unwrap()).unwrap() == rules);
+
+        // only due and defer dates have a rule for whether they're set
+        let completed = Rule::HasDate(DateField::Completed, true);
+        assert!(write_rules(&[Rule::Disabled(Box::new(completed))]).is_err());
+    }
+
+    #[test]
+    fn test_filter_tasks() {
+        let now = Utc::now();
+        let tasks = vec![
+            Task { id: "flagged".into(), flagged: true, ..Task::default() },
+            Task { id: "tagged".into(), due: Some(now + Duration::days(1)), ..Task::default() },
+            Task { id: "done".into(), completed: Some(now), flagged: true, ..Task::default() },
+            Task { id: "later".into(), due: Some(now + Duration::days(9)), ..Task::default() },
+        ];
+        let content = Content {
+            tasks,
+            tags: vec![Tag { id: "a".into(), ..Tag::default() }],
+            task_tags: vec![TaskTag::new("tagged", "a")],
+            ..Content::default()
+        };
+        let ids = |rules: &str, aggregate| -> Vec<String> {
+            filter(&parse_rules(rules).unwrap(), aggregate, &content).iter()
+                .map(|t| t.id.clone())
+                .collect()
+        };
+
+        let rules = r#"[{"actionAvailability":"remaining"},
+            {"aggregateType":"any","aggregateRules":[
+                {"actionStatus":"flagged"},{"actionHasAnyOfTags":["a"]}]}]"#;
+        assert!(ids(rules, Aggregate::All) == vec!["flagged", "tagged"]);
+
+        let rules = r#"[{"actionDateField":"due","actionDateIsInTheNext":
+            {"relativeAmount":1,"relativeComponent":"week"}}]"#;
+        assert!(ids(rules, Aggregate::All) == vec!["tagged"]);
+        assert!(ids(rules, Aggregate::None) == vec!["flagged", "done", "later"]);
+
+        // a perspective with only disabled rules shows everything
+        let rules = r#"[{"disabledRule":{"actionAvailability":"completed"}}]"#;
+        assert!(ids(rules, Aggregate::All).len() == 4);
+
+        // rules that aren't understood are left out of any and none too
+        let rules = r#"[{"actionSomethingNew":1},{"actionStatus":"flagged"}]"#;
+        assert!(ids(rules, Aggregate::Any) == vec!["flagged", "done"]);
+        assert!(ids(rules, Aggregate::None) == vec!["tagged", "later"]);
+    }
+}
@@ -0,0 +1,390 @@
+use chrono::prelude::*;
+
+use crate::util::{ID, generate_id};
+use crate::parse::Content;
+use crate::task::Task;
+use crate::note::Note;
+use crate::unknown::Unknown;
+
+// enumeration of the states a tag can be in. OmniFocus doesn't store this
+// directly, it's derived from <hidden> and <prohibits-next-action>
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TagStatus {
+    Active,
+    OnHold,
+    Dropped,
+}
+
+// a geographic location a tag can be attached to
+#[derive(Debug, PartialEq, Clone)]
+pub struct Location {
+    pub name: Option<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub radius: Option<f64>,
+}
+
+// a struct to represent a tag (called a context in the file format)
+#[derive(Debug, PartialEq, Clone)]
+pub struct Tag {
+    // metadata
+    pub id: ID,
+    pub parent: Option<ID>,
+    pub rank: Option<i64>,
+    pub added: DateTime<Utc>,
+    pub modified: Option<DateTime<Utc>>,
+    // attributes
+    pub name: String,
+    pub note: Option<Note>,
+    pub hidden: Option<DateTime<Utc>>,
+    pub prohibits_next_action: bool,
+    pub location: Option<Location>,
+    pub tasks_user_ordered: bool,
+    // everything else in the record, written back out as it was read
+    pub unknown: Unknown,
+}
+
+// self explanatory
+impl Default for Tag {
+    fn default() -> Self {
+        Tag {
+            id: generate_id(),
+            parent: None,
+            rank: None,
+            added: Utc::now(),
+            modified: Some(Utc::now()),
+            name: "".into(),
+            note: None,
+            hidden: None,
+            prohibits_next_action: false,
+            location: None,
+            tasks_user_ordered: false,
+            unknown: Unknown::default(),
+        }
+    }
+}
+
+// enumeration of the fields of a Tag as they appear in a <context>. see
+// TaskField for why this exists
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TagField {
+    Parent,
+    Rank,
+    Added,
+    Modified,
+    Name,
+    Note,
+    Hidden,
+    ProhibitsNextAction,
+    Location,
+    TasksUserOrdered,
+}
+
+// a link between a task and one of its tags (a <task-to-tag>). a task can have
+// any number of these. the ranks are strings that sort lexicographically
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TaskTag {
+    // metadata
+    pub id: ID, // always "{task id}.{tag id}"
+    pub added: DateTime<Utc>,
+    pub modified: Option<DateTime<Utc>>,
+    // attributes
+    pub task: ID,
+    pub tag: ID,
+    pub rank_in_task: Option<String>,
+    pub rank_in_tag: Option<String>,
+    // everything else in the record, written back out as it was read
+    pub unknown: Unknown,
+}
+
+// enumeration of the fields of a TaskTag as they appear in a <task-to-tag>
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TaskTagField {
+    Added,
+    Modified,
+    Task,
+    Tag,
+    RankInTask,
+    RankInTag,
+}
+
+impl TaskTag {
+    // creates a new link between a task and a tag
+    pub fn new(task: &str, tag: &str) -> TaskTag {
+        TaskTag {
+            id: format!("{}.{}", task, tag),
+            added: Utc::now(),
+            modified: Some(Utc::now()),
+            task: task.into(),
+            tag: tag.into(),
+            rank_in_task: None,
+            rank_in_tag: None,
+            unknown: Unknown::default(),
+        }
+    }
+
+    // copies only the given fields from other onto self
+    pub fn merge(&mut self, other: &TaskTag, fields: &[TaskTagField]) {
+        for field in fields {
+            match field {
+                TaskTagField::Added => self.added = other.added,
+                TaskTagField::Modified => self.modified = other.modified,
+                TaskTagField::Task => self.task = other.task.clone(),
+                TaskTagField::Tag => self.tag = other.tag.clone(),
+                TaskTagField::RankInTask => {
+                    self.rank_in_task = other.rank_in_task.clone()
+                }
+                TaskTagField::RankInTag => {
+                    self.rank_in_tag = other.rank_in_tag.clone()
+                }
+            }
+        }
+    }
+}
+
+impl Tag {
+    // the status of this tag on its own, ignoring its parents. a hidden tag is
+    // dropped and one that prohibits next actions is on hold
+    pub fn status(&self) -> TagStatus {
+        if self.hidden.is_some() {
+            TagStatus::Dropped
+        } else if self.prohibits_next_action {
+            TagStatus::OnHold
+        } else {
+            TagStatus::Active
+        }
+    }
+
+    // copies only the given fields from other onto self
+    pub fn merge(&mut self, other: &Tag, fields: &[TagField]) {
+        for field in fields {
+            match field {
+                TagField::Parent => self.parent = other.parent.clone(),
+                TagField::Rank => self.rank = other.rank,
+                TagField::Added => self.added = other.added,
+                TagField::Modified => self.modified = other.modified,
+                TagField::Name => self.name = other.name.clone(),
+                TagField::Note => self.note = other.note.clone(),
+                TagField::Hidden => self.hidden = other.hidden,
+                TagField::ProhibitsNextAction => {
+                    self.prohibits_next_action = other.prohibits_next_action
+                }
+                TagField::Location => self.location = other.location.clone(),
+                TagField::TasksUserOrdered => {
+                    self.tasks_user_ordered = other.tasks_user_ordered
+                }
+            }
+        }
+    }
+}
+
+// creates a rank string that sorts after all of the given ones. ranks are
+// fixed width hex, ex: "000a", so the next one is the largest plus one. only
+// once that runs out of digits, or for ranks that aren't hex, is it made longer
+pub fn rank_after<'a>(ranks: impl Iterator<Item = &'a str>) -> String {
+    let max = match ranks.max() {
+        Some(max) => max,
+        None => return "8000".into(),
+    };
+    let next = Some(max)
+        .filter(|m| m.chars().all(|c| c.is_ascii_hexdigit()))
+        .and_then(|m| u64::from_str_radix(m, 16).ok())
+        .and_then(|n| n.checked_add(1));
+    match next {
+        Some(n) if format!("{:x}", n).len() <= max.len() => {
+            format!("{:0width$x}", n, width = max.len())
+        }
+        _ => format!("{}8", max),
+    }
+}
+
+// queries over the tag tree
+impl Content {
+    // finds a tag by id
+    pub fn tag(&self, id: &str) -> Option<&Tag> {
+        self.tags.iter().find(|t| t.id == id)
+    }
+
+    // the top level tags, sorted by rank
+    pub fn root_tags(&self) -> Vec<&Tag> {
+        let mut roots: Vec<&Tag> =
+            self.tags.iter().filter(|t| t.parent.is_none()).collect();
+        roots.sort_by_key(|t| t.rank);
+        roots
+    }
+
+    // the direct children of a tag, sorted by rank
+    pub fn tag_children(&self, id: &str) -> Vec<&Tag> {
+        let mut children: Vec<&Tag> = self.tags.iter()
+            .filter(|t| t.parent.as_deref() == Some(id))
+            .collect();
+        children.sort_by_key(|t| t.rank);
+        children
+    }
+
+    // the parents of a tag, closest first. stops early if the tree is broken
+    // or has a cycle rather than looping forever
+    pub fn tag_ancestors(&self, id: &str) -> Vec<&Tag> {
+        let mut ancestors: Vec<&Tag> = Vec::new();
+        let mut curr = self.tag(id).and_then(|t| t.parent.as_deref());
+        while let Some(parent_id) = curr {
+            match self.tag(parent_id) {
+                Some(parent) if !ancestors.iter().any(|a| a.id == parent.id) => {
+                    ancestors.push(parent);
+                    curr = parent.parent.as_deref();
+                }
+                _ => break,
+            }
+        }
+        ancestors
+    }
+
+    // the full name of a tag including its parents. ex: "Errands : Supermarket"
+    pub fn tag_path(&self, id: &str) -> Option<String> {
+        let tag = self.tag(id)?;
+        let mut names: Vec<&str> = self.tag_ancestors(id).iter()
+            .map(|t| t.name.as_str())
+            .collect();
+        names.reverse();
+        names.push(&tag.name);
+        Some(names.join(" : "))
+    }
+
+    // the tags of a task in the order they are shown on the task. databases
+    // that predate multiple tags only have the task's single context
+    pub fn tags_for(&self, task: &Task) -> Vec<&Tag> {
+        let mut links: Vec<&TaskTag> = self.task_tags.iter()
+            .filter(|l| l.task == task.id)
+            .collect();
+
+        if links.is_empty() {
+            return task.context.iter().filter_map(|id| self.tag(id)).collect();
+        }
+
+        links.sort_by(|a, b| a.rank_in_task.cmp(&b.rank_in_task));
+        links.iter().filter_map(|l| self.tag(&l.tag)).collect()
+    }
+
+    // the tasks with a given tag in the order they are shown in the tag
+    pub fn tasks_for(&self, tag: &str) -> Vec<&Task> {
+        let mut links: Vec<&TaskTag> = self.task_tags.iter()
+            .filter(|l| l.tag == tag)
+            .collect();
+        links.sort_by(|a, b| a.rank_in_tag.cmp(&b.rank_in_tag));
+
+        let mut tasks: Vec<&Task> = links.iter()
+            .filter_map(|l| self.tasks.iter().find(|t| t.id == l.task))
+            .collect();
+
+        // tasks that only have the legacy single context go last
+        for task in self.tasks.iter() {
+            if task.context.as_deref() == Some(tag)
+                && !tasks.iter().any(|t| t.id == task.id)
+            {
+                tasks.push(task);
+            }
+        }
+
+        tasks
+    }
+
+    // the status of a tag taking its parents into account. a tag inside of a
+    // dropped or on hold tag is also dropped or on hold
+    pub fn tag_status(&self, id: &str) -> Option<TagStatus> {
+        let mut status = self.tag(id)?.status();
+        for ancestor in self.tag_ancestors(id) {
+            match ancestor.status() {
+                TagStatus::Dropped => return Some(TagStatus::Dropped),
+                TagStatus::OnHold => status = TagStatus::OnHold,
+                TagStatus::Active => {}
+            }
+        }
+        Some(status)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tag(id: &str, parent: Option<&str>, rank: i64) -> Tag {
+        Tag {
+            id: id.into(),
+            parent: parent.map(|p| p.into()),
+            rank: Some(rank),
+            name: id.to_uppercase(),
+            ..Tag::default()
+        }
+    }
+
+    #[test]
+    fn test_tag_tree() {
+        let content = Content {
+            tags: vec![
+                tag("b", Some("a"), 2),
+                tag("a", None, 0),
+                tag("c", Some("a"), 1),
+                tag("d", Some("c"), 0),
+            ],
+            ..Content::default()
+        };
+
+        let children: Vec<&str> = content.tag_children("a").iter()
+            .map(|t| t.id.as_str())
+            .collect();
+        assert!(children == vec!["c", "b"]);
+        assert!(content.tag_path("d") == Some("A : C : D".into()));
+        assert!(content.root_tags().len() == 1);
+    }
+
+    #[test]
+    fn test_tags_for_task_in_rank_order() {
+        let task = Task { context: Some("a".into()), ..Task::default() };
+        let mut first = TaskTag::new(&task.id, "b");
+        first.rank_in_task = Some("0001".into());
+        let mut second = TaskTag::new(&task.id, "a");
+        second.rank_in_task = Some("8bc2".into());
+
+        let mut content = Content {
+            tags: vec![tag("a", None, 0), tag("b", None, 1)],
+            tasks: vec![task.clone()],
+            ..Content::default()
+        };
+        assert!(content.tags_for(&task).len() == 1);
+
+        content.task_tags = vec![second, first];
+        let ids: Vec<&str> = content.tags_for(&task).iter()
+            .map(|t| t.id.as_str())
+            .collect();
+        assert!(ids == vec!["b", "a"]);
+        assert!(content.tasks_for("b").len() == 1);
+    }
+
+    #[test]
+    fn test_rank_after() {
+        assert!(rank_after(vec!["0001", "000a", "0009"].into_iter()) == "000b");
+        assert!(rank_after(vec!["21be", "0fff"].into_iter()) == "21bf");
+        assert!(rank_after(std::iter::empty()) == "8000");
+
+        // out of room at that width, or not hex, still sorts last
+        assert!(rank_after(vec!["00ff", "ffff"].into_iter()) == "ffff8");
+        assert!(rank_after(vec!["abc-"].into_iter()) == "abc-8");
+        assert!(rank_after(vec!["+abc"].into_iter()) == "+abc8");
+    }
+
+    #[test]
+    fn test_tag_status_inherited() {
+        let mut content = Content {
+            tags: vec![tag("a", None, 0), tag("b", Some("a"), 0)],
+            ..Content::default()
+        };
+        content.tags[0].prohibits_next_action = true;
+
+        assert!(content.tag_status("a") == Some(TagStatus::OnHold));
+        assert!(content.tag_status("b") == Some(TagStatus::OnHold));
+        assert!(content.tags[1].status() == TagStatus::Active);
+
+        content.tags[0].hidden = Some(Utc::now());
+        assert!(content.tag_status("b") == Some(TagStatus::Dropped));
+    }
+}
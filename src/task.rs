@@ -4,6 +4,12 @@ use colored::*;
 
 use crate::util::{ID, generate_id};
 use crate::error::*;
+use crate::project::ProjectInfo;
+use crate::note::Note;
+use crate::repeat::{RepetitionRule, RepetitionMethod};
+use crate::unknown::Unknown;
+use crate::parse::Content;
+use crate::tag::Tag;
 
 // enumeration of the order in which subtasks can be completed
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -36,7 +42,7 @@ pub struct Task {
     pub modified: Option<DateTime<Utc>>,
     // attributes
     pub title: String,
-    pub note: Option<String>,
+    pub note: Option<Note>,
     pub context: Option<ID>,
     pub flagged: bool,
     pub due: Option<DateTime<Utc>>,
@@ -45,7 +51,115 @@ pub struct Task {
     pub estimated_duration: Option<u64>,
     pub complete_by_children: bool,
     pub order: Option<SubtaskOrder>,
-    // TODO: repetition and clone attributes
+    pub project: Option<ProjectInfo>, // only Some() if this task is a project
+    pub repetition_rule: Option<RepetitionRule>,
+    pub repetition_method: Option<RepetitionMethod>,
+    pub next_clone_identifier: Option<u64>,
+    // everything else in the record, written back out as it was read
+    pub unknown: Unknown,
+}
+
+// enumeration of the fields of a Task as they appear in a <task>. used to keep
+// track of which fields an op="update" delta actually contains
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TaskField {
+    Parent,
+    Rank,
+    Inbox,
+    Added,
+    Modified,
+    Title,
+    Note,
+    Context,
+    Flagged,
+    Due,
+    Start,
+    Completed,
+    EstimatedDuration,
+    CompleteByChildren,
+    Order,
+    Project,
+    RepetitionRule,
+    RepetitionMethod,
+    NextCloneIdentifier,
+}
+
+impl TaskField {
+    // every field in the order it's written in a <task>
+    pub const ALL: [TaskField; 19] = [
+        TaskField::Project,
+        TaskField::Inbox,
+        TaskField::Parent,
+        TaskField::Added,
+        TaskField::Title,
+        TaskField::Note,
+        TaskField::Rank,
+        TaskField::Context,
+        TaskField::Start,
+        TaskField::Due,
+        TaskField::Completed,
+        TaskField::Modified,
+        TaskField::EstimatedDuration,
+        TaskField::Flagged,
+        TaskField::CompleteByChildren,
+        TaskField::Order,
+        TaskField::RepetitionRule,
+        TaskField::RepetitionMethod,
+        TaskField::NextCloneIdentifier,
+    ];
+}
+
+impl Task {
+    // the fields that are different in other. merging just those fields onto
+    // self gives back other (apart from the id)
+    pub fn changed_fields(&self, other: &Task) -> Vec<TaskField> {
+        TaskField::ALL.iter()
+            .filter(|field| {
+                let mut merged = self.clone();
+                merged.merge(other, &[**field]);
+                merged != *self
+            })
+            .copied()
+            .collect()
+    }
+
+    // copies only the given fields from other onto self. this is how partial
+    // op="update" records are applied to an existing task
+    pub fn merge(&mut self, other: &Task, fields: &[TaskField]) {
+        for field in fields {
+            match field {
+                TaskField::Parent => self.parent = other.parent.clone(),
+                TaskField::Rank => self.rank = other.rank,
+                TaskField::Inbox => self.inbox = other.inbox,
+                TaskField::Added => self.added = other.added,
+                TaskField::Modified => self.modified = other.modified,
+                TaskField::Title => self.title = other.title.clone(),
+                TaskField::Note => self.note = other.note.clone(),
+                TaskField::Context => self.context = other.context.clone(),
+                TaskField::Flagged => self.flagged = other.flagged,
+                TaskField::Due => self.due = other.due,
+                TaskField::Start => self.start = other.start,
+                TaskField::Completed => self.completed = other.completed,
+                TaskField::EstimatedDuration => {
+                    self.estimated_duration = other.estimated_duration
+                }
+                TaskField::CompleteByChildren => {
+                    self.complete_by_children = other.complete_by_children
+                }
+                TaskField::Order => self.order = other.order.clone(),
+                TaskField::Project => self.project = other.project.clone(),
+                TaskField::RepetitionRule => {
+                    self.repetition_rule = other.repetition_rule.clone()
+                }
+                TaskField::RepetitionMethod => {
+                    self.repetition_method = other.repetition_method
+                }
+                TaskField::NextCloneIdentifier => {
+                    self.next_clone_identifier = other.next_clone_identifier
+                }
+            }
+        }
+    }
 }
 
 // self explanatory
@@ -68,10 +182,67 @@ impl Default for Task {
             estimated_duration: None,
             complete_by_children: false,
             order: Some(SubtaskOrder::Sequential),
+            project: None,
+            repetition_rule: None,
+            repetition_method: None,
+            next_clone_identifier: None,
+            unknown: Unknown::default(),
         }
     }
 }
 
+// the functionality shared by tasks and projects. see plan.md
+pub trait Item {
+    fn id(&self) -> &ID;
+    fn parent(&self) -> Option<&ID>;
+    fn rank(&self) -> Option<i64>;
+    fn added(&self) -> DateTime<Utc>;
+    fn modified(&self) -> Option<DateTime<Utc>>;
+    fn title(&self) -> &str;
+    fn note(&self) -> Option<&Note>;
+    fn context(&self) -> Option<&ID>;
+    fn flagged(&self) -> bool;
+    fn due(&self) -> Option<DateTime<Utc>>;
+    fn start(&self) -> Option<DateTime<Utc>>;
+    fn completed(&self) -> Option<DateTime<Utc>>;
+    fn order(&self) -> Option<&SubtaskOrder>;
+    // tags are linked to items by <task-to-tag> records so they're looked up
+    // in the content the item is in
+    fn tags<'a>(&self, content: &'a Content) -> Vec<&'a Tag>;
+
+    fn is_completed(&self) -> bool {
+        self.completed().is_some()
+    }
+
+    // repeating items get a new copy when they're completed
+    fn is_repeating(&self) -> bool {
+        false
+    }
+
+    // overdue items are incomplete and have a due date in the past
+    fn is_overdue(&self) -> bool {
+        !self.is_completed() && self.due().is_some_and(|d| d < Utc::now())
+    }
+}
+
+impl Item for Task {
+    fn id(&self) -> &ID { &self.id }
+    fn parent(&self) -> Option<&ID> { self.parent.as_ref() }
+    fn rank(&self) -> Option<i64> { self.rank }
+    fn added(&self) -> DateTime<Utc> { self.added }
+    fn modified(&self) -> Option<DateTime<Utc>> { self.modified }
+    fn title(&self) -> &str { &self.title }
+    fn note(&self) -> Option<&Note> { self.note.as_ref() }
+    fn context(&self) -> Option<&ID> { self.context.as_ref() }
+    fn flagged(&self) -> bool { self.flagged }
+    fn due(&self) -> Option<DateTime<Utc>> { self.due }
+    fn start(&self) -> Option<DateTime<Utc>> { self.start }
+    fn completed(&self) -> Option<DateTime<Utc>> { self.completed }
+    fn order(&self) -> Option<&SubtaskOrder> { self.order.as_ref() }
+    fn tags<'a>(&self, content: &'a Content) -> Vec<&'a Tag> { content.tags_for(self) }
+    fn is_repeating(&self) -> bool { self.repetition_rule.is_some() }
+}
+
 // turns a Task into a String
 // examples:
 //  [x] this task is complete
@@ -89,7 +260,7 @@ impl fmt::Display for Task {
         };
         let due = if let Some(due) = self.due {
             let date_str = "(".to_string() + &due.to_string() + ")";
-            if due < Utc::now() && !self.completed.is_some() {
+            if due < Utc::now() && self.completed.is_none() {
                 date_str.red().to_string()
             } else {
                 date_str
@@ -0,0 +1,381 @@
+use chrono::prelude::*;
+
+use crate::error::*;
+use crate::util::ID;
+use crate::parse::{Content, Entity, Tombstone, TaskUpdate};
+use crate::task::Task;
+use crate::tag::{TaskTag, rank_after};
+
+// a batch of edits that are written out together as a single archive. every
+// edit is checked against the database as it will be once the earlier edits
+// in the batch are applied. see Database::transaction
+pub struct Transaction {
+    content: Content, // the database with this transaction's edits applied
+    delta: Content,   // everything this transaction has changed so far
+}
+
+impl Transaction {
+    pub fn new(content: Content) -> Transaction {
+        Transaction { content, delta: Content::default() }
+    }
+
+    // the database as it will be once this transaction is committed
+    pub fn content(&self) -> &Content {
+        &self.content
+    }
+
+    // the changes to write out, or None if there aren't any
+    pub fn into_delta(self) -> Option<Content> {
+        if self.delta.ids().is_empty() {
+            None
+        } else {
+            Some(self.delta)
+        }
+    }
+
+    // adds a delta to the transaction as is, without any checks. the same as
+    // Database::write, only task updates can be written so the transaction
+    // fails when it's committed if the delta has updates to anything else
+    pub fn apply(&mut self, delta: Content) {
+        self.content.update(delta.clone());
+        self.delta.append(delta);
+    }
+
+    // adds a new task. its primary tag is linked to it the same as any other
+    // tag, which is what OmniFocus shows the task's tags from
+    pub fn create(&mut self, task: Task) -> Result<(), Error> {
+        if task.id.is_empty() || self.content.tasks.iter().any(|t| t.id == task.id) {
+            return Err(crate::err!(InvalidArgument));
+        }
+        self.check_references(&task)?;
+        let (id, context) = (task.id.clone(), task.context.clone());
+        self.apply(Content::new_task(task));
+        match context {
+            Some(tag) => self.add_tag(&id, &tag),
+            None => Ok(()),
+        }
+    }
+
+    // replaces a task with an edited copy. only the fields that changed are
+    // written, and the modified date is bumped unless it was set by the caller
+    pub fn update(&mut self, mut task: Task) -> Result<(), Error> {
+        let old = match self.content.tasks.iter().find(|t| t.id == task.id) {
+            Some(t) => t.clone(),
+            None => return Err(crate::err!(NotFound)),
+        };
+        self.check_references(&task)?;
+
+        if old.changed_fields(&task).is_empty() {
+            return Ok(());
+        }
+        if task.modified == old.modified {
+            task.modified = Some(Utc::now());
+        }
+        self.apply(Content::edit_task(&old, task));
+        Ok(())
+    }
+
+    // makes sure the parent and primary tag of a task exist and that it
+    // wouldn't end up inside of itself
+    fn check_references(&self, task: &Task) -> Result<(), Error> {
+        if let Some(tag) = task.context.as_ref() {
+            if self.content.tag(tag).is_none() {
+                return Err(crate::err!(NotFound));
+            }
+        }
+
+        let mut parent = task.parent.clone();
+        while let Some(id) = parent {
+            if id == task.id {
+                return Err(crate::err!(InvalidArgument));
+            }
+            parent = match self.content.tasks.iter().find(|t| t.id == id) {
+                Some(t) => t.parent.clone(),
+                None => return Err(crate::err!(NotFound)),
+            };
+        }
+        Ok(())
+    }
+
+    // deletes the entity with the given id. deleting a task or tag also
+    // deletes all of its children so nothing is left pointing at it
+    pub fn delete(&mut self, id: &str) -> Result<(), Error> {
+        // walks a tree breadth first collecting the ids of id and its descendants
+        fn with_descendants<'a>(
+            id: &str,
+            nodes: impl Iterator<Item = (&'a ID, Option<&'a ID>)> + Clone,
+        ) -> Vec<ID> {
+            let mut ids: Vec<ID> = vec![id.into()];
+            let mut i = 0;
+            while i < ids.len() {
+                for (child, parent) in nodes.clone() {
+                    if parent == Some(&ids[i]) && !ids.contains(child) {
+                        ids.push(child.clone());
+                    }
+                }
+                i += 1;
+            }
+            ids
+        }
+
+        let mut tombstones = Vec::new();
+        let mut delta = Content::default();
+
+        if self.content.tasks.iter().any(|t| t.id == id) {
+            let tasks = self.content.tasks.iter().map(|t| (&t.id, t.parent.as_ref()));
+            for id in with_descendants(id, tasks) {
+                // remove the task's tag links and attachments along with it
+                for link in self.content.task_tags.iter().filter(|l| l.task == id) {
+                    tombstones.push(Tombstone { entity: Entity::TaskToTag, id: link.id.clone() });
+                }
+                for attachment in self.content.attachments_for(&id) {
+                    tombstones.push(Tombstone { entity: Entity::Attachment, id: attachment.id.clone() });
+                }
+                tombstones.push(Tombstone { entity: Entity::Task, id });
+            }
+        } else if self.content.tags.iter().any(|t| t.id == id) {
+            let tags = self.content.tags.iter().map(|t| (&t.id, t.parent.as_ref()));
+            let tag_ids = with_descendants(id, tags);
+
+            // tasks whose primary tag is going move on to one of their other
+            // tags, the same as when the tag is removed from them
+            for task in self.content.tasks.iter() {
+                if !task.context.as_ref().is_some_and(|c| tag_ids.contains(c)) {
+                    continue;
+                }
+                let mut edited = task.clone();
+                edited.context = self.content.tags_for(task).iter()
+                    .map(|t| t.id.clone())
+                    .find(|id| !tag_ids.contains(id));
+                edited.modified = Some(Utc::now());
+                delta.task_updates.push(TaskUpdate::diff(task, edited));
+            }
+
+            for id in tag_ids {
+                // remove the links to the tag along with it
+                for link in self.content.task_tags.iter().filter(|l| l.tag == id) {
+                    tombstones.push(Tombstone { entity: Entity::TaskToTag, id: link.id.clone() });
+                }
+                tombstones.push(Tombstone { entity: Entity::Context, id });
+            }
+        } else if self.content.folders.iter().any(|f| f.id == id) {
+            // deleting a folder deletes everything inside of it. first the
+            // subfolders, then the projects in them and their tasks
+            let folders = self.content.folders.iter().map(|f| (&f.id, f.parent.as_ref()));
+            let folder_ids = with_descendants(id, folders);
+            for folder_id in folder_ids.iter() {
+                for project in self.content.folder_projects(Some(folder_id)) {
+                    let tasks = self.content.tasks.iter().map(|t| (&t.id, t.parent.as_ref()));
+                    for id in with_descendants(&project.task.id, tasks) {
+                        for link in self.content.task_tags.iter().filter(|l| l.task == id) {
+                            tombstones.push(Tombstone { entity: Entity::TaskToTag, id: link.id.clone() });
+                        }
+                        for attachment in self.content.attachments_for(&id) {
+                            tombstones.push(Tombstone { entity: Entity::Attachment, id: attachment.id.clone() });
+                        }
+                        tombstones.push(Tombstone { entity: Entity::Task, id });
+                    }
+                }
+            }
+            for id in folder_ids {
+                tombstones.push(Tombstone { entity: Entity::Folder, id });
+            }
+        } else if self.content.attachment(id).is_some() {
+            tombstones.push(Tombstone { entity: Entity::Attachment, id: id.into() });
+        } else if self.content.perspectives.iter().any(|p| p.id == id) {
+            tombstones.push(Tombstone { entity: Entity::Perspective, id: id.into() });
+        } else {
+            return Err(crate::err!(NotFound));
+        }
+
+        delta.tombstones = tombstones;
+        self.apply(delta);
+        Ok(())
+    }
+
+    // tags a task by writing a new task-to-tag link. a task's first tag is also
+    // its primary <context> which older clients rely on so that's kept in sync
+    pub fn add_tag(&mut self, task_id: &str, tag_id: &str) -> Result<(), Error> {
+        let task = match self.content.tasks.iter().find(|t| t.id == task_id) {
+            Some(t) => t,
+            None => return Err(crate::err!(NotFound)),
+        };
+        if self.content.tag(tag_id).is_none() {
+            return Err(crate::err!(NotFound));
+        }
+
+        let mut link = TaskTag::new(task_id, tag_id);
+        if self.content.task_tags.iter().any(|l| l.id == link.id) {
+            // already tagged, nothing to do
+            return Ok(());
+        }
+
+        // put the tag after the task's other tags and the task after the tag's
+        // other tasks
+        let links = &self.content.task_tags;
+        link.rank_in_task = Some(rank_after(links.iter()
+            .filter(|l| l.task == task_id)
+            .filter_map(|l| l.rank_in_task.as_deref())));
+        link.rank_in_tag = Some(rank_after(links.iter()
+            .filter(|l| l.tag == tag_id)
+            .filter_map(|l| l.rank_in_tag.as_deref())));
+
+        let mut delta = Content { task_tags: vec![link], ..Content::default() };
+        if task.context.is_none() {
+            let mut edited = task.clone();
+            edited.context = Some(tag_id.into());
+            edited.modified = Some(Utc::now());
+            delta.task_updates.push(TaskUpdate::diff(task, edited));
+        }
+
+        self.apply(delta);
+        Ok(())
+    }
+
+    // untags a task by writing a tombstone for the task-to-tag link. if it was
+    // the task's primary tag the next one takes its place
+    pub fn remove_tag(&mut self, task_id: &str, tag_id: &str) -> Result<(), Error> {
+        let task = match self.content.tasks.iter().find(|t| t.id == task_id) {
+            Some(t) => t,
+            None => return Err(crate::err!(NotFound)),
+        };
+
+        let id = format!("{}.{}", task_id, tag_id);
+        let mut delta = Content::default();
+        if self.content.task_tags.iter().any(|l| l.id == id) {
+            delta.tombstones.push(Tombstone { entity: Entity::TaskToTag, id });
+        }
+
+        if task.context.as_deref() == Some(tag_id) {
+            let mut edited = task.clone();
+            edited.context = self.content.tags_for(task).iter()
+                .map(|t| t.id.clone())
+                .find(|id| id != tag_id);
+            edited.modified = Some(Utc::now());
+            delta.task_updates.push(TaskUpdate::diff(task, edited));
+        }
+
+        if delta.task_updates.is_empty() && delta.tombstones.is_empty() {
+            return Err(crate::err!(NotFound));
+        }
+
+        self.apply(delta);
+        Ok(())
+    }
+
+    // marks a task as complete. a repeating task moves on to its next
+    // occurrence instead, and what was completed is cloned, see complete_task
+    pub fn complete(&mut self, id: &str) -> Result<(), Error> {
+        let task = match self.content.tasks.iter().find(|t| t.id == id) {
+            Some(t) => t,
+            None => return Err(crate::err!(NotFound)),
+        };
+        let delta = self.content.complete_task(task, Utc::now())?;
+        self.apply(delta);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task::TaskField;
+    use crate::tag::Tag;
+
+    fn task(id: &str, title: &str) -> Task {
+        Task { id: id.into(), title: title.into(), ..Task::default() }
+    }
+
+    #[test]
+    fn test_edits_fold_into_one_record() {
+        let mut tx = Transaction::new(Content::new_task(task("existing", "Old")));
+
+        // a new task stays a single full record however often it's edited
+        tx.create(task("new", "First")).unwrap();
+        tx.update(task("new", "Second")).unwrap();
+
+        // edits to an existing task become one update with every changed field
+        let mut edited = tx.content().tasks[0].clone();
+        edited.title = "New".into();
+        tx.update(edited.clone()).unwrap();
+        edited.flagged = true;
+        tx.update(edited).unwrap();
+
+        let delta = tx.into_delta().unwrap();
+        assert!(delta.tasks.len() == 1);
+        assert!(delta.tasks[0].title == "Second");
+        assert!(delta.task_updates.len() == 1);
+        let update = &delta.task_updates[0];
+        assert!(update.item.title == "New" && update.item.flagged);
+        assert!(update.fields.contains(&TaskField::Title));
+        assert!(update.fields.contains(&TaskField::Flagged));
+        assert!(update.fields.contains(&TaskField::Modified));
+    }
+
+    #[test]
+    fn test_invalid_edits_are_rejected() {
+        let mut tx = Transaction::new(Content::new_task(task("existing", "Old")));
+
+        assert!(tx.create(task("existing", "Duplicate")).is_err());
+        assert!(tx.update(task("missing", "Nope")).is_err());
+        let orphan = Task { parent: Some("missing".into()), ..task("orphan", "Orphan") };
+        assert!(tx.create(orphan).is_err());
+        let cycle = Task { parent: Some("existing".into()), ..task("existing", "Old") };
+        assert!(tx.update(cycle).is_err());
+
+        assert!(tx.into_delta().is_none());
+    }
+
+    #[test]
+    fn test_create_links_primary_tag() {
+        let content = Content {
+            tasks: vec![task("tagged", "Tagged")],
+            tags: vec![Tag { id: "tag".into(), ..Tag::default() }],
+            task_tags: vec![TaskTag { rank_in_tag: Some("0001".into()), ..TaskTag::new("tagged", "tag") }],
+            ..Content::default()
+        };
+
+        // the new task goes after the tag's other tasks
+        let mut tx = Transaction::new(content);
+        tx.create(Task { context: Some("tag".into()), ..task("new", "New") }).unwrap();
+        let delta = tx.into_delta().unwrap();
+        assert!(delta.tasks.len() == 1 && delta.task_updates.is_empty());
+        let link = &delta.task_tags[0];
+        assert!(link.id == "new.tag");
+        assert!(link.rank_in_task.is_some());
+        assert!(link.rank_in_tag.as_deref().unwrap() > "0001");
+    }
+
+    #[test]
+    fn test_delete_primary_tag() {
+        let tag = |id: &str, parent: Option<&str>| {
+            Tag { id: id.into(), parent: parent.map(|p| p.into()), ..Tag::default() }
+        };
+        let mut content = Content {
+            tasks: vec![
+                Task { context: Some("child".into()), ..task("retagged", "Retagged") },
+                Task { context: Some("parent".into()), ..task("untagged", "Untagged") },
+            ],
+            tags: vec![tag("parent", None), tag("child", Some("parent")), tag("other", None)],
+            ..Content::default()
+        };
+        content.task_tags = vec![
+            TaskTag { rank_in_task: Some("0001".into()), ..TaskTag::new("retagged", "child") },
+            TaskTag { rank_in_task: Some("0002".into()), ..TaskTag::new("retagged", "other") },
+            TaskTag::new("untagged", "parent"),
+        ];
+
+        // the tag and everything in it go, and nothing is left tagged with them
+        let mut tx = Transaction::new(content);
+        tx.delete("parent").unwrap();
+        let context = |id: &str| {
+            tx.content().tasks.iter().find(|t| t.id == id).unwrap().context.clone()
+        };
+        assert!(context("retagged") == Some("other".into()));
+        assert!(context("untagged").is_none());
+        assert!(tx.content().tags.len() == 1);
+        let delta = tx.into_delta().unwrap();
+        assert!(delta.task_updates.len() == 2);
+        assert!(delta.tombstones.len() == 4);
+    }
+}
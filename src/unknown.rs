@@ -0,0 +1,186 @@
+use std::io::Write;
+use xml::attribute::OwnedAttribute;
+use xml::reader::XmlEvent;
+use xml::writer::{EventWriter, XmlEvent as WriterEvent};
+
+use crate::error::*;
+use crate::parse::{Reader, name_to_str};
+
+// helpers to carry along the parts of an archive openfocus doesn't understand.
+// the database advertises unknown_element_import.capability which promises
+// that anything a newer version of OmniFocus wrote survives being read and
+// written back out by an older client
+
+// an XML element kept exactly as it was read
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct RawElement {
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<RawNode>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum RawNode {
+    Element(RawElement),
+    Text(String),
+}
+
+impl RawElement {
+    // the value of an attribute, ex: "id"
+    pub fn attr(&self, name: &str) -> Option<&str> {
+        self.attributes.iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+// the attributes and child elements of an entity that aren't modeled
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct Unknown {
+    pub attributes: Vec<(String, String)>,
+    pub elements: Vec<RawElement>,
+}
+
+impl Unknown {
+    // keeps the attributes of a record's root element that aren't in known
+    pub fn from_attributes(attrs: &[OwnedAttribute], known: &[&str]) -> Unknown {
+        Unknown {
+            attributes: attrs.iter()
+                .filter(|a| !known.contains(&name_to_str(&a.name)))
+                .map(|a| (name_to_str(&a.name).to_string(), a.value.clone()))
+                .collect(),
+            elements: vec![],
+        }
+    }
+
+    // applies the unknown parts of an op="update" record. elements and
+    // attributes replace the ones with the same name
+    pub fn merge(&mut self, other: &Unknown) {
+        for (key, value) in other.attributes.iter() {
+            match self.attributes.iter_mut().find(|(k, _)| k == key) {
+                Some(attr) => attr.1 = value.clone(),
+                None => self.attributes.push((key.clone(), value.clone())),
+            }
+        }
+        for element in other.elements.iter() {
+            match self.elements.iter_mut().find(|e| e.name == element.name) {
+                Some(e) => *e = element.clone(),
+                None => self.elements.push(element.clone()),
+            }
+        }
+    }
+}
+
+// applies a top level record of an unknown kind to the ones already read.
+// records are matched by their element name and id. op="delete" removes the
+// record, op="update" replaces the children of the same name like
+// Unknown::merge and anything else replaces the whole record
+pub fn apply_record(records: &mut Vec<RawElement>, record: RawElement) {
+    let existing = records.iter().position(|r| {
+        r.name == record.name && r.attr("id").is_some() && r.attr("id") == record.attr("id")
+    });
+
+    match (existing, record.attr("op")) {
+        (Some(i), Some("delete")) => {
+            records.remove(i);
+        }
+        (Some(i), Some("update")) => {
+            for child in record.children.into_iter() {
+                let old = &mut records[i].children;
+                let name = match &child {
+                    RawNode::Element(e) => e.name.clone(),
+                    RawNode::Text(_) => continue,
+                };
+                let same = old.iter().position(|c| matches!(c, RawNode::Element(e) if e.name == name));
+                match same {
+                    Some(j) => old[j] = child,
+                    None => old.push(child),
+                }
+            }
+        }
+        (Some(i), _) => records[i] = record,
+        (None, _) => records.push(record),
+    }
+}
+
+// reads an element whose start tag has already been read, up to and including
+// its closing tag
+pub fn read_element<'a>(
+    name: &str,
+    attributes: &[OwnedAttribute],
+    parser: &mut Reader<zip::read::ZipFile<'a>>,
+) -> Result<RawElement, Error> {
+    let mut element = RawElement {
+        name: name.into(),
+        attributes: Unknown::from_attributes(attributes, &[]).attributes,
+        children: vec![],
+    };
+
+    while let Some(evt) = parser.next() {
+        match evt? {
+            XmlEvent::StartElement { name, attributes, .. } => {
+                let child = read_element(name_to_str(&name), &attributes, parser)?;
+                element.children.push(RawNode::Element(child));
+            }
+            XmlEvent::Characters(t) | XmlEvent::Whitespace(t) | XmlEvent::CData(t) => {
+                element.children.push(RawNode::Text(t));
+            }
+            XmlEvent::EndElement { .. } => return Ok(element),
+            _ => {}
+        }
+    }
+    Err(crate::err!(Parse))
+}
+
+// writes an element back out the way it was read
+pub fn write_element<W: Write>(
+    xml: &mut EventWriter<W>,
+    element: &RawElement,
+) -> Result<(), Error> {
+    let mut start = WriterEvent::start_element(element.name.as_str());
+    for (k, v) in element.attributes.iter() {
+        start = start.attr(k.as_str(), v);
+    }
+    xml.write(start)?;
+
+    for child in element.children.iter() {
+        match child {
+            RawNode::Element(e) => write_element(xml, e)?,
+            RawNode::Text(t) => xml.write(WriterEvent::characters(t))?,
+        }
+    }
+
+    xml.write(WriterEvent::end_element())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn element(name: &str, text: &str) -> RawElement {
+        RawElement {
+            name: name.into(),
+            attributes: vec![],
+            children: vec![RawNode::Text(text.into())],
+        }
+    }
+
+    #[test]
+    fn test_merge_replaces_by_name() {
+        let mut unknown = Unknown {
+            attributes: vec![("a".into(), "1".into())],
+            elements: vec![element("hidden", "old"), element("other", "kept")],
+        };
+        unknown.merge(&Unknown {
+            attributes: vec![("a".into(), "2".into()), ("b".into(), "3".into())],
+            elements: vec![element("hidden", "new")],
+        });
+
+        assert!(unknown.attributes == vec![
+            ("a".to_string(), "2".to_string()),
+            ("b".to_string(), "3".to_string()),
+        ]);
+        assert!(unknown.elements == vec![element("hidden", "new"), element("other", "kept")]);
+    }
+}
@@ -2,7 +2,7 @@ use rand::{Rng, thread_rng};
 
 pub type ID = String;
 
-const ALPHABET: &'static [u8] =
+const ALPHABET: &[u8] =
     b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz012356789_-";
 
 pub fn generate_id() -> ID {
@@ -13,6 +13,34 @@ pub fn generate_id() -> ID {
     s
 }
 
+// a directory for a test to write to, removed along with everything in it
+// when it's dropped
+#[cfg(test)]
+pub struct TestDir(std::path::PathBuf);
+
+#[cfg(test)]
+impl Default for TestDir {
+    fn default() -> TestDir {
+        let path = std::env::temp_dir().join(format!("openfocus-test-{}", generate_id()));
+        std::fs::create_dir_all(&path).unwrap();
+        TestDir(path)
+    }
+}
+
+#[cfg(test)]
+impl TestDir {
+    pub fn path(&self) -> &std::path::Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::generate_id;